@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Take};
+use std::path::Path;
+use crate::formats::bhd5::FileHeader;
+use crate::formats::CompressionType;
+use crate::util::binary_reader::BinaryReader;
+use crate::util::sf_util::SFUtil;
+
+/// The data half of a dvdbnd archive.
+///
+/// Files are read by seeking to the offset their BHD5 header records, so only the requested
+/// files are ever loaded from what is usually a multi-gigabyte file.
+pub struct BDT<R: Read + Seek> {
+    reader: R,
+}
+
+impl BDT<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read + Seek> BDT<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Returns a reader over the stored bytes of a file, including any padding.
+    /// Encrypted ranges are returned as they are stored, use `read_file` to have them decrypted.
+    pub fn file_reader(&mut self, header: &FileHeader) -> Result<Take<&mut R>, Error> {
+        self.reader.seek(SeekFrom::Start(header.file_offset))?;
+        Ok(self.reader.by_ref().take(header.padded_file_size as u64))
+    }
+
+    /// Reads the data of a file without its padding, decrypting it if its header has an AES key.
+    pub fn read_file(&mut self, header: &FileHeader) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(header.padded_file_size as usize);
+        self.file_reader(header)?.read_to_end(&mut data)?;
+        if data.len() < header.padded_file_size as usize {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("BDT file {:#x} at {:#x} is cut off.", header.file_name_hash, header.file_offset),
+            ));
+        }
+
+        // Encrypted ranges may extend into the padding, so they are decrypted before it is cut off
+        if let Some(aes_key) = &header.aes_key {
+            aes_key.decrypt(&mut data)?;
+        }
+        data.truncate(header.file_size().min(data.len() as u64) as usize);
+        Ok(data)
+    }
+
+    /// Reads and decrypts a file, then decompresses it if it is DCX compressed.
+    pub fn read_file_decompressed(&mut self, header: &FileHeader) -> Result<Vec<u8>, Error> {
+        let mut br = BinaryReader::new(false, self.read_file(header)?);
+        let mut compression = CompressionType::Unknown;
+        SFUtil::decompress_if_neccessary(&mut br, &mut compression)?;
+        Ok(br.memory)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use crate::formats::bhd5::{FileHeader, Game, BHD5};
+use crate::util::path_hash::normalize_path;
+
+/// Known paths of a game's archives, keyed by their path hash.
+///
+/// BHD5 headers only store hashes, so the names of extracted files have to be recovered by
+/// hashing every path the game is known to use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveDictionary {
+    pub game: Game,
+    names: HashMap<u64, String>,
+}
+
+/// The files of a BHD5 split into those the dictionary has a name for and those it does not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedArchive<'a> {
+    pub named: Vec<NamedFile<'a>>,
+    pub unresolved: Vec<&'a FileHeader>,
+    bhd: &'a BHD5,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedFile<'a> {
+    /// The normalized path, starting with a slash.
+    pub name: &'a str,
+    pub header: &'a FileHeader,
+}
+
+impl ArchiveDictionary {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Reads a list with one path per line. Empty lines and lines starting with `#` are skipped.
+    pub fn read<P: AsRef<Path>>(path: P, game: Game) -> Result<Self, Error> {
+        let mut dictionary = Self::new(game);
+        dictionary.add_list(&fs::read_to_string(path)?);
+        Ok(dictionary)
+    }
+
+    /// Adds every path of a list in the format accepted by `read`.
+    pub fn add_list(&mut self, list: &str) {
+        for line in list.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                self.add(line);
+            }
+        }
+    }
+
+    /// Adds a path and returns its hash. A path whose hash is already known is ignored.
+    pub fn add(&mut self, path: &str) -> u64 {
+        let hash = self.hash(path);
+        self.names.entry(hash).or_insert_with(|| normalize_path(path));
+        hash
+    }
+
+    pub fn hash(&self, path: &str) -> u64 {
+        self.game.path_hash(path)
+    }
+
+    /// Returns the path with the given hash.
+    pub fn name(&self, hash: u64) -> Option<&str> {
+        self.names.get(&hash).map(String::as_str)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.names.contains_key(&self.hash(path))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Looks up the name of every file of `bhd`.
+    pub fn resolve<'a>(&'a self, bhd: &'a BHD5) -> ResolvedArchive<'a> {
+        let mut resolved = ResolvedArchive {
+            named: Vec::new(),
+            unresolved: Vec::new(),
+            bhd,
+        };
+        for header in bhd.files() {
+            match self.name(header.file_name_hash) {
+                Some(name) => resolved.named.push(NamedFile { name, header }),
+                None => resolved.unresolved.push(header),
+            }
+        }
+        resolved
+    }
+}
+
+impl<'a> ResolvedArchive<'a> {
+    /// Returns the file with the given name, ignoring case and slash direction.
+    pub fn file(&self, name: &str) -> Option<&'a FileHeader> {
+        self.bhd.file_by_name(name)
+    }
+
+    /// The hashes no name was found for.
+    pub fn unresolved_hashes(&self) -> Vec<u64> {
+        self.unresolved.iter().map(|header| header.file_name_hash).collect()
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{BufReader, Error, Read, Seek};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::formats::bhd5::bdt::BDT;
+use crate::formats::bhd5::{read_archive_header, FileHeader, Game, BHD5};
+use crate::formats::binder::lazy::{EntryCache, EntryReader};
+
+/// A dvdbnd archive whose files are read from the data file when their data is requested.
+pub struct LazyArchive {
+    pub bhd: BHD5,
+    source: Arc<ArchiveSource>,
+}
+
+/// A file of a `LazyArchive` that reads, decrypts and decompresses its data on demand.
+#[derive(Clone)]
+pub struct ArchiveEntry {
+    pub header: FileHeader,
+    source: Arc<ArchiveSource>,
+}
+
+// The data file shared by every entry of an archive.
+struct ArchiveSource {
+    bdt: Mutex<BDT<Box<dyn EntryReader>>>,
+    cache: EntryCache<u64>,
+}
+
+impl LazyArchive {
+    /// Opens an archive from its header and the data file next to it. An encrypted header needs a key
+    /// named after it in `key_directory`.
+    pub fn open<P: AsRef<Path>>(bhd_path: P, game: Game, key_directory: Option<&Path>) -> Result<Self, Error> {
+        let bhd_path = bhd_path.as_ref();
+        let bhd = read_archive_header(bhd_path, game, key_directory)?;
+        Ok(Self::new(bhd, BufReader::new(File::open(bhd_path.with_extension("bdt"))?)))
+    }
+
+    pub fn new<R: Read + Seek + Send + 'static>(bhd: BHD5, bdt: R) -> Self {
+        let bdt: Box<dyn EntryReader> = Box::new(bdt);
+        let source = Arc::new(ArchiveSource {
+            bdt: Mutex::new(BDT::new(bdt)),
+            cache: EntryCache::new(),
+        });
+        Self { bhd, source }
+    }
+
+    /// Sets whether entries keep their data in memory after it has been read. Disabling it drops the cached data.
+    pub fn set_caching(&self, enabled: bool) {
+        self.source.cache.set_enabled(enabled);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = ArchiveEntry> + '_ {
+        self.bhd.files().map(|header| self.entry_for(header))
+    }
+
+    pub fn entry(&self, file_name_hash: u64) -> Option<ArchiveEntry> {
+        self.bhd.file(file_name_hash).map(|header| self.entry_for(header))
+    }
+
+    /// Looks a file up by the hash of its path.
+    pub fn entry_by_name(&self, path: &str) -> Option<ArchiveEntry> {
+        self.bhd.file_by_name(path).map(|header| self.entry_for(header))
+    }
+
+    fn entry_for(&self, header: &FileHeader) -> ArchiveEntry {
+        ArchiveEntry {
+            header: header.clone(),
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+impl ArchiveEntry {
+    /// Reads and decrypts the file, then decompresses it if it is DCX compressed. Cached data is shared, not copied.
+    pub fn data(&self) -> Result<Arc<[u8]>, Error> {
+        if let Some(data) = self.source.cache.get(&self.header.file_name_hash) {
+            return Ok(data);
+        }
+        let data: Arc<[u8]> = self.source.bdt.lock().unwrap_or_else(PoisonError::into_inner).read_file_decompressed(&self.header)?.into();
+        self.source.cache.insert(self.header.file_name_hash, Arc::clone(&data));
+        Ok(data)
+    }
+
+    /// Reads and decrypts the file without decompressing it.
+    pub fn raw_data(&self) -> Result<Vec<u8>, Error> {
+        self.source.bdt.lock().unwrap_or_else(PoisonError::into_inner).read_file(&self.header)
+    }
+
+    /// Whether the data of this entry is currently cached.
+    pub fn is_cached(&self) -> bool {
+        self.source.cache.contains(&self.header.file_name_hash)
+    }
+}
@@ -0,0 +1,434 @@
+pub mod bdt;
+pub mod dictionary;
+pub mod lazy;
+pub mod pack;
+pub mod unpack;
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use aes::Aes128;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, KeyInit};
+use crate::formats::binder::to_field;
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::path_hash::{path_hash_32, path_hash_64};
+use crate::util::rsa::RsaPublicKey;
+
+/// The game an archive comes from, which decides the layout of its file headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Game {
+    DarkSouls1,
+    DarkSouls2,
+    DarkSouls3,
+    Sekiro,
+    EldenRing,
+}
+
+impl Game {
+    /// Hashes `path` the way this game's archives address their files.
+    pub fn path_hash(self, path: &str) -> u64 {
+        if self >= Game::EldenRing { path_hash_64(path) } else { path_hash_32(path) as u64 }
+    }
+}
+
+/// The header of a dvdbnd archive, listing the location of every file in its BDT by path hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BHD5 {
+    pub game: Game,
+    pub big_endian: bool,
+    /// Identifies the archive from DS2 onwards, usually the name of its key.
+    pub salt: String,
+    /// Files grouped by their path hash modulo the bucket count.
+    pub buckets: Vec<Vec<FileHeader>>,
+}
+
+/// The location of a single file in the BDT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHeader {
+    /// The 32-bit path hash before Elden Ring, the 64-bit one from Elden Ring onwards.
+    pub file_name_hash: u64,
+    /// Size of the data in the BDT, including padding to the AES block size.
+    pub padded_file_size: u32,
+    /// Size of the data without padding, stored from DS3 onwards.
+    pub unpadded_file_size: Option<u64>,
+    pub file_offset: u64,
+    pub sha_hash: Option<SHAHash>,
+    pub aes_key: Option<AESKey>,
+}
+
+/// A SHA-256 hash over parts of the file data, stored from DS2 onwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SHAHash {
+    pub hash: [u8; 32],
+    pub ranges: Vec<ByteRange>,
+}
+
+/// The AES-128 key of a file whose data is partly encrypted, stored from DS2 onwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AESKey {
+    pub key: [u8; 16],
+    /// The parts of the file data that are encrypted with `key`.
+    pub ranges: Vec<ByteRange>,
+}
+
+/// A range of file data, relative to the start of the file. Unused ranges are -1 to -1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl BHD5 {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            big_endian: false,
+            salt: String::new(),
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Returns whether `bytes` start with an unencrypted BHD5 header.
+    pub fn is(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"BHD5")
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P, game: Game) -> Result<Self, Error> {
+        Self::from_bytes(fs::read(path)?, game)
+    }
+
+    /// Reads a retail header, which is RSA encrypted with the archive's key.
+    pub fn read_encrypted<P: AsRef<Path>>(path: P, game: Game, key: &RsaPublicKey) -> Result<Self, Error> {
+        Self::from_encrypted_bytes(&fs::read(path)?, game, key)
+    }
+
+    pub fn from_encrypted_bytes(bytes: &[u8], game: Game, key: &RsaPublicKey) -> Result<Self, Error> {
+        let bytes = key.decrypt(bytes)?;
+        if !BHD5::is(&bytes) {
+            return Err(invalid("Decrypted BHD5 has no BHD5 magic, the key does not belong to this archive.".to_string()));
+        }
+        Self::from_bytes(bytes, game)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>, game: Game) -> Result<Self, Error> {
+        let mut br = BinaryReader::new(false, bytes);
+        let mut bhd = BHD5::new(game);
+
+        br.assert_ascii(&["BHD5"])?;
+        bhd.big_endian = br.assert_byte(&[0, 0xFF]) == 0;
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+
+        br.big_endian = bhd.big_endian;
+        br.assert_i32(&[1]);
+        br.read_i32(); // File size
+        let bucket_count = br.read_i32();
+        let buckets_offset = br.read_i32();
+        if game >= Game::DarkSouls2 {
+            let salt_length = br.read_i32();
+            let salt_length = usize::try_from(salt_length).map_err(|_| invalid(format!("Invalid BHD5 salt length: {}", salt_length)))?;
+            check_range(&br, br.position, salt_length, "salt")?;
+            bhd.salt = br.read_ascii(salt_length)?;
+        }
+
+        let bucket_count = usize::try_from(bucket_count).map_err(|_| invalid(format!("Invalid BHD5 bucket count: {}", bucket_count)))?;
+        let buckets_offset = usize::try_from(buckets_offset).map_err(|_| invalid(format!("Invalid BHD5 buckets offset: {}", buckets_offset)))?;
+        check_range(&br, buckets_offset, bucket_count * 8, "bucket table")?;
+
+        br.position = buckets_offset;
+        bhd.buckets = Vec::with_capacity(bucket_count);
+        for _ in 0..bucket_count {
+            let file_count = br.read_i32();
+            let file_headers_offset = br.read_i32();
+            let file_count = usize::try_from(file_count).map_err(|_| invalid(format!("Invalid BHD5 bucket size: {}", file_count)))?;
+            let file_headers_offset = usize::try_from(file_headers_offset)
+                .map_err(|_| invalid(format!("Invalid BHD5 bucket offset: {}", file_headers_offset)))?;
+            check_range(&br, file_headers_offset, file_count * file_header_size(game), "bucket")?;
+
+            br.step_in(file_headers_offset);
+            let bucket = (0..file_count).map(|_| FileHeader::read(&mut br, game)).collect::<Result<Vec<_>, _>>();
+            br.step_out()?;
+            bhd.buckets.push(bucket?);
+        }
+
+        Ok(bhd)
+    }
+
+    /// Iterates over the files of every bucket.
+    pub fn files(&self) -> impl Iterator<Item = &FileHeader> {
+        self.buckets.iter().flatten()
+    }
+
+    /// Returns the file with the given path hash from the bucket it belongs to.
+    pub fn file(&self, file_name_hash: u64) -> Option<&FileHeader> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = &self.buckets[(file_name_hash % self.buckets.len() as u64) as usize];
+        bucket.iter().find(|file| file.file_name_hash == file_name_hash)
+    }
+
+    /// Returns the file with the given path, ignoring case and slash direction.
+    pub fn file_by_name(&self, path: &str) -> Option<&FileHeader> {
+        self.file(self.game.path_hash(path))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_bytes()?)
+    }
+
+    /// Serializes the header unencrypted, which the game does not accept for its own archives but tools do.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bw = BinaryWriter::new(self.big_endian);
+        bw.write_ascii("BHD5");
+        bw.write_byte(if self.big_endian { 0 } else { 0xFF });
+        bw.write_bytes(&[0, 0, 0]);
+        bw.write_i32(1);
+        bw.reserve_i32("FileSize");
+        bw.write_i32(to_field(self.buckets.len(), "bucket count")?);
+        bw.reserve_i32("BucketsOffset");
+        if self.game >= Game::DarkSouls2 {
+            bw.write_i32(to_field(self.salt.len(), "salt length")?);
+            bw.write_ascii(&self.salt);
+        }
+
+        bw.fill_i32("BucketsOffset", to_field(bw.position(), "buckets offset")?);
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            bw.write_i32(to_field(bucket.len(), "bucket size")?);
+            bw.reserve_i32(&format!("FileHeadersOffset{}", i));
+        }
+
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            bw.fill_i32(&format!("FileHeadersOffset{}", i), to_field(bw.position(), "bucket offset")?);
+            for (j, header) in bucket.iter().enumerate() {
+                header.write(&mut bw, self.game, &format!("{}:{}", i, j));
+            }
+        }
+
+        if self.game >= Game::DarkSouls2 {
+            for (i, bucket) in self.buckets.iter().enumerate() {
+                for (j, header) in bucket.iter().enumerate() {
+                    header.write_records(&mut bw, &format!("{}:{}", i, j))?;
+                }
+            }
+        }
+
+        bw.fill_i32("FileSize", to_field(bw.position(), "header size")?);
+        Ok(bw.finish())
+    }
+}
+
+impl FileHeader {
+    fn read(br: &mut BinaryReader, game: Game) -> Result<FileHeader, Error> {
+        let file_name_hash = if game >= Game::EldenRing { br.read_u64() } else { br.read_u32() as u64 };
+        let padded_file_size = br.read_u32();
+        let mut unpadded_file_size = None;
+        if game >= Game::EldenRing {
+            unpadded_file_size = Some(br.read_u32() as u64);
+        }
+        let file_offset = br.read_i64() as u64;
+
+        let mut sha_hash = None;
+        let mut aes_key = None;
+        if game >= Game::DarkSouls2 {
+            let sha_hash_offset = br.read_i64();
+            let aes_key_offset = br.read_i64();
+            if sha_hash_offset != 0 {
+                br.step_in(record_offset(br, sha_hash_offset, 32, "SHA hash")?);
+                let hash = read_array(br);
+                let ranges = read_ranges(br);
+                br.step_out()?;
+                sha_hash = Some(SHAHash { hash, ranges: ranges? });
+            }
+            if aes_key_offset != 0 {
+                br.step_in(record_offset(br, aes_key_offset, 16, "AES key")?);
+                let key = read_array(br);
+                let ranges = read_ranges(br);
+                br.step_out()?;
+                aes_key = Some(AESKey { key, ranges: ranges? });
+            }
+        }
+
+        if game == Game::DarkSouls3 || game == Game::Sekiro {
+            unpadded_file_size = Some(br.read_i64() as u64);
+        }
+
+        Ok(FileHeader {
+            file_name_hash,
+            padded_file_size,
+            unpadded_file_size,
+            file_offset,
+            sha_hash,
+            aes_key,
+        })
+    }
+
+    // Writes the header, reserving the offsets of its SHA hash and AES key under `key`.
+    fn write(&self, bw: &mut BinaryWriter, game: Game, key: &str) {
+        if game >= Game::EldenRing {
+            bw.write_i64(self.file_name_hash as i64);
+        } else {
+            bw.write_u32(self.file_name_hash as u32);
+        }
+        bw.write_u32(self.padded_file_size);
+        if game >= Game::EldenRing {
+            bw.write_u32(self.unpadded_file_size.unwrap_or_default() as u32);
+        }
+        bw.write_i64(self.file_offset as i64);
+
+        if game >= Game::DarkSouls2 {
+            for (record, present) in [("SHAHashOffset", self.sha_hash.is_some()), ("AESKeyOffset", self.aes_key.is_some())] {
+                if present {
+                    bw.reserve_i64(&format!("{}{}", record, key));
+                } else {
+                    bw.write_i64(0);
+                }
+            }
+        }
+
+        if game == Game::DarkSouls3 || game == Game::Sekiro {
+            bw.write_i64(self.unpadded_file_size.unwrap_or_default() as i64);
+        }
+    }
+
+    fn write_records(&self, bw: &mut BinaryWriter, key: &str) -> Result<(), Error> {
+        if let Some(sha_hash) = &self.sha_hash {
+            bw.fill_i64(&format!("SHAHashOffset{}", key), bw.position() as i64);
+            bw.write_bytes(&sha_hash.hash);
+            write_ranges(bw, &sha_hash.ranges)?;
+        }
+        if let Some(aes_key) = &self.aes_key {
+            bw.fill_i64(&format!("AESKeyOffset{}", key), bw.position() as i64);
+            bw.write_bytes(&aes_key.key);
+            write_ranges(bw, &aes_key.ranges)?;
+        }
+        Ok(())
+    }
+
+    /// The size of the file once its padding has been removed.
+    pub fn file_size(&self) -> u64 {
+        match self.unpadded_file_size {
+            Some(size) if size > 0 => size,
+            _ => self.padded_file_size as u64,
+        }
+    }
+}
+
+impl AESKey {
+    /// Decrypts the encrypted ranges of `data` in place with AES-128 in ECB mode.
+    pub fn decrypt(&self, data: &mut [u8]) -> Result<(), Error> {
+        let cipher = Aes128::new(GenericArray::from_slice(&self.key));
+        for range in &self.ranges {
+            if range.start == -1 && range.end == -1 {
+                continue;
+            }
+
+            let bounds = usize::try_from(range.start).ok().zip(usize::try_from(range.end).ok());
+            let encrypted = bounds.and_then(|(start, end)| data.get_mut(start..end)).ok_or_else(|| {
+                invalid(format!("AES range {:#x}..{:#x} lies outside of the file data.", range.start, range.end))
+            })?;
+            if !encrypted.len().is_multiple_of(16) {
+                return Err(invalid(format!("AES range {:#x}..{:#x} is not a whole number of blocks.", range.start, range.end)));
+            }
+
+            for block in encrypted.chunks_exact_mut(16) {
+                cipher.decrypt_block(GenericArray::from_mut_slice(block));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Size of a single file header in a bucket.
+pub(crate) fn file_header_size(game: Game) -> usize {
+    match game {
+        Game::DarkSouls1 => 0x10,
+        Game::DarkSouls2 => 0x20,
+        Game::DarkSouls3 | Game::Sekiro | Game::EldenRing => 0x28,
+    }
+}
+
+// Finds every header below `dir` with a data file next to it, leaving out the `exclude` directory.
+pub(crate) fn find_archives(dir: &Path, exclude: Option<&Path>, archives: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if !exclude.is_some_and(|exclude| same_path(&path, exclude)) {
+                find_archives(&path, exclude, archives)?;
+            }
+            continue;
+        }
+
+        let is_header = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("bhd") || extension.eq_ignore_ascii_case("bhd5"));
+        if is_header && path.with_extension("bdt").is_file() {
+            archives.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Reads a header as it is, or decrypts it with the key in `key_directory` named after the archive.
+pub(crate) fn read_archive_header(bhd_path: &Path, game: Game, key_directory: Option<&Path>) -> Result<BHD5, Error> {
+    let bytes = fs::read(bhd_path)?;
+    if BHD5::is(&bytes) {
+        return BHD5::from_bytes(bytes, game);
+    }
+
+    let archive = bhd_path.file_stem().unwrap_or_default().to_string_lossy();
+    let key_path = key_directory
+        .map(|directory| directory.join(format!("{}.pem", archive)))
+        .filter(|path| path.is_file())
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} is encrypted and no key was found for it.", archive)))?;
+    BHD5::from_encrypted_bytes(&bytes, game, &RsaPublicKey::read(key_path)?)
+}
+
+fn read_array<const N: usize>(br: &mut BinaryReader) -> [u8; N] {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&br.read_bytes(N));
+    bytes
+}
+
+// Reads the range count and ranges that follow a SHA hash or AES key.
+fn read_ranges(br: &mut BinaryReader) -> Result<Vec<ByteRange>, Error> {
+    let range_count = br.read_i32();
+    let range_count = usize::try_from(range_count).map_err(|_| invalid(format!("Invalid BHD5 range count: {}", range_count)))?;
+    check_range(br, br.position, range_count * 16, "range list")?;
+    Ok((0..range_count).map(|_| ByteRange { start: br.read_i64(), end: br.read_i64() }).collect())
+}
+
+fn write_ranges(bw: &mut BinaryWriter, ranges: &[ByteRange]) -> Result<(), Error> {
+    bw.write_i32(to_field(ranges.len(), "range count")?);
+    for range in ranges {
+        bw.write_i64(range.start);
+        bw.write_i64(range.end);
+    }
+    Ok(())
+}
+
+// Validates the offset of a SHA hash or AES key record, which is followed by at least a range count.
+fn record_offset(br: &BinaryReader, offset: i64, size: usize, what: &str) -> Result<usize, Error> {
+    let offset = usize::try_from(offset).map_err(|_| invalid(format!("Invalid BHD5 {} offset: {}", what, offset)))?;
+    check_range(br, offset, size + 4, what)?;
+    Ok(offset)
+}
+
+fn check_range(br: &BinaryReader, offset: usize, length: usize, what: &str) -> Result<(), Error> {
+    match offset.checked_add(length) {
+        Some(end) if end <= br.memory.len() => Ok(()),
+        _ => Err(invalid(format!("BHD5 {} lies outside of the file.", what))),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use crate::formats::bhd5::unpack::{has_decompressed_files, MANIFEST_NAME};
+use crate::formats::bhd5::{FileHeader, Game, BHD5};
+use crate::formats::binder::hash_table::is_prime;
+use crate::formats::CompressionType;
+
+// Every dvdbnd data file starts with an empty BDF3 header.
+const BDT_HEADER: &[u8; 0x10] = b"BDF307D7R6\0\0\0\0\0\0";
+
+/// Builds a new dvdbnd archive, a BHD5 header and its BDT data file, from loose files.
+///
+/// The header is written unencrypted, which tools and `BHD5::read` accept but the game does not.
+pub struct ArchiveBuilder {
+    pub game: Game,
+    pub big_endian: bool,
+    /// Stored from DS2 onwards, where it usually names the archive's key.
+    pub salt: String,
+    /// DCX variant applied to every named file that does not end in `.dcx` yet, which then gets the
+    /// extension added to its path. None stores files as they are.
+    pub compression: Option<CompressionType>,
+    files: Vec<PendingFile>,
+}
+
+struct PendingFile {
+    // The game path, or None for files only known by their hash.
+    path: Option<String>,
+    file_name_hash: u64,
+    source: FileSource,
+}
+
+enum FileSource {
+    Data(Vec<u8>),
+    Disk(PathBuf),
+}
+
+impl ArchiveBuilder {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            big_endian: false,
+            salt: String::new(),
+            compression: None,
+            files: Vec::new(),
+        }
+    }
+
+    /// Adds a file under a game path such as `/chr/c0000.chrbnd.dcx`.
+    pub fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        self.push(path.to_string(), FileSource::Data(data));
+    }
+
+    /// Adds a file whose path is unknown under the hash it is stored with.
+    pub fn add_hashed_file(&mut self, file_name_hash: u64, data: Vec<u8>) {
+        self.files.push(PendingFile { path: None, file_name_hash, source: FileSource::Data(data) });
+    }
+
+    /// Adds every file below `dir` under its path relative to `dir`. Files are read when the archive is built.
+    ///
+    /// The output of `unpack_game` can be added as is: its manifest and the `.part` files of an
+    /// interrupted run are skipped, and files in `_unknown/<archive>/` are added under the hash
+    /// they are named after. Output written with `UnpackOptions::decompress` is rejected, as its
+    /// files have lost their DCX compression and the `.dcx` in their names.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        if has_decompressed_files(&dir.join(MANIFEST_NAME))? {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "{} was unpacked with decompression, which cannot be packed back.", dir.display())));
+        }
+
+        let mut paths = Vec::new();
+        find_files(dir, &mut paths)?;
+        paths.sort();
+
+        for path in paths {
+            let relative = path.strip_prefix(dir).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+            let components: Vec<String> = relative.components().map(|component| component.as_os_str().to_string_lossy().into_owned()).collect();
+            match components.as_slice() {
+                [name] if name == MANIFEST_NAME => {}
+                [.., name] if name.ends_with(".part") => {}
+                [unknown, _, hash] if unknown == "_unknown" => {
+                    let file_name_hash = u64::from_str_radix(hash, 16)
+                        .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} is not named after a path hash.", path.display())))?;
+                    self.files.push(PendingFile { path: None, file_name_hash, source: FileSource::Disk(path) });
+                }
+                _ => self.push(format!("/{}", components.join("/")), FileSource::Disk(path)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the data file to `bdt` and returns the header describing it.
+    pub fn build<W: Write>(&self, bdt: &mut W) -> Result<BHD5, Error> {
+        let mut headers: Vec<FileHeader> = Vec::with_capacity(self.files.len());
+        let mut names: HashMap<u64, String> = HashMap::new();
+
+        bdt.write_all(BDT_HEADER)?;
+        let mut offset = BDT_HEADER.len() as u64;
+        for file in &self.files {
+            let mut data = match &file.source {
+                FileSource::Data(data) => data.clone(),
+                FileSource::Disk(path) => fs::read(path)?,
+            };
+            let mut file_name_hash = file.file_name_hash;
+            if let (Some(path), Some(compression)) = (&file.path, self.compression) {
+                if !path.to_ascii_lowercase().ends_with(".dcx") {
+                    data = compression.compress(&data)?;
+                    file_name_hash = self.game.path_hash(&format!("{}.dcx", path));
+                }
+            }
+
+            let name = file.path.clone().unwrap_or_else(|| format!("{:016x}", file_name_hash));
+            if let Some(other) = names.insert(file_name_hash, name.clone()) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{} and {} have the same path hash.", other, name)));
+            }
+
+            // Later games pad files to the AES block size and record the size without it
+            let size = data.len() as u64;
+            let padded_size = if self.game >= Game::DarkSouls3 { size.next_multiple_of(0x10) } else { size };
+            let padded_file_size = u32::try_from(padded_size)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} is too large for a BHD5 archive.", name)))?;
+            data.resize(size.next_multiple_of(0x10) as usize, 0);
+
+            headers.push(FileHeader {
+                file_name_hash,
+                padded_file_size,
+                unpadded_file_size: (self.game >= Game::DarkSouls3).then_some(size),
+                file_offset: offset,
+                sha_hash: None,
+                aes_key: None,
+            });
+            bdt.write_all(&data)?;
+            offset += data.len() as u64;
+        }
+
+        let bucket_count = (to_u32(headers.len())? / 7..).find(|&count| is_prime(count)).unwrap_or(2) as u64;
+        let mut bhd = BHD5::new(self.game);
+        bhd.big_endian = self.big_endian;
+        if self.game >= Game::DarkSouls2 {
+            bhd.salt = self.salt.clone();
+        }
+        bhd.buckets = vec![Vec::new(); bucket_count as usize];
+        for header in headers {
+            bhd.buckets[(header.file_name_hash % bucket_count) as usize].push(header);
+        }
+        Ok(bhd)
+    }
+
+    /// Builds the archive and writes its header and data file.
+    pub fn write<P: AsRef<Path>, Q: AsRef<Path>>(&self, bhd_path: P, bdt_path: Q) -> Result<BHD5, Error> {
+        let mut bdt = BufWriter::new(File::create(bdt_path)?);
+        let bhd = self.build(&mut bdt)?;
+        bdt.flush()?;
+        bhd.write(bhd_path)?;
+        Ok(bhd)
+    }
+
+    fn push(&mut self, path: String, source: FileSource) {
+        let file_name_hash = self.game.path_hash(&path);
+        self.files.push(PendingFile { path: Some(path), file_name_hash, source });
+    }
+}
+
+fn to_u32(count: usize) -> Result<u32, Error> {
+    u32::try_from(count).map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Too many files for a BHD5 archive: {}", count)))
+}
+
+fn find_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::formats::bhd5::bdt::BDT;
+use crate::formats::bhd5::dictionary::ArchiveDictionary;
+use crate::formats::bhd5::{find_archives, read_archive_header, FileHeader, Game};
+use crate::formats::CompressionType;
+use crate::util::binary_reader::BinaryReader;
+use crate::util::path::relative_path;
+use crate::util::sf_util::SFUtil;
+
+/// Name of the manifest written to the output directory.
+pub const MANIFEST_NAME: &str = "unpack_manifest.tsv";
+
+/// Settings for `unpack_game`.
+pub struct UnpackOptions {
+    pub game: Game,
+    /// Directory with the public key of each encrypted archive, named after it (`Data0.pem`, `sd.pem`).
+    pub key_directory: Option<PathBuf>,
+    /// Names files, which are otherwise written to `_unknown/<archive>/<hash>`.
+    pub dictionary: Option<ArchiveDictionary>,
+    /// Whether DCX files are decompressed, which also drops their `.dcx` extension.
+    pub decompress: bool,
+    /// Called after every file with the state of the unpack.
+    pub progress: Option<ProgressCallback>,
+}
+
+pub type ProgressCallback = Box<dyn FnMut(&UnpackProgress)>;
+
+/// The file `unpack_game` has just finished with.
+#[derive(Debug, Clone)]
+pub struct UnpackProgress<'a> {
+    /// The stem of the archive the file comes from, such as `Data0`.
+    pub archive: &'a str,
+    pub archive_index: usize,
+    pub archive_count: usize,
+    pub file_index: usize,
+    pub file_count: usize,
+    /// The output path, relative to the output directory.
+    pub path: &'a Path,
+    /// Whether the file was left alone because an earlier run already unpacked it.
+    pub skipped: bool,
+}
+
+/// What `unpack_game` did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnpackReport {
+    pub written: usize,
+    pub skipped: usize,
+    /// Files written under `_unknown` because the dictionary had no name for them.
+    pub unresolved: usize,
+    /// Files written under `_unknown` because a file of an earlier archive already has their name.
+    pub conflicting: usize,
+    /// Archives or files that could not be unpacked, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+// A file recorded in the manifest by an earlier run.
+struct ManifestEntry {
+    size: u64,
+    // Whether it was written with `UnpackOptions::decompress` set.
+    decompressed: bool,
+    path: String,
+}
+
+impl UnpackOptions {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            key_directory: None,
+            dictionary: None,
+            decompress: false,
+            progress: None,
+        }
+    }
+}
+
+/// Extracts every dvdbnd archive found in `install_dir` to `out_dir`.
+///
+/// Each file is written to a temporary name and renamed once complete, after which it is appended
+/// to the manifest in `out_dir`. A later run skips every file the manifest lists that is still on
+/// disk with the recorded size, so an interrupted unpack continues where it stopped. Files written
+/// with a different `decompress` setting are unpacked again.
+///
+/// When files of several archives have the same name, the one from the archive that sorts first
+/// keeps it and the others are written to `_unknown/<archive>/<hash>`.
+pub fn unpack_game<P: AsRef<Path>, Q: AsRef<Path>>(install_dir: P, out_dir: Q, mut options: UnpackOptions) -> Result<UnpackReport, Error> {
+    let install_dir = install_dir.as_ref();
+    let out_dir = out_dir.as_ref();
+    fs::create_dir_all(out_dir)?;
+
+    let manifest_path = out_dir.join(MANIFEST_NAME);
+    let previous = read_manifest(&manifest_path)?;
+    let mut manifest = BufWriter::new(OpenOptions::new().create(true).append(true).open(&manifest_path)?);
+    // An interrupted run may have left half a line, which must not run into the first new one
+    if ends_mid_line(&manifest_path)? {
+        writeln!(manifest)?;
+    }
+    // Output paths taken by the files unpacked so far, lowercase, with the manifest key of each
+    let mut claimed: HashMap<String, String> = HashMap::new();
+
+    let mut archives = Vec::new();
+    find_archives(install_dir, Some(out_dir), &mut archives)?;
+    archives.sort();
+
+    let mut report = UnpackReport::default();
+    for (archive_index, bhd_path) in archives.iter().enumerate() {
+        let archive = bhd_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let bhd = match read_archive_header(bhd_path, options.game, options.key_directory.as_deref()) {
+            Ok(bhd) => bhd,
+            Err(e) => {
+                report.failed.push((bhd_path.clone(), e.to_string()));
+                continue;
+            }
+        };
+        let bdt_path = bhd_path.with_extension("bdt");
+        let mut bdt = match BDT::open(&bdt_path) {
+            Ok(bdt) => bdt,
+            Err(e) => {
+                report.failed.push((bdt_path, e.to_string()));
+                continue;
+            }
+        };
+
+        let file_count = bhd.files().count();
+        for (file_index, header) in bhd.files().enumerate() {
+            let name = options.dictionary.as_ref().and_then(|dictionary| dictionary.name(header.file_name_hash));
+            let key = manifest_key(&archive, header.file_name_hash);
+            let path = match name {
+                Some(name) => {
+                    let path = relative_path(name);
+                    let claim = path.to_string_lossy().replace('\\', "/").to_lowercase();
+                    match claimed.get(&claim) {
+                        Some(owner) if *owner != key => {
+                            report.conflicting += 1;
+                            unknown_output_path(&archive, header, options.game)
+                        }
+                        _ => {
+                            claimed.insert(claim, key.clone());
+                            path
+                        }
+                    }
+                }
+                None => {
+                    report.unresolved += 1;
+                    unknown_output_path(&archive, header, options.game)
+                }
+            };
+
+            let skipped = previous
+                .get(&key)
+                .is_some_and(|entry| entry.decompressed == options.decompress && is_unpacked(out_dir, entry));
+            let mut written_path = path.clone();
+            if skipped {
+                report.skipped += 1;
+                written_path = PathBuf::from(&previous[&key].path);
+            } else {
+                match extract_file(&mut bdt, header, out_dir, &path, options.decompress) {
+                    Ok((path, size)) => {
+                        let path_text = path.to_string_lossy().replace('\\', "/");
+                        writeln!(manifest, "{}\t{}\t{}\t{}", key, size, options.decompress as u8, path_text)?;
+                        manifest.flush()?;
+                        report.written += 1;
+                        written_path = path;
+                    }
+                    Err(e) => report.failed.push((path.clone(), e.to_string())),
+                }
+            }
+
+            if let Some(progress) = options.progress.as_mut() {
+                progress(&UnpackProgress {
+                    archive: &archive,
+                    archive_index,
+                    archive_count: archives.len(),
+                    file_index,
+                    file_count,
+                    path: &written_path,
+                    skipped,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// Writes a single file and returns the path it ended up at, relative to `out_dir`, and its size.
+fn extract_file<R: Read + Seek>(
+    bdt: &mut BDT<R>,
+    header: &FileHeader,
+    out_dir: &Path,
+    path: &Path,
+    decompress: bool,
+) -> Result<(PathBuf, u64), Error> {
+    let mut data = bdt.read_file(header)?;
+    let mut path = path.to_path_buf();
+    if decompress {
+        let mut br = BinaryReader::new(false, data);
+        let mut compression = CompressionType::Unknown;
+        SFUtil::decompress_if_neccessary(&mut br, &mut compression)?;
+        if compression != CompressionType::None && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("dcx")) {
+            path.set_extension("");
+        }
+        data = br.memory;
+    }
+
+    let full_path = out_dir.join(&path);
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut part_path = OsString::from(full_path.as_os_str());
+    part_path.push(".part");
+    let mut file = File::create(&part_path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&part_path, &full_path)?;
+
+    Ok((path, data.len() as u64))
+}
+
+fn unknown_output_path(archive: &str, header: &FileHeader, game: Game) -> PathBuf {
+    let hash = if game >= Game::EldenRing { format!("{:016x}", header.file_name_hash) } else { format!("{:08x}", header.file_name_hash) };
+    Path::new("_unknown").join(archive).join(hash)
+}
+
+fn manifest_key(archive: &str, file_name_hash: u64) -> String {
+    format!("{}\t{:016x}", archive, file_name_hash)
+}
+
+fn ends_mid_line(path: &Path) -> Result<bool, Error> {
+    let mut file = File::open(path)?;
+    if file.seek(SeekFrom::End(0))? == 0 {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0u8];
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+fn is_unpacked(out_dir: &Path, entry: &ManifestEntry) -> bool {
+    fs::metadata(out_dir.join(&entry.path)).is_ok_and(|metadata| metadata.is_file() && metadata.len() == entry.size)
+}
+
+// Whether any file recorded in the manifest at `path` was written decompressed.
+pub(crate) fn has_decompressed_files(path: &Path) -> Result<bool, Error> {
+    Ok(read_manifest(path)?.values().any(|entry| entry.decompressed))
+}
+
+// Reads the files an earlier run recorded, keyed by archive and hash. Later lines win.
+fn read_manifest(path: &Path) -> Result<HashMap<String, ManifestEntry>, Error> {
+    let mut entries = HashMap::new();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+
+    for line in text.lines() {
+        // A line cut off by an interruption lacks fields and is ignored
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+        if let [archive, hash, size, decompressed, path] = fields[..] {
+            if let (Ok(size), Ok(decompressed)) = (size.parse(), decompressed.parse::<u8>()) {
+                let entry = ManifestEntry { size, decompressed: decompressed != 0, path: path.to_string() };
+                entries.insert(format!("{}\t{}", archive, hash), entry);
+            }
+        }
+    }
+    Ok(entries)
+}
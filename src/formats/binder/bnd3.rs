@@ -0,0 +1,125 @@
+use std::io::Error;
+use crate::formats::binder::file_header::{self, FileHeader};
+use crate::formats::binder::{to_field, Binder, BinderFile, Format};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::SoulsFile;
+
+/// A general-purpose file container used before DS2 (chrbnd, objbnd, partsbnd and most menu files).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BND3 {
+    /// A timestamp or other identifier of up to 8 bytes, usually "07D7R6".
+    pub version: String,
+    pub format: Format,
+    /// Whether integers are big-endian, also implied by `Format::BIG_ENDIAN`.
+    pub big_endian: bool,
+    /// Whether the format and flag bytes are stored without reversing their bits.
+    pub bit_big_endian: bool,
+    /// Either 0 or 0x80000000.
+    pub unk18: i32,
+    /// Alignment of the entry data, taken from the entry offsets when read and 0x10 by default.
+    pub alignment: usize,
+    pub files: Vec<BinderFile>,
+}
+
+impl BND3 {
+    // Reads the header and the file headers, leaving the entry data for the caller.
+    pub(crate) fn read_headers(&mut self, br: &mut BinaryReader) -> Result<Vec<FileHeader>, Error> {
+        br.position = 0;
+        br.big_endian = false;
+        br.assert_ascii(&["BND3"])?;
+        self.version = br.read_fixstr(8)?;
+
+        // The bit order byte comes after the format byte it describes
+        self.bit_big_endian = br.get_boolean(0xE);
+        self.format = Format::read(br.read_byte(), self.bit_big_endian);
+        self.big_endian = br.read_boolean();
+        br.assert_byte(&[self.bit_big_endian as u8]);
+        br.assert_byte(&[0]);
+
+        br.big_endian = self.big_endian || self.format.contains(Format::BIG_ENDIAN);
+        let file_count = br.read_i32();
+        let headers_end = br.read_i32() as u32 as u64;
+        self.unk18 = br.assert_i32(&[0, i32::MIN]);
+        br.assert_i32(&[0]);
+
+        let file_count = file_header::check_file_count(file_count, "BND3")?;
+
+        let mut headers = Vec::with_capacity(file_count.min(0x10000));
+        for _ in 0..file_count {
+            headers.push(FileHeader::read_binder3(br, self.format, self.bit_big_endian)?);
+        }
+        self.alignment = file_header::data_alignment(&headers, headers_end);
+        Ok(headers)
+    }
+}
+
+impl SoulsFile for BND3 {
+    fn is(&self, br: &mut BinaryReader) -> bool {
+        br.len() >= 4 && &br.memory[..4] == b"BND3"
+    }
+
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error> {
+        let headers = self.read_headers(br)?;
+        self.files = Vec::with_capacity(headers.len());
+        for header in headers {
+            self.files.push(header.read_data(br)?);
+        }
+
+        Ok(())
+    }
+
+    fn specific_write(&self, bw: &mut BinaryWriter) -> Result<(), Error> {
+        bw.big_endian = self.big_endian || self.format.contains(Format::BIG_ENDIAN);
+        bw.write_ascii("BND3");
+        bw.write_fixstr(&self.version, 8)?;
+        bw.write_byte(self.format.write(self.bit_big_endian));
+        bw.write_boolean(self.big_endian);
+        bw.write_boolean(self.bit_big_endian);
+        bw.write_byte(0);
+        bw.write_i32(to_field(self.files.len(), "file count")?);
+        bw.reserve_i32("FileHeadersEnd");
+        bw.write_i32(self.unk18);
+        bw.write_i32(0);
+
+        for (i, file) in self.files.iter().enumerate() {
+            file_header::write_binder3_file_header(bw, file, self.format, self.bit_big_endian, i);
+        }
+        file_header::write_file_names(bw, &self.files, self.format, false)?;
+        let position = to_field(bw.position(), "header size")?;
+        bw.fill_i32("FileHeadersEnd", position);
+
+        for (i, file) in self.files.iter().enumerate() {
+            let data = file_header::prepare_file_data(bw, file, self.alignment)?;
+            let data_offset = bw.position();
+            file_header::fill_binder3_file_header(bw, file, self.format, i, data_offset, &data)?;
+            bw.write_bytes(&data);
+        }
+
+        Ok(())
+    }
+}
+
+impl Binder for BND3 {
+    fn files(&self) -> &[BinderFile] {
+        &self.files
+    }
+
+    fn files_mut(&mut self) -> &mut Vec<BinderFile> {
+        &mut self.files
+    }
+}
+
+impl Default for BND3 {
+    fn default() -> Self {
+        Self {
+            version: "07D7R6".to_string(),
+            format: Format::IDS | Format::NAMES1 | Format::NAMES2 | Format::COMPRESSION,
+            big_endian: false,
+            bit_big_endian: false,
+            unk18: 0,
+            alignment: 0x10,
+            files: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{Error, ErrorKind};
+use crate::formats::binder::hash_table::BinderHashTable;
+use crate::formats::binder::file_header::{self, FileHeader};
+use crate::formats::binder::{to_field, Binder, BinderFile, Format};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::SoulsFile;
+
+/// A general-purpose file container used from DS2 onwards (chrbnd.dcx, partsbnd.dcx, regulation.bin).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BND4 {
+    pub unk04: bool,
+    pub unk05: bool,
+    /// Whether integers are big-endian.
+    pub big_endian: bool,
+    /// Whether the format and flag bytes are stored without reversing their bits.
+    pub bit_big_endian: bool,
+    /// A timestamp or other identifier of up to 8 bytes.
+    pub version: String,
+    /// Whether names are stored as UTF-16 instead of Shift-JIS.
+    pub unicode: bool,
+    pub format: Format,
+    /// 0, 1, 4 or 0x80. A value of 4 adds a hash table for looking up files by name, which is generated on write.
+    pub extended: u8,
+    /// Alignment of the entry data, taken from the entry offsets when read and 0x10 by default.
+    pub alignment: usize,
+    pub files: Vec<BinderFile>,
+    pub(crate) hash_table_valid: Option<bool>,
+}
+
+impl BND4 {
+    /// Whether the hash table read from the file matches its entries, None if it had no table.
+    pub fn hash_table_valid(&self) -> Option<bool> {
+        self.hash_table_valid
+    }
+
+    // Reads the header, hash table and file headers, leaving the entry data for the caller.
+    pub(crate) fn read_headers(&mut self, br: &mut BinaryReader) -> Result<(Vec<FileHeader>, Option<BinderHashTable>), Error> {
+        br.position = 0;
+        br.big_endian = false;
+        br.assert_ascii(&["BND4"])?;
+        self.unk04 = br.read_boolean();
+        self.unk05 = br.read_boolean();
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        self.big_endian = br.read_boolean();
+        self.bit_big_endian = !br.read_boolean();
+        br.assert_byte(&[0]);
+
+        br.big_endian = self.big_endian;
+        let file_count = br.read_i32();
+        br.assert_i64(&[0x40]); // Header size
+        self.version = br.read_fixstr(8)?;
+        let file_header_size = br.read_i64();
+        let headers_end = br.read_i64() as u64; // Including the hash table
+        self.unicode = br.read_boolean();
+        self.format = Format::read(br.read_byte(), self.bit_big_endian);
+        self.extended = br.assert_byte(&[0, 1, 4, 0x80]);
+        br.assert_byte(&[0]);
+        br.assert_i32(&[0]);
+
+        let hash_table_offset = br.read_i64();
+        let mut hash_table = None;
+        if self.extended == 4 {
+            let offset = usize::try_from(hash_table_offset)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid BND4 hash table offset: {}", hash_table_offset)))?;
+            hash_table = Some(BinderHashTable::read(br, offset)?);
+        } else if hash_table_offset != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected BND4 hash table offset: {}", hash_table_offset)));
+        }
+
+        let file_count = file_header::check_file_count(file_count, "BND4")?;
+        if file_header_size != file_header::binder4_file_header_size(self.format) as i64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("BND4 file header size {:#x} does not match format {:#04x}.", file_header_size, self.format.0),
+            ));
+        }
+
+        let mut headers = Vec::with_capacity(file_count.min(0x10000));
+        for _ in 0..file_count {
+            headers.push(FileHeader::read_binder4(br, self.format, self.bit_big_endian, self.unicode)?);
+        }
+        self.alignment = file_header::data_alignment(&headers, headers_end);
+        Ok((headers, hash_table))
+    }
+}
+
+impl SoulsFile for BND4 {
+    fn is(&self, br: &mut BinaryReader) -> bool {
+        br.len() >= 4 && &br.memory[..4] == b"BND4"
+    }
+
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error> {
+        let (headers, hash_table) = self.read_headers(br)?;
+        self.files = Vec::with_capacity(headers.len());
+        for header in headers {
+            self.files.push(header.read_data(br)?);
+        }
+        self.hash_table_valid = hash_table.map(|hash_table| hash_table.validate(&self.files).is_ok());
+
+        Ok(())
+    }
+
+    fn specific_write(&self, bw: &mut BinaryWriter) -> Result<(), Error> {
+        bw.big_endian = self.big_endian;
+        bw.write_ascii("BND4");
+        bw.write_boolean(self.unk04);
+        bw.write_boolean(self.unk05);
+        bw.write_bytes(&[0, 0, 0]);
+        bw.write_boolean(self.big_endian);
+        bw.write_boolean(!self.bit_big_endian);
+        bw.write_byte(0);
+
+        bw.write_i32(to_field(self.files.len(), "file count")?);
+        bw.write_i64(0x40);
+        bw.write_fixstr(&self.version, 8)?;
+        bw.write_i64(file_header::binder4_file_header_size(self.format) as i64);
+        bw.reserve_i64("HeadersEnd");
+        bw.write_boolean(self.unicode);
+        bw.write_byte(self.format.write(self.bit_big_endian));
+        bw.write_byte(self.extended);
+        bw.write_byte(0);
+        bw.write_i32(0);
+
+        let hash_table = if self.extended == 4 { Some(BinderHashTable::build(&self.files)?) } else { None };
+        if hash_table.is_some() {
+            bw.reserve_i64("HashTableOffset");
+        } else {
+            bw.write_i64(0);
+        }
+
+        for (i, file) in self.files.iter().enumerate() {
+            file_header::write_binder4_file_header(bw, file, self.format, self.bit_big_endian, i);
+        }
+        file_header::write_file_names(bw, &self.files, self.format, self.unicode)?;
+
+        if let Some(hash_table) = hash_table {
+            bw.pad(0x8);
+            bw.fill_i64("HashTableOffset", to_field(bw.position(), "hash table offset")?);
+            hash_table.write(bw)?;
+        }
+        bw.fill_i64("HeadersEnd", to_field(bw.position(), "header size")?);
+
+        for (i, file) in self.files.iter().enumerate() {
+            let data = file_header::prepare_file_data(bw, file, self.alignment)?;
+            let data_offset = bw.position();
+            file_header::fill_binder4_file_header(bw, file, self.format, i, data_offset, &data)?;
+            bw.write_bytes(&data);
+        }
+
+        Ok(())
+    }
+}
+
+impl Binder for BND4 {
+    fn files(&self) -> &[BinderFile] {
+        &self.files
+    }
+
+    fn files_mut(&mut self) -> &mut Vec<BinderFile> {
+        &mut self.files
+    }
+}
+
+impl Default for BND4 {
+    fn default() -> Self {
+        Self {
+            unk04: false,
+            unk05: false,
+            big_endian: false,
+            bit_big_endian: false,
+            version: "07D7R6".to_string(),
+            unicode: true,
+            format: Format::IDS | Format::NAMES1 | Format::NAMES2 | Format::COMPRESSION,
+            extended: 0,
+            alignment: 0x10,
+            files: Vec::new(),
+            hash_table_valid: None,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use crate::formats::binder::file_header::{self, FileHeader};
+use crate::formats::binder::{to_field, Binder, BinderFile, Format};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+
+/// A BND3 split into a header file (BHF3) and a data file (BDF3), as used for tpfbhd and hkxbhd.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BXF3 {
+    /// A timestamp or other identifier of up to 8 bytes, usually "07D7R6".
+    pub version: String,
+    pub format: Format,
+    /// Whether integers are big-endian, also implied by `Format::BIG_ENDIAN`.
+    pub big_endian: bool,
+    /// Whether the format and flag bytes are stored without reversing their bits.
+    pub bit_big_endian: bool,
+    /// Alignment of the entry data, taken from the entry offsets when read and 0x10 by default.
+    pub alignment: usize,
+    pub files: Vec<BinderFile>,
+}
+
+impl BXF3 {
+    /// Returns whether `bytes` start with a BXF3 header.
+    pub fn is_bhd(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"BHF3")
+    }
+
+    /// Returns whether `bytes` start with a BXF3 data header.
+    pub fn is_bdt(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"BDF3")
+    }
+
+    pub fn read<P: AsRef<Path>, Q: AsRef<Path>>(bhd_path: P, bdt_path: Q) -> Result<Self, Error> {
+        Self::from_bytes(fs::read(bhd_path)?, fs::read(bdt_path)?)
+    }
+
+    pub fn from_bytes(bhd: Vec<u8>, bdt: Vec<u8>) -> Result<Self, Error> {
+        let mut bhd = BinaryReader::new(false, bhd);
+        let mut bdt = BinaryReader::new(false, bdt);
+        let mut bxf = BXF3::default();
+
+        let headers = bxf.read_bhf3(&mut bhd)?;
+        read_bdf3(&mut bdt)?;
+
+        bxf.files = Vec::with_capacity(headers.len());
+        for header in headers {
+            bxf.files.push(header.read_data(&bdt)?);
+        }
+
+        Ok(bxf)
+    }
+
+    pub fn write<P: AsRef<Path>, Q: AsRef<Path>>(&self, bhd_path: P, bdt_path: Q) -> Result<(), Error> {
+        let (bhd, bdt) = self.to_bytes()?;
+        fs::write(bhd_path, bhd)?;
+        fs::write(bdt_path, bdt)
+    }
+
+    /// Returns the header file and the data file.
+    pub fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let big_endian = self.big_endian || self.format.contains(Format::BIG_ENDIAN);
+        let mut bhd = BinaryWriter::new(big_endian);
+        let mut bdt = BinaryWriter::new(big_endian);
+
+        bhd.write_ascii("BHF3");
+        bhd.write_fixstr(&self.version, 8)?;
+        bhd.write_byte(self.format.write(self.bit_big_endian));
+        bhd.write_boolean(self.big_endian);
+        bhd.write_boolean(self.bit_big_endian);
+        bhd.write_byte(0);
+        bhd.write_i32(to_field(self.files.len(), "file count")?);
+        bhd.write_i32(0);
+        bhd.write_i32(0);
+        bhd.write_i32(0);
+
+        for (i, file) in self.files.iter().enumerate() {
+            file_header::write_binder3_file_header(&mut bhd, file, self.format, self.bit_big_endian, i);
+        }
+        file_header::write_file_names(&mut bhd, &self.files, self.format, false)?;
+
+        bdt.write_ascii("BDF3");
+        bdt.write_fixstr(&self.version, 8)?;
+        bdt.write_i32(0);
+
+        for (i, file) in self.files.iter().enumerate() {
+            let data = file_header::prepare_file_data(&mut bdt, file, self.alignment)?;
+            file_header::fill_binder3_file_header(&mut bhd, file, self.format, i, bdt.position(), &data)?;
+            bdt.write_bytes(&data);
+        }
+
+        Ok((bhd.finish(), bdt.finish()))
+    }
+
+    // Reads the header file up to and including the file headers.
+    pub(crate) fn read_bhf3(&mut self, br: &mut BinaryReader) -> Result<Vec<FileHeader>, Error> {
+        br.assert_ascii(&["BHF3"])?;
+        self.version = br.read_fixstr(8)?;
+
+        // The bit order byte comes after the format byte it describes
+        self.bit_big_endian = br.get_boolean(0xE);
+        self.format = Format::read(br.read_byte(), self.bit_big_endian);
+        self.big_endian = br.read_boolean();
+        br.assert_byte(&[self.bit_big_endian as u8]);
+        br.assert_byte(&[0]);
+
+        br.big_endian = self.big_endian || self.format.contains(Format::BIG_ENDIAN);
+        let file_count = br.read_i32();
+        br.assert_i32(&[0]);
+        br.assert_i32(&[0]);
+        br.assert_i32(&[0]);
+        let file_count = file_header::check_file_count(file_count, "BXF3")?;
+
+        let mut headers = Vec::with_capacity(file_count.min(0x10000));
+        for _ in 0..file_count {
+            headers.push(FileHeader::read_binder3(br, self.format, self.bit_big_endian)?);
+        }
+        // Entry data starts right after the BDF3 header
+        self.alignment = file_header::data_alignment(&headers, 0x10);
+        Ok(headers)
+    }
+}
+
+pub(crate) fn read_bdf3(br: &mut BinaryReader) -> Result<(), Error> {
+    br.assert_ascii(&["BDF3"])?;
+    br.read_fixstr(8)?; // Version
+    br.assert_i32(&[0]);
+    Ok(())
+}
+
+impl Binder for BXF3 {
+    fn files(&self) -> &[BinderFile] {
+        &self.files
+    }
+
+    fn files_mut(&mut self) -> &mut Vec<BinderFile> {
+        &mut self.files
+    }
+}
+
+impl Default for BXF3 {
+    fn default() -> Self {
+        Self {
+            version: "07D7R6".to_string(),
+            format: Format::IDS | Format::NAMES1 | Format::NAMES2 | Format::COMPRESSION,
+            big_endian: false,
+            bit_big_endian: false,
+            alignment: 0x10,
+            files: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::formats::binder::file_header::{self, FileHeader};
+use crate::formats::binder::hash_table::BinderHashTable;
+use crate::formats::binder::{to_field, Binder, BinderFile, Format};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+
+/// A BND4 split into a header file (BHF4) and a data file (BDF4), as used for chrtpfbhd and sound banks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BXF4 {
+    pub unk04: bool,
+    pub unk05: bool,
+    /// Whether integers are big-endian.
+    pub big_endian: bool,
+    /// Whether the format and flag bytes are stored without reversing their bits.
+    pub bit_big_endian: bool,
+    /// A timestamp or other identifier of up to 8 bytes.
+    pub version: String,
+    /// Whether names are stored as UTF-16 instead of Shift-JIS.
+    pub unicode: bool,
+    pub format: Format,
+    /// 0 or 4. A value of 4 adds a hash table for looking up files by name, which is generated on write.
+    pub extended: u8,
+    /// Alignment of the entry data, taken from the entry offsets when read and 0x10 by default.
+    pub alignment: usize,
+    pub files: Vec<BinderFile>,
+    pub(crate) hash_table_valid: Option<bool>,
+}
+
+impl BXF4 {
+    /// Returns whether `bytes` start with a BXF4 header.
+    pub fn is_bhd(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"BHF4")
+    }
+
+    /// Returns whether `bytes` start with a BXF4 data header.
+    pub fn is_bdt(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"BDF4")
+    }
+
+    pub fn read<P: AsRef<Path>, Q: AsRef<Path>>(bhd_path: P, bdt_path: Q) -> Result<Self, Error> {
+        Self::from_bytes(fs::read(bhd_path)?, fs::read(bdt_path)?)
+    }
+
+    pub fn from_bytes(bhd: Vec<u8>, bdt: Vec<u8>) -> Result<Self, Error> {
+        let mut bhd = BinaryReader::new(false, bhd);
+        let mut bdt = BinaryReader::new(false, bdt);
+        let mut bxf = BXF4::default();
+
+        let (headers, hash_table) = bxf.read_bhf4(&mut bhd)?;
+        let header_size = read_bdf4(&mut bdt)?;
+        if header_size != 0x30 {
+            bxf.alignment = file_header::data_alignment(&headers, header_size);
+        }
+
+        bxf.files = Vec::with_capacity(headers.len());
+        for header in headers {
+            bxf.files.push(header.read_data(&bdt)?);
+        }
+        bxf.hash_table_valid = hash_table.map(|hash_table| hash_table.validate(&bxf.files).is_ok());
+
+        Ok(bxf)
+    }
+
+    pub fn write<P: AsRef<Path>, Q: AsRef<Path>>(&self, bhd_path: P, bdt_path: Q) -> Result<(), Error> {
+        let (bhd, bdt) = self.to_bytes()?;
+        fs::write(bhd_path, bhd)?;
+        fs::write(bdt_path, bdt)
+    }
+
+    /// Returns the header file and the data file.
+    pub fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let mut bhd = BinaryWriter::new(self.big_endian);
+        let mut bdt = BinaryWriter::new(self.big_endian);
+
+        bhd.write_ascii("BHF4");
+        bhd.write_boolean(self.unk04);
+        bhd.write_boolean(self.unk05);
+        bhd.write_bytes(&[0, 0, 0]);
+        bhd.write_boolean(self.big_endian);
+        bhd.write_boolean(!self.bit_big_endian);
+        bhd.write_byte(0);
+
+        bhd.write_i32(to_field(self.files.len(), "file count")?);
+        bhd.write_i64(0x40);
+        bhd.write_fixstr(&self.version, 8)?;
+        bhd.write_i64(file_header::binder4_file_header_size(self.format) as i64);
+        bhd.write_i64(0);
+        bhd.write_boolean(self.unicode);
+        bhd.write_byte(self.format.write(self.bit_big_endian));
+        bhd.write_byte(self.extended);
+        bhd.write_byte(0);
+        bhd.write_i32(0);
+
+        let hash_table = if self.extended == 4 { Some(BinderHashTable::build(&self.files)?) } else { None };
+        if hash_table.is_some() {
+            bhd.reserve_i64("HashTableOffset");
+        } else {
+            bhd.write_i64(0);
+        }
+
+        for (i, file) in self.files.iter().enumerate() {
+            file_header::write_binder4_file_header(&mut bhd, file, self.format, self.bit_big_endian, i);
+        }
+        file_header::write_file_names(&mut bhd, &self.files, self.format, self.unicode)?;
+
+        if let Some(hash_table) = hash_table {
+            bhd.pad(0x8);
+            bhd.fill_i64("HashTableOffset", to_field(bhd.position(), "hash table offset")?);
+            hash_table.write(&mut bhd)?;
+        }
+
+        bdt.write_ascii("BDF4");
+        bdt.write_boolean(self.unk04);
+        bdt.write_boolean(self.unk05);
+        bdt.write_bytes(&[0, 0, 0]);
+        bdt.write_boolean(self.big_endian);
+        bdt.write_boolean(!self.bit_big_endian);
+        bdt.write_byte(0);
+        bdt.write_i32(0);
+        bdt.write_i64(0x30);
+        bdt.write_fixstr(&self.version, 8)?;
+        bdt.write_i64(0);
+        bdt.write_i64(0);
+
+        for (i, file) in self.files.iter().enumerate() {
+            let data = file_header::prepare_file_data(&mut bdt, file, self.alignment)?;
+            file_header::fill_binder4_file_header(&mut bhd, file, self.format, i, bdt.position(), &data)?;
+            bdt.write_bytes(&data);
+        }
+
+        Ok((bhd.finish(), bdt.finish()))
+    }
+
+    /// Whether the hash table read from the file matches its entries, None if it had no table.
+    pub fn hash_table_valid(&self) -> Option<bool> {
+        self.hash_table_valid
+    }
+
+    // Reads the header file up to and including the file headers.
+    pub(crate) fn read_bhf4(&mut self, br: &mut BinaryReader) -> Result<(Vec<FileHeader>, Option<BinderHashTable>), Error> {
+        br.assert_ascii(&["BHF4"])?;
+        self.unk04 = br.read_boolean();
+        self.unk05 = br.read_boolean();
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        self.big_endian = br.read_boolean();
+        self.bit_big_endian = !br.read_boolean();
+        br.assert_byte(&[0]);
+
+        br.big_endian = self.big_endian;
+        let file_count = br.read_i32();
+        br.assert_i64(&[0x40]); // Header size
+        self.version = br.read_fixstr(8)?;
+        let file_header_size = br.read_i64();
+        br.assert_i64(&[0]);
+        self.unicode = br.read_boolean();
+        self.format = Format::read(br.read_byte(), self.bit_big_endian);
+        self.extended = br.assert_byte(&[0, 4]);
+        br.assert_byte(&[0]);
+        br.assert_i32(&[0]);
+
+        let hash_table_offset = br.read_i64();
+        let mut hash_table = None;
+        if self.extended == 4 {
+            let offset = usize::try_from(hash_table_offset)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid BXF4 hash table offset: {}", hash_table_offset)))?;
+            hash_table = Some(BinderHashTable::read(br, offset)?);
+        } else if hash_table_offset != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected BXF4 hash table offset: {}", hash_table_offset)));
+        }
+
+        if file_header_size != file_header::binder4_file_header_size(self.format) as i64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("BXF4 file header size {:#x} does not match format {:#04x}.", file_header_size, self.format.0),
+            ));
+        }
+        let file_count = file_header::check_file_count(file_count, "BXF4")?;
+
+        let mut headers = Vec::with_capacity(file_count.min(0x10000));
+        for _ in 0..file_count {
+            headers.push(FileHeader::read_binder4(br, self.format, self.bit_big_endian, self.unicode)?);
+        }
+        // Entry data starts right after the BDF4 header
+        self.alignment = file_header::data_alignment(&headers, 0x30);
+        Ok((headers, hash_table))
+    }
+}
+
+// Reads the BDF4 header and returns its size, which is 0x30 or 0x40.
+pub(crate) fn read_bdf4(br: &mut BinaryReader) -> Result<u64, Error> {
+    br.assert_ascii(&["BDF4"])?;
+    br.read_boolean(); // Unk04
+    br.read_boolean(); // Unk05
+    br.assert_byte(&[0]);
+    br.assert_byte(&[0]);
+    br.assert_byte(&[0]);
+    br.big_endian = br.read_boolean();
+    br.read_boolean(); // Bit big-endian
+    br.assert_byte(&[0]);
+    br.assert_i32(&[0]);
+    let header_size = br.read_i64();
+    if header_size != 0x30 && header_size != 0x40 {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected BDF4 header size: {:#x}", header_size)));
+    }
+    br.read_fixstr(8)?; // Version
+    br.assert_i64(&[0]);
+    br.assert_i64(&[0]);
+    Ok(header_size as u64)
+}
+
+impl Binder for BXF4 {
+    fn files(&self) -> &[BinderFile] {
+        &self.files
+    }
+
+    fn files_mut(&mut self) -> &mut Vec<BinderFile> {
+        &mut self.files
+    }
+}
+
+impl Default for BXF4 {
+    fn default() -> Self {
+        Self {
+            unk04: false,
+            unk05: false,
+            big_endian: false,
+            bit_big_endian: false,
+            version: "07D7R6".to_string(),
+            unicode: true,
+            format: Format::IDS | Format::NAMES1 | Format::NAMES2 | Format::COMPRESSION,
+            extended: 0,
+            alignment: 0x10,
+            files: Vec::new(),
+            hash_table_valid: None,
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{Error, ErrorKind};
+use crate::formats::binder::{to_field, BinderFile, FileFlags, Format};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+
+// An entry as described by its header, before its data has been read.
+pub(crate) struct FileHeader {
+    pub(crate) file: BinderFile,
+    pub(crate) data_offset: u64,
+    pub(crate) compressed_size: u64,
+}
+
+// Rejects the negative file count of a corrupt header, `binder` names the format in the error.
+pub(crate) fn check_file_count(file_count: i32, binder: &str) -> Result<usize, Error> {
+    usize::try_from(file_count).map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid {} file count: {}", binder, file_count)))
+}
+
+impl FileHeader {
+    // Reads a BND3 or BXF3 file header.
+    pub(crate) fn read_binder3(br: &mut BinaryReader, format: Format, bit_big_endian: bool) -> Result<FileHeader, Error> {
+        let mut file = BinderFile {
+            flags: FileFlags::read(br.read_byte(), bit_big_endian, format),
+            ..BinderFile::default()
+        };
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+
+        let compressed_size = br.read_i32() as u32 as u64;
+        let data_offset = if format.has_long_offsets() { br.read_i64() as u64 } else { br.read_u32() as u64 };
+        if format.has_ids() {
+            file.id = br.read_i32();
+        }
+        if format.has_names() {
+            let name_offset = br.read_u32() as usize;
+            file.name = Some(br.get_shift_jis(name_offset)?);
+        }
+        if format.has_compression() {
+            br.read_i32(); // Uncompressed size
+        }
+
+        Ok(FileHeader { file, data_offset, compressed_size })
+    }
+
+    // Reads a BND4 or BXF4 file header.
+    pub(crate) fn read_binder4(br: &mut BinaryReader, format: Format, bit_big_endian: bool, unicode: bool) -> Result<FileHeader, Error> {
+        let mut file = BinderFile {
+            flags: FileFlags::read(br.read_byte(), bit_big_endian, format),
+            ..BinderFile::default()
+        };
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_byte(&[0]);
+        br.assert_i32(&[-1]);
+
+        let compressed_size = br.read_i64() as u64;
+        if format.has_compression() {
+            br.read_i64(); // Uncompressed size
+        }
+        let data_offset = if format.has_long_offsets() { br.read_i64() as u64 } else { br.read_u32() as u64 };
+        if format.has_ids() {
+            file.id = br.read_i32();
+        }
+        if format.has_names() {
+            let name_offset = br.read_u32() as usize;
+            file.name = Some(if unicode { br.get_utf16(name_offset)? } else { br.get_shift_jis(name_offset)? });
+        }
+        if format == Format::NAMES1 {
+            file.id = br.read_i32();
+            br.assert_i32(&[0]);
+        }
+
+        Ok(FileHeader { file, data_offset, compressed_size })
+    }
+
+    // Reads the entry data from `br`, which is the binder itself or its separate data file.
+    pub(crate) fn read_data(mut self, br: &BinaryReader) -> Result<BinderFile, Error> {
+        self.file.read_data(br, self.data_offset, self.compressed_size)?;
+        Ok(self.file)
+    }
+}
+
+pub(crate) fn write_binder3_file_header(bw: &mut BinaryWriter, file: &BinderFile, format: Format, bit_big_endian: bool, index: usize) {
+    bw.write_byte(file.flags.write(bit_big_endian, format));
+    bw.write_bytes(&[0, 0, 0]);
+    bw.reserve_i32(&format!("CompressedSize{}", index));
+    write_data_offset(bw, format, index);
+    if format.has_ids() {
+        bw.write_i32(file.id);
+    }
+    if format.has_names() {
+        bw.reserve_u32(&format!("NameOffset{}", index));
+    }
+    if format.has_compression() {
+        bw.reserve_i32(&format!("UncompressedSize{}", index));
+    }
+}
+
+pub(crate) fn write_binder4_file_header(bw: &mut BinaryWriter, file: &BinderFile, format: Format, bit_big_endian: bool, index: usize) {
+    bw.write_byte(file.flags.write(bit_big_endian, format));
+    bw.write_bytes(&[0, 0, 0]);
+    bw.write_i32(-1);
+    bw.reserve_i64(&format!("CompressedSize{}", index));
+    if format.has_compression() {
+        bw.reserve_i64(&format!("UncompressedSize{}", index));
+    }
+    write_data_offset(bw, format, index);
+    if format.has_ids() {
+        bw.write_i32(file.id);
+    }
+    if format.has_names() {
+        bw.reserve_u32(&format!("NameOffset{}", index));
+    }
+    if format == Format::NAMES1 {
+        bw.write_i32(file.id);
+        bw.write_i32(0);
+    }
+}
+
+// Size of a single BND4 or BXF4 file header, which depends on the fields the format enables.
+pub(crate) fn binder4_file_header_size(format: Format) -> usize {
+    let mut size = 0x10;
+    if format.has_compression() {
+        size += 8;
+    }
+    size += if format.has_long_offsets() { 8 } else { 4 };
+    if format.has_ids() {
+        size += 4;
+    }
+    if format.has_names() {
+        size += 4;
+    }
+    if format == Format::NAMES1 {
+        size += 8;
+    }
+    size
+}
+
+fn write_data_offset(bw: &mut BinaryWriter, format: Format, index: usize) {
+    if format.has_long_offsets() {
+        bw.reserve_i64(&format!("DataOffset{}", index));
+    } else {
+        bw.reserve_u32(&format!("DataOffset{}", index));
+    }
+}
+
+// Writes the names of all entries and points their headers at them.
+pub(crate) fn write_file_names(bw: &mut BinaryWriter, files: &[BinderFile], format: Format, unicode: bool) -> Result<(), Error> {
+    if !format.has_names() {
+        return Ok(());
+    }
+
+    for (i, file) in files.iter().enumerate() {
+        let position = to_field(bw.position(), "name offset")?;
+        bw.fill_u32(&format!("NameOffset{}", i), position);
+        let name = file.name.as_deref().unwrap_or("");
+        if unicode {
+            bw.write_utf16(name);
+        } else {
+            bw.write_shift_jis(name)?;
+        }
+    }
+    Ok(())
+}
+
+// Fills in the size and offset fields of an entry whose stored `data` was written at `data_offset`.
+pub(crate) fn fill_binder3_file_header(bw: &mut BinaryWriter, file: &BinderFile, format: Format, index: usize, data_offset: usize, data: &[u8]) -> Result<(), Error> {
+    fill_data_offset(bw, format, index, data_offset)?;
+    bw.fill_i32(&format!("CompressedSize{}", index), to_field(data.len(), "file size")?);
+    if format.has_compression() {
+        bw.fill_i32(&format!("UncompressedSize{}", index), to_field(file.data.len(), "file size")?);
+    }
+    Ok(())
+}
+
+pub(crate) fn fill_binder4_file_header(bw: &mut BinaryWriter, file: &BinderFile, format: Format, index: usize, data_offset: usize, data: &[u8]) -> Result<(), Error> {
+    fill_data_offset(bw, format, index, data_offset)?;
+    bw.fill_i64(&format!("CompressedSize{}", index), to_field(data.len(), "file size")?);
+    if format.has_compression() {
+        bw.fill_i64(&format!("UncompressedSize{}", index), to_field(file.data.len(), "file size")?);
+    }
+    Ok(())
+}
+
+fn fill_data_offset(bw: &mut BinaryWriter, format: Format, index: usize, data_offset: usize) -> Result<(), Error> {
+    if format.has_long_offsets() {
+        bw.fill_i64(&format!("DataOffset{}", index), to_field(data_offset, "data offset")?);
+    } else {
+        bw.fill_u32(&format!("DataOffset{}", index), to_field(data_offset, "data offset")?);
+    }
+    Ok(())
+}
+
+// Returns the bytes to store for an entry, aligning the writer they will be written to.
+pub(crate) fn prepare_file_data(bw: &mut BinaryWriter, file: &BinderFile, alignment: usize) -> Result<Vec<u8>, Error> {
+    let data = file.stored_data()?;
+    if !data.is_empty() {
+        bw.pad(alignment.max(1));
+    }
+    Ok(data)
+}
+
+// The alignment that gives back the data offsets of `headers` when their data is written in order
+// from `data_start`. 0x10 is preferred, and also used when no alignment matches the offsets.
+pub(crate) fn data_alignment(headers: &[FileHeader], data_start: u64) -> usize {
+    let matches = |alignment: u64| {
+        let mut position = data_start;
+        headers.iter().filter(|header| header.compressed_size > 0).all(|header| {
+            let expected = position.next_multiple_of(alignment);
+            position = header.data_offset.saturating_add(header.compressed_size);
+            header.data_offset == expected
+        })
+    };
+    let larger = (5..=16).map(|shift| 1u64 << shift);
+    let smaller = (0..4).rev().map(|shift| 1u64 << shift);
+    std::iter::once(0x10).chain(larger).chain(smaller).find(|&alignment| matches(alignment)).unwrap_or(0x10) as usize
+}
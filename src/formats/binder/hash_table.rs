@@ -0,0 +1,147 @@
+use std::io::{Error, ErrorKind};
+use crate::formats::binder::{to_field, BinderFile};
+use crate::util::path_hash::path_hash_32;
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+
+// A range of `hashes` that share a bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HashGroup {
+    pub(crate) length: i32,
+    pub(crate) index: i32,
+}
+
+// The path hash of the file at `index`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PathHash {
+    pub(crate) hash: u32,
+    pub(crate) index: i32,
+}
+
+// The name lookup table of BND4 and BXF4 headers with the extended flag set to 4.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct BinderHashTable {
+    pub(crate) groups: Vec<HashGroup>,
+    pub(crate) hashes: Vec<PathHash>,
+}
+
+impl BinderHashTable {
+    pub(crate) fn read(br: &mut BinaryReader, offset: usize) -> Result<BinderHashTable, Error> {
+        br.step_in(offset);
+        let hashes_offset = br.read_i64();
+        let group_count = br.read_u32();
+        br.assert_byte(&[0x10]);
+        br.assert_byte(&[8]);
+        br.assert_byte(&[8]);
+        br.assert_byte(&[0]);
+
+        let mut table = BinderHashTable::default();
+        for _ in 0..group_count {
+            let length = br.read_i32();
+            let index = br.read_i32();
+            table.groups.push(HashGroup { length, index });
+        }
+
+        let hash_count = table.groups.iter().map(|group| group.length.max(0) as usize).sum::<usize>();
+        if hashes_offset < 0 || hashes_offset as usize + hash_count * 8 > br.len() {
+            br.step_out()?;
+            return Err(Error::new(ErrorKind::InvalidData, "Binder hash table lies outside of the file."));
+        }
+
+        br.position = hashes_offset as usize;
+        for _ in 0..hash_count {
+            let hash = br.read_u32();
+            let index = br.read_i32();
+            table.hashes.push(PathHash { hash, index });
+        }
+
+        br.step_out()?;
+        Ok(table)
+    }
+
+    // Builds the table for `files`: the smallest prime number of groups that is at least a seventh of
+    // the file count, each holding the files whose path hash falls into it sorted by hash.
+    pub(crate) fn build(files: &[BinderFile]) -> Result<BinderHashTable, Error> {
+        let group_count = (files.len() as u32 / 7..=100_000)
+            .find(|&p| is_prime(p))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Too many binder files for a hash table: {}", files.len())))?;
+
+        let mut buckets: Vec<Vec<PathHash>> = vec![Vec::new(); group_count as usize];
+        for (i, file) in files.iter().enumerate() {
+            let hash = path_hash_32(file.name.as_deref().unwrap_or(""));
+            buckets[(hash % group_count) as usize].push(PathHash { hash, index: to_field(i, "file index")? });
+        }
+
+        let mut table = BinderHashTable::default();
+        for mut bucket in buckets {
+            bucket.sort_by_key(|path_hash| path_hash.hash);
+            let index = to_field(table.hashes.len(), "hash index")?;
+            table.groups.push(HashGroup { length: to_field(bucket.len(), "hash group size")?, index });
+            table.hashes.extend(bucket);
+        }
+        Ok(table)
+    }
+
+    // Checks that every file is listed once, under its path hash, in the group that hash belongs to.
+    pub(crate) fn validate(&self, files: &[BinderFile]) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidData, message));
+        let group_count = self.groups.len() as u32;
+        if group_count == 0 && !files.is_empty() {
+            return invalid("Binder hash table has no groups.".to_string());
+        }
+
+        let mut seen = vec![false; files.len()];
+        for (group_index, group) in self.groups.iter().enumerate() {
+            let start = usize::try_from(group.index).ok();
+            let end = start.zip(usize::try_from(group.length).ok()).and_then(|(start, length)| start.checked_add(length));
+            let hashes = match start.zip(end).and_then(|(start, end)| self.hashes.get(start..end)) {
+                Some(hashes) => hashes,
+                None => return invalid(format!("Binder hash group {} lies outside of the hash list.", group_index)),
+            };
+
+            for path_hash in hashes {
+                let file = usize::try_from(path_hash.index).ok().and_then(|index| files.get(index).map(|file| (index, file)));
+                let Some((index, file)) = file else {
+                    return invalid(format!("Binder hash points to missing file {}.", path_hash.index));
+                };
+                if path_hash_32(file.name.as_deref().unwrap_or("")) != path_hash.hash {
+                    return invalid(format!("Binder hash {:#010x} does not match the name of file {}.", path_hash.hash, index));
+                }
+                if path_hash.hash % group_count != group_index as u32 {
+                    return invalid(format!("Binder hash {:#010x} is in group {} instead of {}.", path_hash.hash, group_index, path_hash.hash % group_count));
+                }
+                if std::mem::replace(&mut seen[index], true) {
+                    return invalid(format!("Binder file {} is listed in the hash table twice.", index));
+                }
+            }
+        }
+
+        match seen.iter().position(|&seen| !seen) {
+            Some(index) => invalid(format!("Binder file {} is missing from the hash table.", index)),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn write(&self, bw: &mut BinaryWriter) -> Result<(), Error> {
+        bw.reserve_i64("HashesOffset");
+        bw.write_u32(to_field(self.groups.len(), "hash group count")?);
+        bw.write_bytes(&[0x10, 8, 8, 0]);
+
+        for group in &self.groups {
+            bw.write_i32(group.length);
+            bw.write_i32(group.index);
+        }
+
+        bw.fill_i64("HashesOffset", to_field(bw.position(), "hash table offset")?);
+        for hash in &self.hashes {
+            bw.write_u32(hash.hash);
+            bw.write_i32(hash.index);
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn is_prime(value: u32) -> bool {
+    value >= 2 && (2..).take_while(|d| d * d <= value).all(|d| !value.is_multiple_of(d))
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::formats::binder::{AnyBinder, Binder, BinderFile, FileFlags, Format};
+use crate::formats::{CompressionType, LazyBinder, BND3, BND4, BXF3, BXF4};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::path::relative_path;
+use crate::util::sf_util::SFUtil;
+use crate::util::{Compressed, SoulsFile};
+
+/// Name of the layout file written next to the entries of an unpacked binder.
+pub const LAYOUT_NAME: &str = "_binder_layout.json";
+
+// Directory next to the layout file that holds the stored bytes of compressed entries and binders.
+const STORED_DIR: &str = "_stored";
+
+/// Which binder an unpacked directory came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinderKind {
+    BND3,
+    BND4,
+    BXF3,
+    BXF4,
+}
+
+/// Everything about a binder except the contents of its entries.
+///
+/// Together with the unpacked entries this is enough to write the binder again exactly as it was read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinderLayout {
+    pub kind: BinderKind,
+    pub version: String,
+    /// The format byte after undoing its bit order.
+    pub format: u8,
+    pub big_endian: bool,
+    pub bit_big_endian: bool,
+    /// BND3 and BXF3 only.
+    pub unk18: i32,
+    /// BND4 and BXF4 only.
+    pub unk04: bool,
+    pub unk05: bool,
+    pub unicode: bool,
+    pub extended: u8,
+    /// The compression of the whole binder. Split binders are never compressed.
+    pub compression: CompressionType,
+    pub compression_level: u8,
+    /// Alignment of the entry data. Layouts written before it was recorded use 0x10.
+    #[serde(default = "default_alignment")]
+    pub alignment: usize,
+    /// The compressed binder as it was read, written again when the rebuilt binder is unchanged.
+    #[serde(default)]
+    pub stored: Option<StoredBytes>,
+    /// The entries in the order they are stored.
+    pub files: Vec<FileLayout>,
+}
+
+/// A single entry of a `BinderLayout`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileLayout {
+    pub id: i32,
+    /// The name as stored, including its root path such as `N:\FDP\data\INTERROOT_win64\`.
+    pub name: Option<String>,
+    pub flags: u8,
+    pub compression: CompressionType,
+    pub compression_level: u8,
+    /// Where the entry was unpacked to, relative to the layout file.
+    pub path: String,
+    /// The compressed entry as it was read, written again while the unpacked file is unchanged.
+    #[serde(default)]
+    pub stored: Option<StoredBytes>,
+}
+
+/// Compressed bytes kept from unpacking, so unchanged data is not compressed again on repacking.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredBytes {
+    /// Where the compressed bytes were saved, relative to the layout file.
+    pub path: String,
+    /// SHA-256 of the uncompressed data they were read as, in hex.
+    pub sha256: String,
+}
+
+/// A repacked binder, ready to be written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackedBinder {
+    Single(Vec<u8>),
+    Split { bhd: Vec<u8>, bdt: Vec<u8> },
+}
+
+impl BinderLayout {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Unpacks a BND3 or BND4, compressed or not, into `out_dir` and writes its layout there.
+pub fn unpack_binder<P: AsRef<Path>>(bytes: Vec<u8>, out_dir: P) -> Result<BinderLayout, Error> {
+    let out_dir = out_dir.as_ref();
+    let mut binder = Compressed::<AnyBinder>::from_bytes(bytes.clone())?;
+
+    let mut layout = match &binder.file {
+        AnyBinder::BND3(bnd) => {
+            let mut layout = empty_layout(BinderKind::BND3, &bnd.version, bnd.format, bnd.big_endian, bnd.bit_big_endian, bnd.alignment);
+            layout.unk18 = bnd.unk18;
+            layout
+        }
+        AnyBinder::BND4(bnd) => {
+            let mut layout = empty_layout(BinderKind::BND4, &bnd.version, bnd.format, bnd.big_endian, bnd.bit_big_endian, bnd.alignment);
+            layout.unk04 = bnd.unk04;
+            layout.unk05 = bnd.unk05;
+            layout.unicode = bnd.unicode;
+            layout.extended = bnd.extended;
+            layout
+        }
+    };
+    layout.compression = binder.compression;
+    layout.compression_level = binder.compression_level;
+
+    if binder.files().iter().any(|file| file.flags.is_compressed()) {
+        keep_stored_bytes(binder.files_mut(), &LazyBinder::new(Cursor::new(bytes.clone()))?)?;
+    }
+    if binder.compression != CompressionType::None {
+        let mut bw = BinaryWriter::new(false);
+        binder.specific_write(&mut bw)?;
+        layout.stored = Some(write_stored(out_dir, "binder", &bytes, &bw.finish())?);
+    }
+    write_entries(&mut layout, binder.files(), out_dir)?;
+    Ok(layout)
+}
+
+/// Unpacks a BXF3 or BXF4 from its header and data file into `out_dir` and writes its layout there.
+pub fn unpack_split_binder<P: AsRef<Path>>(bhd: Vec<u8>, bdt: Vec<u8>, out_dir: P) -> Result<BinderLayout, Error> {
+    let lazy = LazyBinder::new_split(bhd.clone(), Cursor::new(bdt))?;
+    let mut files = lazy.load_files()?;
+    keep_stored_bytes(&mut files, &lazy)?;
+
+    let mut br = BinaryReader::new(false, bhd);
+    let mut layout = if lazy.kind == BinderKind::BXF3 {
+        let mut bxf = BXF3::default();
+        bxf.read_bhf3(&mut br)?;
+        empty_layout(BinderKind::BXF3, &bxf.version, bxf.format, bxf.big_endian, bxf.bit_big_endian, bxf.alignment)
+    } else {
+        let mut bxf = BXF4::default();
+        bxf.read_bhf4(&mut br)?;
+        let mut layout = empty_layout(BinderKind::BXF4, &bxf.version, bxf.format, bxf.big_endian, bxf.bit_big_endian, bxf.alignment);
+        layout.unk04 = bxf.unk04;
+        layout.unk05 = bxf.unk05;
+        layout.unicode = bxf.unicode;
+        layout.extended = bxf.extended;
+        layout
+    };
+    write_entries(&mut layout, &files, out_dir.as_ref())?;
+    Ok(layout)
+}
+
+/// Builds the binder described by the layout in `dir` from the entries next to it.
+///
+/// A directory that was unpacked and left unchanged repacks to the bytes it was unpacked from.
+/// Compressed entries and binders are only compressed again when their contents changed.
+pub fn repack_binder<P: AsRef<Path>>(dir: P) -> Result<PackedBinder, Error> {
+    let dir = dir.as_ref();
+    let layout = BinderLayout::read(dir.join(LAYOUT_NAME))?;
+
+    let mut files = Vec::with_capacity(layout.files.len());
+    for file in &layout.files {
+        let data = fs::read(dir.join(checked_path(&file.path)?))?;
+        let stored = match &file.stored {
+            Some(stored) if stored.sha256 == sha256_hex(&data) => Some(fs::read(dir.join(checked_path(&stored.path)?))?),
+            _ => None,
+        };
+        files.push(BinderFile {
+            flags: FileFlags(file.flags),
+            id: file.id,
+            name: file.name.clone(),
+            data,
+            compression: file.compression,
+            compression_level: file.compression_level,
+            stored,
+        });
+    }
+
+    let format = Format(layout.format);
+    let packed = match layout.kind {
+        BinderKind::BND3 => {
+            let bnd = BND3 {
+                version: layout.version.clone(),
+                format,
+                big_endian: layout.big_endian,
+                bit_big_endian: layout.bit_big_endian,
+                unk18: layout.unk18,
+                alignment: layout.alignment,
+                files,
+            };
+            PackedBinder::Single(pack_single(dir, &layout, AnyBinder::BND3(bnd))?)
+        }
+        BinderKind::BND4 => {
+            let bnd = BND4 {
+                unk04: layout.unk04,
+                unk05: layout.unk05,
+                big_endian: layout.big_endian,
+                bit_big_endian: layout.bit_big_endian,
+                version: layout.version.clone(),
+                unicode: layout.unicode,
+                format,
+                extended: layout.extended,
+                alignment: layout.alignment,
+                files,
+                hash_table_valid: None,
+            };
+            PackedBinder::Single(pack_single(dir, &layout, AnyBinder::BND4(bnd))?)
+        }
+        BinderKind::BXF3 => {
+            let bxf = BXF3 {
+                version: layout.version.clone(),
+                format,
+                big_endian: layout.big_endian,
+                bit_big_endian: layout.bit_big_endian,
+                alignment: layout.alignment,
+                files,
+            };
+            let (bhd, bdt) = bxf.to_bytes()?;
+            PackedBinder::Split { bhd, bdt }
+        }
+        BinderKind::BXF4 => {
+            let bxf = BXF4 {
+                unk04: layout.unk04,
+                unk05: layout.unk05,
+                big_endian: layout.big_endian,
+                bit_big_endian: layout.bit_big_endian,
+                version: layout.version.clone(),
+                unicode: layout.unicode,
+                format,
+                extended: layout.extended,
+                alignment: layout.alignment,
+                files,
+                hash_table_valid: None,
+            };
+            let (bhd, bdt) = bxf.to_bytes()?;
+            PackedBinder::Split { bhd, bdt }
+        }
+    };
+    Ok(packed)
+}
+
+// Writes a BND3 or BND4 with the compression of the layout, or as it was read if it was rebuilt unchanged.
+fn pack_single(dir: &Path, layout: &BinderLayout, binder: AnyBinder) -> Result<Vec<u8>, Error> {
+    let mut bw = BinaryWriter::new(false);
+    binder.specific_write(&mut bw)?;
+    let bytes = bw.finish();
+    match &layout.stored {
+        Some(stored) if stored.sha256 == sha256_hex(&bytes) => fs::read(dir.join(checked_path(&stored.path)?)),
+        _ => SFUtil::compress(&bytes, layout.compression, layout.compression_level),
+    }
+}
+
+// Keeps the bytes compressed entries are stored as, read again from `lazy`, which holds the same entries.
+fn keep_stored_bytes(files: &mut [BinderFile], lazy: &LazyBinder) -> Result<(), Error> {
+    for (file, entry) in files.iter_mut().zip(&lazy.entries) {
+        if file.flags.is_compressed() {
+            file.stored = Some(entry.raw_data()?);
+        }
+    }
+    Ok(())
+}
+
+// Saves the compressed bytes of `data` below the stored directory.
+fn write_stored(out_dir: &Path, name: &str, stored: &[u8], data: &[u8]) -> Result<StoredBytes, Error> {
+    let path = Path::new(STORED_DIR).join(name);
+    fs::create_dir_all(out_dir.join(STORED_DIR))?;
+    fs::write(out_dir.join(&path), stored)?;
+    Ok(StoredBytes {
+        path: portable_path(&path),
+        sha256: sha256_hex(data),
+    })
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn default_alignment() -> usize {
+    0x10
+}
+
+fn empty_layout(kind: BinderKind, version: &str, format: Format, big_endian: bool, bit_big_endian: bool, alignment: usize) -> BinderLayout {
+    BinderLayout {
+        kind,
+        version: version.to_string(),
+        format: format.0,
+        big_endian,
+        bit_big_endian,
+        unk18: 0,
+        unk04: false,
+        unk05: false,
+        unicode: false,
+        extended: 0,
+        compression: CompressionType::None,
+        compression_level: 0,
+        alignment,
+        stored: None,
+        files: Vec::new(),
+    }
+}
+
+// Writes every entry below `out_dir`, recording where each went, and then the layout itself.
+fn write_entries(layout: &mut BinderLayout, files: &[BinderFile], out_dir: &Path) -> Result<(), Error> {
+    let root = name_root(files);
+    let mut taken = HashSet::from([LAYOUT_NAME.to_ascii_lowercase()]);
+
+    for (i, file) in files.iter().enumerate() {
+        let mut path = file
+            .name
+            .as_deref()
+            .map(|name| relative_path(name.get(root.len()..).unwrap_or_default()))
+            .filter(|path| !path.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from(file.id.to_string()));
+        // Entries sharing a name, or clashing with the stored bytes, are kept apart so none of them is lost
+        if !taken.insert(portable_path(&path).to_ascii_lowercase()) || path.starts_with(STORED_DIR) {
+            path = Path::new("_duplicates").join(i.to_string()).join(path);
+            taken.insert(portable_path(&path).to_ascii_lowercase());
+        }
+
+        let full_path = out_dir.join(&path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&full_path, &file.data)?;
+        let stored = match &file.stored {
+            Some(stored) => Some(write_stored(out_dir, &i.to_string(), stored, &file.data)?),
+            None => None,
+        };
+
+        layout.files.push(FileLayout {
+            id: file.id,
+            name: file.name.clone(),
+            flags: file.flags.0,
+            compression: file.compression,
+            compression_level: file.compression_level,
+            path: portable_path(&path),
+            stored,
+        });
+    }
+
+    fs::create_dir_all(out_dir)?;
+    layout.write(out_dir.join(LAYOUT_NAME))
+}
+
+// The directory every named entry shares, such as `N:\FDP\data\INTERROOT_win64\chr\c1000\`.
+fn name_root(files: &[BinderFile]) -> String {
+    let mut names = files.iter().filter_map(|file| file.name.as_deref()).filter(|name| !name.is_empty());
+    let Some(first) = names.next() else {
+        return String::new();
+    };
+    let mut root = first.rfind(['\\', '/']).map_or("", |end| &first[..=end]);
+    for name in names {
+        while !name.get(..root.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(root)) {
+            root = root[..root.len() - 1].rfind(['\\', '/']).map_or("", |end| &root[..=end]);
+        }
+    }
+    root.to_string()
+}
+
+fn portable_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Paths in a layout may have been edited by hand, so they are checked before being read.
+fn checked_path(path: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(path);
+    if relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        Ok(relative.to_path_buf())
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, format!("Binder layout path {:?} leaves the directory.", path)))
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::formats::binder::bxf3::read_bdf3;
+use crate::formats::binder::bxf4::read_bdf4;
+use crate::formats::binder::file_header::FileHeader;
+use crate::formats::binder::layout::BinderKind;
+use crate::formats::binder::{BinderFile, FileFlags, Format};
+use crate::formats::{CompressionType, BND3, BND4, BXF3, BXF4, DCX};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::sf_util::SFUtil;
+
+/// A binder of which only the headers have been read.
+///
+/// Entries are read from the underlying reader, and decompressed, when their data is requested,
+/// so opening a large binder costs no more than reading its file headers and the DCX headers of
+/// compressed entries. A binder that is DCX
+/// compressed as a whole still has to be decompressed up front, but its entries are not.
+pub struct LazyBinder {
+    pub kind: BinderKind,
+    pub entries: Vec<LazyEntry>,
+    source: Arc<EntrySource>,
+}
+
+/// A binder entry that reads its data on demand.
+#[derive(Clone)]
+pub struct LazyEntry {
+    pub flags: FileFlags,
+    pub id: i32,
+    pub name: Option<String>,
+    /// Where the stored bytes start, in the binder or, for split binders, in the data file.
+    pub offset: u64,
+    /// Size of the stored bytes, which are DCX compressed when `flags` says so.
+    pub size: u64,
+    /// The DCX variant of the stored bytes, None when `flags` does not mark them as compressed.
+    pub compression: CompressionType,
+    /// The Oodle level byte when `compression` is DCX_KRAK.
+    pub compression_level: u8,
+    index: usize,
+    source: Arc<EntrySource>,
+}
+
+pub(crate) trait EntryReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> EntryReader for T {}
+
+// The reader entries are loaded from, shared by every entry of a binder.
+struct EntrySource {
+    reader: Mutex<Box<dyn EntryReader>>,
+    cache: EntryCache<usize>,
+}
+
+// Decompressed entry data kept in memory once it has been read, if enabled.
+pub(crate) struct EntryCache<K> {
+    entries: Mutex<Option<HashMap<K, Arc<[u8]>>>>,
+}
+
+impl LazyBinder {
+    /// Opens a BND3 or BND4 file, compressed or not.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    /// Opens a BXF3 or BXF4 from its header file and data file.
+    pub fn open_split<P: AsRef<Path>, Q: AsRef<Path>>(bhd_path: P, bdt_path: Q) -> Result<Self, Error> {
+        Self::new_split(std::fs::read(bhd_path)?, BufReader::new(File::open(bdt_path)?))
+    }
+
+    /// Reads the headers of a BND3 or BND4 from `reader`, which is kept to load the entries from.
+    pub fn new<R: Read + Seek + Send + 'static>(mut reader: R) -> Result<Self, Error> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"BND3" && &magic != b"BND4" {
+            // Only the whole file can be decompressed, after which the entries are read from memory
+            let mut bytes = Vec::new();
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_to_end(&mut bytes)?;
+            let mut br = BinaryReader::new(false, bytes);
+            let mut compression = CompressionType::Unknown;
+            SFUtil::decompress_if_neccessary(&mut br, &mut compression)?;
+            if compression == CompressionType::None {
+                return Err(Error::new(ErrorKind::InvalidData, "Not a BND3 or BND4 file."));
+            }
+            return Self::new(Cursor::new(br.memory));
+        }
+
+        let headers_end = headers_end(&mut reader, &magic)?;
+        let mut br = BinaryReader::new(false, read_prefix(&mut reader, headers_end)?);
+        let (kind, headers) = if &magic == b"BND3" {
+            (BinderKind::BND3, BND3::default().read_headers(&mut br)?)
+        } else {
+            (BinderKind::BND4, BND4::default().read_headers(&mut br)?.0)
+        };
+        Self::from_headers(kind, headers, Box::new(reader))
+    }
+
+    /// Reads the headers of a BXF3 or BXF4 from its header file. Entries are loaded from `bdt`.
+    pub fn new_split<R: Read + Seek + Send + 'static>(bhd: Vec<u8>, mut bdt: R) -> Result<Self, Error> {
+        let mut bhd = BinaryReader::new(false, bhd);
+        let (kind, headers, bdt_header_size) = if BXF3::is_bhd(&bhd.memory) {
+            (BinderKind::BXF3, BXF3::default().read_bhf3(&mut bhd)?, 0x10)
+        } else {
+            (BinderKind::BXF4, BXF4::default().read_bhf4(&mut bhd)?.0, bdf4_header_size(&mut bdt)?)
+        };
+
+        let mut bdt_header = BinaryReader::new(false, read_prefix(&mut bdt, bdt_header_size)?);
+        if kind == BinderKind::BXF3 {
+            read_bdf3(&mut bdt_header)?;
+        } else {
+            read_bdf4(&mut bdt_header)?;
+        }
+        Self::from_headers(kind, headers, Box::new(bdt))
+    }
+
+    fn from_headers(kind: BinderKind, headers: Vec<FileHeader>, mut reader: Box<dyn EntryReader>) -> Result<Self, Error> {
+        let mut compressions = Vec::with_capacity(headers.len());
+        for header in &headers {
+            compressions.push(if header.file.flags.is_compressed() {
+                entry_compression(reader.as_mut(), header)?
+            } else {
+                (CompressionType::None, 0)
+            });
+        }
+
+        let source = Arc::new(EntrySource {
+            reader: Mutex::new(reader),
+            cache: EntryCache::new(),
+        });
+        let entries = headers
+            .into_iter()
+            .zip(compressions)
+            .enumerate()
+            .map(|(index, (header, (compression, compression_level)))| LazyEntry {
+                flags: header.file.flags,
+                id: header.file.id,
+                name: header.file.name,
+                offset: header.data_offset,
+                size: header.compressed_size,
+                compression,
+                compression_level,
+                index,
+                source: Arc::clone(&source),
+            })
+            .collect();
+        Ok(Self { kind, entries, source })
+    }
+
+    /// Sets whether entries keep their data in memory after it has been read. Disabling it drops the cached data.
+    pub fn set_caching(&self, enabled: bool) {
+        self.source.cache.set_enabled(enabled);
+    }
+
+    /// Returns the first entry with the given ID.
+    pub fn entry_by_id(&self, id: i32) -> Option<&LazyEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Returns the first entry with the given name, ignoring case.
+    pub fn entry_by_name(&self, name: &str) -> Option<&LazyEntry> {
+        self.entries.iter().find(|entry| entry.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// Reads every entry into a regular binder file.
+    pub fn load_files(&self) -> Result<Vec<BinderFile>, Error> {
+        self.entries.iter().map(LazyEntry::load).collect()
+    }
+}
+
+impl LazyEntry {
+    /// Reads the entry and decompresses it if it is compressed. Cached data is shared, not copied.
+    pub fn data(&self) -> Result<Arc<[u8]>, Error> {
+        if let Some(data) = self.source.cache.get(&self.index) {
+            return Ok(data);
+        }
+        let data: Arc<[u8]> = self.load()?.data.into();
+        self.source.cache.insert(self.index, Arc::clone(&data));
+        Ok(data)
+    }
+
+    /// Reads the entry as it is stored, without decompressing it.
+    pub fn raw_data(&self) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        let mut reader = self.source.reader.lock().unwrap_or_else(PoisonError::into_inner);
+        reader.seek(SeekFrom::Start(self.offset))?;
+        reader.by_ref().take(self.size).read_to_end(&mut data)?;
+        if (data.len() as u64) < self.size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Binder entry {} ({:?}) lies outside of the file.", self.id, self.name.as_deref().unwrap_or("")),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Whether the data of this entry is currently cached.
+    pub fn is_cached(&self) -> bool {
+        self.source.cache.contains(&self.index)
+    }
+
+    /// Reads the entry into a regular binder file, which also records its DCX variant.
+    pub fn load(&self) -> Result<BinderFile, Error> {
+        let mut file = BinderFile {
+            flags: self.flags,
+            id: self.id,
+            name: self.name.clone(),
+            ..BinderFile::default()
+        };
+        file.read_data(&BinaryReader::new(false, self.raw_data()?), 0, self.size)?;
+        Ok(file)
+    }
+}
+
+impl<K: Hash + Eq> EntryCache<K> {
+    pub(crate) fn new() -> Self {
+        Self { entries: Mutex::new(None) }
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if enabled != entries.is_some() {
+            *entries = if enabled { Some(HashMap::new()) } else { None };
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<Arc<[u8]>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner).as_ref()?.get(key).cloned()
+    }
+
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner).as_ref().is_some_and(|entries| entries.contains_key(key))
+    }
+
+    // Does nothing while caching is disabled.
+    pub(crate) fn insert(&self, key: K, data: Arc<[u8]>) {
+        if let Some(entries) = self.entries.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            entries.insert(key, data);
+        }
+    }
+}
+
+// The DCX variant and level of a compressed entry, read from the start of its stored bytes.
+fn entry_compression(reader: &mut dyn EntryReader, header: &FileHeader) -> Result<(CompressionType, u8), Error> {
+    // Every DCX header field the variant is detected from lies within the first 0x40 bytes
+    reader.seek(SeekFrom::Start(header.data_offset))?;
+    let mut prefix = Vec::new();
+    reader.take(header.compressed_size.min(0x40)).read_to_end(&mut prefix)?;
+    let mut br = BinaryReader::new(false, prefix);
+    let compression = DCX::detect(&mut br).unwrap_or(CompressionType::Unknown);
+    Ok((compression, DCX::compression_level(&mut br)))
+}
+
+// Size of everything before the entry data, which holds the headers, names and hash table.
+fn headers_end<R: Read + Seek>(reader: &mut R, magic: &[u8; 4]) -> Result<usize, Error> {
+    let mut br = BinaryReader::new(false, read_prefix(reader, 0x40)?);
+    if br.memory.len() < 0x40 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Binder header is cut off."));
+    }
+    let end = if magic == b"BND3" {
+        let bit_big_endian = br.get_boolean(0xE);
+        let format = Format::read(br.get_byte(0xC), bit_big_endian);
+        br.big_endian = br.get_boolean(0xD) || format.contains(Format::BIG_ENDIAN);
+        br.get_i32(0x14) as i64
+    } else {
+        br.big_endian = br.get_boolean(0x9);
+        br.position = 0x28;
+        br.read_i64()
+    };
+    usize::try_from(end).map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid binder headers end: {}", end)))
+}
+
+// Reads up to `size` bytes from the start of the reader.
+// Reads the size field of a BDF4 header, which follows the magic, flags and endianness.
+fn bdf4_header_size<R: Read + Seek>(reader: &mut R) -> Result<usize, Error> {
+    let prefix = read_prefix(reader, 0x18)?;
+    if prefix.len() < 0x18 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "The BDF4 header is truncated."));
+    }
+    let field: [u8; 8] = prefix[0x10..0x18].try_into().unwrap();
+    let size = if prefix[0x09] != 0 { u64::from_be_bytes(field) } else { u64::from_le_bytes(field) };
+    Ok(size.clamp(0x30, 0x40) as usize)
+}
+
+fn read_prefix<R: Read + Seek>(reader: &mut R, size: usize) -> Result<Vec<u8>, Error> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut prefix = Vec::with_capacity(size.min(0x100000));
+    reader.by_ref().take(size as u64).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
@@ -0,0 +1,275 @@
+pub mod bnd3;
+pub mod bnd4;
+pub mod bxf3;
+pub mod bxf4;
+pub(crate) mod file_header;
+pub(crate) mod hash_table;
+pub mod layout;
+pub mod lazy;
+
+use std::io::{Error, ErrorKind};
+use std::ops::BitOr;
+use crate::formats::{CompressionType, BND3, BND4, DCX};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::SoulsFile;
+
+/// Layout flags of a binder header, after undoing the bit order the file was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Format(pub u8);
+
+impl Format {
+    pub const NONE: Format = Format(0);
+    /// Forces the binder to be big-endian regardless of the endianness byte.
+    pub const BIG_ENDIAN: Format = Format(0b0000_0001);
+    pub const IDS: Format = Format(0b0000_0010);
+    pub const NAMES1: Format = Format(0b0000_0100);
+    pub const NAMES2: Format = Format(0b0000_1000);
+    pub const LONG_OFFSETS: Format = Format(0b0001_0000);
+    pub const COMPRESSION: Format = Format(0b0010_0000);
+    pub const FLAG6: Format = Format(0b0100_0000);
+    pub const FLAG7: Format = Format(0b1000_0000);
+
+    pub fn contains(self, other: Format) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn has_ids(self) -> bool {
+        self.contains(Format::IDS)
+    }
+
+    pub fn has_names(self) -> bool {
+        self.0 & (Format::NAMES1.0 | Format::NAMES2.0) != 0
+    }
+
+    pub fn has_long_offsets(self) -> bool {
+        self.contains(Format::LONG_OFFSETS)
+    }
+
+    pub fn has_compression(self) -> bool {
+        self.contains(Format::COMPRESSION)
+    }
+
+    // The format byte is stored with its bits reversed unless it looks big-endian already.
+    pub(crate) fn read(raw: u8, bit_big_endian: bool) -> Format {
+        let keep_raw = bit_big_endian || (raw & 1 != 0 && raw & 0b1000_0000 == 0);
+        Format(if keep_raw { raw } else { raw.reverse_bits() })
+    }
+
+    pub(crate) fn write(self, bit_big_endian: bool) -> u8 {
+        let keep_raw = bit_big_endian || (self.contains(Format::BIG_ENDIAN) && !self.contains(Format::FLAG7));
+        if keep_raw { self.0 } else { self.0.reverse_bits() }
+    }
+}
+
+impl BitOr for Format {
+    type Output = Format;
+
+    fn bitor(self, rhs: Format) -> Format {
+        Format(self.0 | rhs.0)
+    }
+}
+
+/// Per-entry flags of a binder file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileFlags(pub u8);
+
+impl FileFlags {
+    pub const NONE: FileFlags = FileFlags(0);
+    /// The entry data is DCX compressed.
+    pub const COMPRESSED: FileFlags = FileFlags(0b0000_0001);
+    pub const FLAG1: FileFlags = FileFlags(0b0000_0010);
+    pub const FLAG2: FileFlags = FileFlags(0b0000_0100);
+    pub const FLAG3: FileFlags = FileFlags(0b0000_1000);
+    pub const FLAG4: FileFlags = FileFlags(0b0001_0000);
+    pub const FLAG5: FileFlags = FileFlags(0b0010_0000);
+    pub const FLAG6: FileFlags = FileFlags(0b0100_0000);
+    pub const FLAG7: FileFlags = FileFlags(0b1000_0000);
+
+    pub fn contains(self, other: FileFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self.contains(FileFlags::COMPRESSED)
+    }
+
+    // Entry flags follow the same bit order rules as the format byte, keyed on the format.
+    pub(crate) fn read(raw: u8, bit_big_endian: bool, format: Format) -> FileFlags {
+        let keep_raw = bit_big_endian || (format.contains(Format::COMPRESSION) && format.contains(Format::FLAG7));
+        FileFlags(if keep_raw { raw } else { raw.reverse_bits() })
+    }
+
+    pub(crate) fn write(self, bit_big_endian: bool, format: Format) -> u8 {
+        let keep_raw = bit_big_endian || (format.contains(Format::COMPRESSION) && format.contains(Format::FLAG7));
+        if keep_raw { self.0 } else { self.0.reverse_bits() }
+    }
+}
+
+impl BitOr for FileFlags {
+    type Output = FileFlags;
+
+    fn bitor(self, rhs: FileFlags) -> FileFlags {
+        FileFlags(self.0 | rhs.0)
+    }
+}
+
+/// Access to the entries of any binder, whether it is stored in one file or split into a header and data file.
+pub trait Binder {
+    fn files(&self) -> &[BinderFile];
+    fn files_mut(&mut self) -> &mut Vec<BinderFile>;
+
+    /// Returns the first entry with the given ID.
+    fn file_by_id(&self, id: i32) -> Option<&BinderFile> {
+        self.files().iter().find(|file| file.id == id)
+    }
+
+    /// Returns the first entry with the given name, ignoring case.
+    fn file_by_name(&self, name: &str) -> Option<&BinderFile> {
+        self.files().iter().find(|file| file.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+    }
+}
+
+/// A BND3 or BND4, for code that takes either.
+///
+/// Read it through `Compressed<AnyBinder>` to also get the compression the binder was stored with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnyBinder {
+    BND3(BND3),
+    BND4(BND4),
+}
+
+impl AnyBinder {
+    pub fn into_files(self) -> Vec<BinderFile> {
+        match self {
+            AnyBinder::BND3(bnd) => bnd.files,
+            AnyBinder::BND4(bnd) => bnd.files,
+        }
+    }
+}
+
+impl Default for AnyBinder {
+    fn default() -> Self {
+        AnyBinder::BND4(BND4::default())
+    }
+}
+
+impl SoulsFile for AnyBinder {
+    fn is(&self, br: &mut BinaryReader) -> bool {
+        BND3::default().is(br) || BND4::default().is(br)
+    }
+
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error> {
+        *self = if br.memory.starts_with(b"BND3") {
+            let mut bnd = BND3::default();
+            bnd.specific_read(br)?;
+            AnyBinder::BND3(bnd)
+        } else if br.memory.starts_with(b"BND4") {
+            let mut bnd = BND4::default();
+            bnd.specific_read(br)?;
+            AnyBinder::BND4(bnd)
+        } else {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a BND3 or BND4 file."));
+        };
+        Ok(())
+    }
+
+    fn specific_write(&self, bw: &mut BinaryWriter) -> Result<(), Error> {
+        match self {
+            AnyBinder::BND3(bnd) => bnd.specific_write(bw),
+            AnyBinder::BND4(bnd) => bnd.specific_write(bw),
+        }
+    }
+}
+
+impl Binder for AnyBinder {
+    fn files(&self) -> &[BinderFile] {
+        match self {
+            AnyBinder::BND3(bnd) => &bnd.files,
+            AnyBinder::BND4(bnd) => &bnd.files,
+        }
+    }
+
+    fn files_mut(&mut self) -> &mut Vec<BinderFile> {
+        match self {
+            AnyBinder::BND3(bnd) => &mut bnd.files,
+            AnyBinder::BND4(bnd) => &mut bnd.files,
+        }
+    }
+}
+
+/// A single entry of a binder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinderFile {
+    pub flags: FileFlags,
+    pub id: i32,
+    pub name: Option<String>,
+    /// The uncompressed contents of the entry.
+    pub data: Vec<u8>,
+    /// The DCX variant the data is stored with when `flags` has the compressed bit set.
+    pub compression: CompressionType,
+    /// The Oodle level byte used when `compression` is DCX_KRAK.
+    pub compression_level: u8,
+    // Stored bytes written instead of compressing `data` again, set when repacking an unchanged entry.
+    pub(crate) stored: Option<Vec<u8>>,
+}
+
+impl BinderFile {
+    pub fn new(id: i32, name: Option<String>, data: Vec<u8>) -> Self {
+        Self {
+            flags: FileFlags::FLAG1,
+            id,
+            name,
+            data,
+            compression: CompressionType::None,
+            compression_level: 0,
+            stored: None,
+        }
+    }
+
+    // Reads the stored bytes of an entry and decompresses them if the entry is flagged as compressed.
+    pub(crate) fn read_data(&mut self, br: &BinaryReader, offset: u64, size: u64) -> Result<(), Error> {
+        let start = usize::try_from(offset).map_err(|_| invalid_entry(self))?;
+        let end = usize::try_from(size).ok().and_then(|size| start.checked_add(size)).ok_or_else(|| invalid_entry(self))?;
+        let bytes = br.memory.get(start..end).ok_or_else(|| invalid_entry(self))?.to_vec();
+
+        if !self.flags.is_compressed() {
+            self.data = bytes;
+            return Ok(());
+        }
+
+        let mut dcx = BinaryReader::new(false, bytes);
+        self.compression_level = DCX::compression_level(&mut dcx);
+        self.data = DCX::decompress(&mut dcx, &mut self.compression)?;
+        Ok(())
+    }
+
+    // Returns the bytes as they are stored in the binder.
+    pub(crate) fn stored_data(&self) -> Result<Vec<u8>, Error> {
+        if !self.flags.is_compressed() {
+            return Ok(self.data.clone());
+        }
+        if let Some(stored) = &self.stored {
+            return Ok(stored.clone());
+        }
+        DCX::compress(&self.data, self.compression, self.compression_level)
+    }
+}
+
+impl Default for BinderFile {
+    fn default() -> Self {
+        Self::new(-1, None, Vec::new())
+    }
+}
+
+fn invalid_entry(file: &BinderFile) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Binder entry {} ({:?}) lies outside of the file.", file.id, file.name.as_deref().unwrap_or("")),
+    )
+}
+
+// Converts a size or offset to the width of its header field.
+pub(crate) fn to_field<T: TryFrom<usize>>(value: usize, field: &str) -> Result<T, Error> {
+    T::try_from(value).map_err(|_| Error::new(ErrorKind::InvalidInput, format!("The {} is too large: {}", field, value)))
+}
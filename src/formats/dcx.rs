@@ -1,11 +1,15 @@
 use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
 use crate::util::sf_util::SFUtil;
-use crate::util::oodle::Oodle;
-use std::io::{Error, ErrorKind, Cursor, Write};
+use crate::util::oodle::{Oodle, OodleLZ_CompressionLevel, OodleLZ_Compressor};
+use flate2::{Compression, Decompress, FlushDecompress};
+use flate2::write::DeflateEncoder;
+use std::io::{Error, ErrorKind, Write};
+use serde::{Deserialize, Serialize};
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) enum CompressionType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionType {
     Unknown,
     None,
     Zlib,
@@ -20,6 +24,34 @@ pub(crate) enum CompressionType {
     DCX_KRAK,
 }
 
+impl CompressionType {
+    /// Compresses `data` into this format. DCX_KRAK uses Oodle level 6.
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        SFUtil::compress(data, self, 6)
+    }
+
+    /// Compresses `data` into this format with the given Oodle level, as stored in a DCX_KRAK header.
+    pub fn compress_with_level(self, data: &[u8], compression_level: u8) -> Result<Vec<u8>, Error> {
+        SFUtil::compress(data, self, compression_level)
+    }
+
+    /// Decompresses a DCX or DCP file in memory, returning the data and the format it was stored in.
+    pub fn decompress(data: Vec<u8>) -> Result<(Vec<u8>, CompressionType), Error> {
+        let mut compression = CompressionType::Unknown;
+        let decompressed = DCX::decompress(&mut BinaryReader::new(false, data), &mut compression)?;
+        Ok((decompressed, compression))
+    }
+}
+
+// Size of the data EDGE chunks decompress to, except for the final chunk.
+pub(crate) const EDGE_CHUNK_SIZE: usize = 0x10000;
+
+pub(crate) struct EdgeChunk {
+    pub(crate) offset: usize,
+    pub(crate) size: usize,
+    pub(crate) compressed: bool,
+}
+
 pub(crate) struct DCX {}
 
 impl DCX {
@@ -28,29 +60,67 @@ impl DCX {
             return false;
         }
 
-        let magic = br.get_ascii(0, 4).expect("Error!");
-        return magic == "DCP\0" || magic == "DCX\0";
+        let magic = &br.memory[..4];
+        magic == b"DCP\0" || magic == b"DCX\0"
     }
 
     pub(crate) fn decompress(br: &mut BinaryReader, compression: &mut CompressionType) -> Result<Vec<u8>, Error> {
+        *compression = DCX::detect(br)?;
+
+        br.position = 0;
+        match *compression {
+            CompressionType::Zlib => {
+                let compression_size = br.len();
+                return SFUtil::read_zlib(br, compression_size);
+            }
+            CompressionType::DCP_EDGE => {
+                return DCX::decompress_dcp_edge(br);
+            }
+            CompressionType::DCP_DFLT => {
+                return DCX::decompress_dcp_dflt(br);
+            }
+            CompressionType::DCX_EDGE => {
+                return DCX::decompress_dcx_edge(br);
+            }
+            CompressionType::DCX_DFLT_10000_24_9
+            | CompressionType::DCX_DFLT_10000_44_9
+            | CompressionType::DCX_DFLT_11000_44_8
+            | CompressionType::DCX_DFLT_11000_44_9
+            | CompressionType::DCX_DFLT_11000_44_9_15 => {
+                return DCX::decompress_dcx_dflt(br, compression);
+            }
+            CompressionType::DCX_KRAK => {
+                return DCX::decompress_dcx_krak(br);
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Unknown DCX format.",
+                ));
+            }
+        }
+    }
+
+    // Identifies the compression variant from the header without consuming any input.
+    pub(crate) fn detect(br: &mut BinaryReader) -> Result<CompressionType, Error> {
         br.big_endian = true;
-        *compression = CompressionType::Unknown;
+        let mut compression = CompressionType::Unknown;
 
-        let magic = br.get_ascii(0, 4)?;
+        let magic = br.memory.get(..4).unwrap_or_default();
 
-        if magic == "DCP\0" {
+        if magic == b"DCP\0" {
             let format = br.get_ascii(4, 4)?;
 
             if format == "DFLT" {
-                *compression = CompressionType::DCP_DFLT;
+                compression = CompressionType::DCP_DFLT;
             } else if format == "EDGE" {
-                *compression = CompressionType::DCP_EDGE;
+                compression = CompressionType::DCP_EDGE;
             }
-        } else if magic == "DCX\0" {
+        } else if magic == b"DCX\0" {
             let format = br
                 .get_ascii(0x28, 4)?;
             if format == "EDGE" {
-                *compression = CompressionType::DCX_EDGE;
+                compression = CompressionType::DCX_EDGE;
             } else if format == "DFLT" {
                 let unk04 = br.get_i32(0x4);
                 let unk10 = br.get_i32(0x10);
@@ -58,69 +128,28 @@ impl DCX {
                 let unk38 = br.get_byte(0x38);
 
                 if unk04 == 0x10000 && unk10 == 0x24 && unk30 == 9 && unk38 == 0 {
-                    *compression = CompressionType::DCX_DFLT_10000_24_9;
+                    compression = CompressionType::DCX_DFLT_10000_24_9;
                 } else if unk04 == 0x10000 && unk10 == 0x44 && unk30 == 9 && unk38 == 0 {
-                    *compression = CompressionType::DCX_DFLT_10000_44_9;
+                    compression = CompressionType::DCX_DFLT_10000_44_9;
                 } else if unk04 == 0x11000 && unk10 == 0x44 && unk30 == 8 && unk38 == 0 {
-                    *compression = CompressionType::DCX_DFLT_11000_44_8;
+                    compression = CompressionType::DCX_DFLT_11000_44_8;
                 } else if unk04 == 0x11000 && unk10 == 0x44 && unk30 == 9 && unk38 == 0 {
-                    *compression = CompressionType::DCX_DFLT_11000_44_9;
+                    compression = CompressionType::DCX_DFLT_11000_44_9;
                 } else if unk04 == 0x11000 && unk10 == 0x44 && unk30 == 9 && unk38 == 15 {
-                    *compression = CompressionType::DCX_DFLT_11000_44_9_15;
+                    compression = CompressionType::DCX_DFLT_11000_44_9_15;
                 }
             }
             else if format == "KRAK" {
-                *compression = CompressionType::DCX_KRAK;
-            }
-            else {
-                let b0 = br.get_byte(0);
-                let b1 = br.get_byte(1);
-
-                if b0 == 0x78 && (b1 == 0x01 || b1 == 0x5E || b1 == 0x9C || b1 == 0xDA)
-                {
-                    *compression = CompressionType::Zlib;
-                }
-            }
-
-            br.position = 0;
-            match *compression {
-                CompressionType::Zlib => {
-                    let compression_size = br.len();
-                    return SFUtil::read_zlib(br, compression_size);
-                }
-                CompressionType::DCP_EDGE => {
-                    return DCX::decompress_dcp_edge(br);
-                }
-                CompressionType::DCP_DFLT => {
-                    return DCX::decompress_dcp_dflt(br);
-                }
-                CompressionType::DCX_EDGE => {
-                    return DCX::decompress_dcx_edge(br);
-                }
-                CompressionType::DCX_DFLT_10000_24_9
-                | CompressionType::DCX_DFLT_10000_44_9
-                | CompressionType::DCX_DFLT_11000_44_8
-                | CompressionType::DCX_DFLT_11000_44_9
-                | CompressionType::DCX_DFLT_11000_44_9_15 => {
-                    return DCX::decompress_dcx_dflt(br, compression);
-                }
-                CompressionType::DCX_KRAK => {
-                    return DCX::decompress_dcx_krak(br, None);
-                }
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "Unknown DCX format.",
-                    ));
-                }
+                compression = CompressionType::DCX_KRAK;
             }
+        } else if SFUtil::is_zlib(br) {
+            compression = CompressionType::Zlib;
         }
 
-        return Ok(Vec::new());
+        Ok(compression)
     }
     
     fn decompress_dcp_edge(br: &mut BinaryReader) -> Result<Vec<u8>, Error> {
-        println!("decompress_dcp_edge");
         br.assert_ascii(&["DCP\0"])?;
         br.assert_ascii(&["EDGE"])?;
         br.assert_i32(&[0x20]);
@@ -150,61 +179,16 @@ impl DCX {
         let chunk_count = br.read_i32();
         br.assert_i32(&[0x100000]);
 
-        if egdt_size != 0x20 + chunk_count * 0x10 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Unexpected EgdT size in EDGE DCX.",
-            ));
-        }
-
-        // Create a vector to store the decompressed data with the size of uncompressed_size
-        let decompressed = vec![0u8; uncompressed_size as usize];
-
-        // Create a cursor to write the decompressed data
-        let mut dcmp_stream: Cursor<Vec<u8>> = Cursor::new(decompressed);
+        let chunk_count = DCX::edge_chunk_count(chunk_count, egdt_size, 0x20, uncompressed_size as u32 as u64)?;
 
-        // Loop through each data chunk
-        for _ in 0..chunk_count {
-            // Verify that the next 4 bytes are all zeros
-            br.assert_i32(&[0]);
-
-            // Read the offset (4 bytes) from the binary reader
-            let offset = br.read_i32();
-
-            // Read the size (4 bytes) from the binary reader
-            let size = br.read_i32();
-
-            // Check if the chunk is compressed (next 4 bytes should be 1 if compressed)
-            let compressed = br.assert_i32(&[0, 1]) == 1;
-
-            // Get the chunk data based on the offset and size
-            let chunk = br.get_bytes(data_start + offset as usize, size as usize);
-
-            // If the chunk is compressed, decompress it
-            if compressed {
-                // Create a decompressor for zlib (flate2) with "raw" format
-                let mut decompresser = flate2::Decompress::new(false);
-
-                // Create a temporary vector to store the decompressed data
-                let mut temp_stream: Vec<u8> = Vec::new();
-
-                // Decompress the chunk data and store it in temp_stream
-                decompresser.decompress_vec(&chunk, &mut temp_stream, flate2::FlushDecompress::None)?;
-
-                // Write the decompressed data to the dcmp_stream
-                dcmp_stream.write_all(&temp_stream)?;
-            } else {
-                // If the chunk is not compressed, write it directly to the dcmp_stream
-                dcmp_stream.write_all(&chunk)?;
-            }
-        }
+        // Read the chunk table, offsets are relative to the start of the data
+        let chunks = DCX::read_edge_chunks(br, chunk_count, data_start);
 
         // Return the decompressed data as a result of the function
-        Ok(dcmp_stream.into_inner())
+        DCX::decode_edge_chunks(&br.memory, &chunks, uncompressed_size as usize)
     }
 
     fn decompress_dcp_dflt(br: &mut BinaryReader) -> Result<Vec<u8>, Error> {
-        println!("decompress_dcp_dflt");
         br.assert_ascii(&["DCP\0"])?;
         br.assert_ascii(&["DFLT"])?;
         br.assert_i32(&[0x20]);
@@ -228,7 +212,6 @@ impl DCX {
     }
 
     fn decompress_dcx_edge(br: &mut BinaryReader) -> Result<Vec<u8>, Error> {
-        println!("decompress_dcx_edge");
         br.assert_ascii(&["DCX\0"])?;
         br.assert_i32(&[0x10000]);
         br.assert_i32(&[0x18]);
@@ -263,64 +246,20 @@ impl DCX {
         let chunk_count = br.read_i32();
         br.assert_i32(&[0x100000]);
 
-        if unk1 != 0x50 + chunk_count * 0x10 {
+        let chunk_count = DCX::edge_chunk_count(chunk_count, egdt_size, 0x24, uncompressed_size as u32 as u64)?;
+        if unk1 as i64 != 0x50 + chunk_count as i64 * 0x10 {
             return Err(std::io::Error::new(std::io::ErrorKind::Other, "Unexpected unk1 value in EDGE DCX."));
         }
 
-        if egdt_size != 0x24 + chunk_count * 0x10 {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Unexpected EgdT size in EDGE DCX."));
-        }
-
-        // Create a vector to store the decompressed data with the size of uncompressed_size
-        let decompressed = vec![0u8; uncompressed_size as usize];
-
-        // Create a cursor to write the decompressed data
-        let mut dcmp_stream: Cursor<Vec<u8>> = Cursor::new(decompressed);
-
-        // Loop through each data chunk
-        for _ in 0..chunk_count {
-            // Verify that the next 4 bytes are all zeros
-            br.assert_i32(&[0]);
-
-            // Read the offset (4 bytes) from the binary reader
-            let offset = br.read_i32();
-
-            // Read the size (4 bytes) from the binary reader
-            let size = br.read_i32();
-
-            // Check if the chunk is compressed (next 4 bytes should be 1 if compressed)
-            let compressed = br.assert_i32(&[0, 1]) == 1;
-
-            // Get the chunk data based on the offset and size
-            let chunk = br.get_bytes(dca_start + dca_size as usize + offset as usize, size as usize);
-
-            // If the chunk is compressed, decompress it
-            if compressed {
-                // Create a decompressor for zlib (flate2) with "raw" format
-                let mut decompresser = flate2::Decompress::new(false);
-
-                // Create a temporary vector to store the decompressed data
-                let mut temp_stream: Vec<u8> = Vec::new();
-
-                // Decompress the chunk data and store it in temp_stream
-                decompresser.decompress_vec(&chunk, &mut temp_stream, flate2::FlushDecompress::None)?;
-
-                // Write the decompressed data to the dcmp_stream
-                dcmp_stream.write_all(&temp_stream)?;
-            } else {
-                // If the chunk is not compressed, write it directly to the dcmp_stream
-                dcmp_stream.write_all(&chunk)?;
-            }
-        }
+        // Read the chunk table, offsets are relative to the end of the DCA block
+        let chunks = DCX::read_edge_chunks(br, chunk_count, dca_start + dca_size as u32 as usize);
 
         // Return the decompressed data as a result of the function
-        Ok(dcmp_stream.into_inner())
-
+        DCX::decode_edge_chunks(&br.memory, &chunks, uncompressed_size as usize)
     }
 
     fn decompress_dcx_dflt(br: &mut BinaryReader, compression: &mut CompressionType) -> Result<Vec<u8>, Error> {
-        println!("decompress_dcx_dflt");
-        let unk04 = if *compression == CompressionType::DCX_DFLT_10000_24_9 || *compression == CompressionType::DCX_DFLT_11000_44_9 {  0x10000  } else { 0x11000 };
+        let unk04 = if *compression == CompressionType::DCX_DFLT_10000_24_9 || *compression == CompressionType::DCX_DFLT_10000_44_9 {  0x10000  } else { 0x11000 };
         let unk10 = if *compression == CompressionType::DCX_DFLT_10000_24_9 { 0x24 } else { 0x44 };
         let unk14 = if *compression == CompressionType::DCX_DFLT_10000_24_9 { 0x2C } else { 0x4C };
         let unk30 = if *compression == CompressionType::DCX_DFLT_11000_44_8 { 8 as u8 } else { 9 as u8 };
@@ -360,15 +299,8 @@ impl DCX {
 
     }
 
-    fn decompress_dcx_krak(br: &mut BinaryReader, compression_level: Option<u8>) -> Result<Vec<u8>, Error> {
-
-        // Default value for compression_level 6 if no value specified in the params
-        let compression_level_result = match compression_level {
-            Some(cl) => cl,
-            None => 6
-        };
+    fn decompress_dcx_krak(br: &mut BinaryReader) -> Result<Vec<u8>, Error> {
 
-        println!("decompress_dcx_krak");
         br.assert_ascii(&["DCX\0"])?;
         br.assert_i32(&[0x11000]);
         br.assert_i32(&[0x18]);
@@ -381,7 +313,8 @@ impl DCX {
         br.assert_ascii(&["DCP\0"])?;
         br.assert_ascii(&["KRAK"])?;
         br.assert_i32(&[0x20]);
-        br.assert_byte(&[compression_level_result]);
+        // Sekiro uses level 6, Elden Ring level 9
+        let compression_level = br.read_byte();
         br.assert_byte(&[0]);
         br.assert_byte(&[0]);
         br.assert_byte(&[0]);
@@ -394,8 +327,380 @@ impl DCX {
 
         
         let compressed: &[u8] = br.read_span_view(compressed_size as usize)?;
-        let mut compressor = Oodle::get_oodle_compressor(compression_level_result as i32)?;
+        let compressor = Oodle::get_oodle_compressor(compression_level as i32)?;
         return compressor.decompress(compressed, uncompressed_size as usize);
 
     }
+
+    // Checks the chunk count of an EgdT block against the block size and the decompressed size,
+    // before anything is sized from it. `header_size` is the size of the block without its table.
+    pub(crate) fn edge_chunk_count(chunk_count: i32, egdt_size: i32, header_size: i64, uncompressed_size: u64) -> Result<usize, Error> {
+        if chunk_count < 0 || chunk_count as u64 != uncompressed_size.div_ceil(EDGE_CHUNK_SIZE as u64) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected chunk count in EDGE DCX: {}", chunk_count)));
+        }
+        if egdt_size as i64 != header_size + chunk_count as i64 * 0x10 {
+            return Err(Error::new(ErrorKind::InvalidData, "Unexpected EgdT size in EDGE DCX."));
+        }
+        Ok(chunk_count as usize)
+    }
+
+    pub(crate) fn read_edge_chunks(br: &mut BinaryReader, chunk_count: usize, data_start: usize) -> Vec<EdgeChunk> {
+        let mut chunks = Vec::with_capacity(chunk_count);
+
+        for _ in 0..chunk_count {
+            // Verify that the next 4 bytes are all zeros
+            br.assert_i32(&[0]);
+
+            let offset = br.read_i32() as u32 as usize;
+            let size = br.read_i32() as u32 as usize;
+
+            // Check if the chunk is compressed (next 4 bytes should be 1 if compressed)
+            let compressed = br.assert_i32(&[0, 1]) == 1;
+
+            chunks.push(EdgeChunk { offset: data_start + offset, size, compressed });
+        }
+
+        chunks
+    }
+
+    // Every chunk except the last one inflates to exactly EDGE_CHUNK_SIZE bytes, so each chunk
+    // can be decoded straight into its own slice of the output independently of the others.
+    fn decode_edge_chunks(memory: &[u8], chunks: &[EdgeChunk], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+        if uncompressed_size.div_ceil(EDGE_CHUNK_SIZE) != chunks.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "Unexpected chunk count in EDGE DCX."));
+        }
+        if let Some(chunk) = chunks.iter().find(|chunk| chunk.offset + chunk.size > memory.len()) {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("EDGE chunk at 0x{:X} runs past the end of the file.", chunk.offset),
+            ));
+        }
+
+        let mut decompressed = vec![0u8; uncompressed_size];
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            decompressed
+                .par_chunks_mut(EDGE_CHUNK_SIZE)
+                .zip(chunks.par_iter())
+                .try_for_each_init(
+                    || Decompress::new(false),
+                    |inflater, (output, chunk)| DCX::decode_edge_chunk(inflater, memory, chunk, output),
+                )?;
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            let mut inflater = Decompress::new(false);
+            for (output, chunk) in decompressed.chunks_mut(EDGE_CHUNK_SIZE).zip(chunks) {
+                DCX::decode_edge_chunk(&mut inflater, memory, chunk, output)?;
+            }
+        }
+
+        Ok(decompressed)
+    }
+
+    fn decode_edge_chunk(inflater: &mut Decompress, memory: &[u8], chunk: &EdgeChunk, output: &mut [u8]) -> Result<(), Error> {
+        let input = &memory[chunk.offset..chunk.offset + chunk.size];
+
+        // If the chunk is not compressed, copy it directly into place
+        if !chunk.compressed {
+            if input.len() != output.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "Unexpected uncompressed chunk size in EDGE DCX."));
+            }
+            output.copy_from_slice(input);
+            return Ok(());
+        }
+
+        // Each chunk is an independent raw deflate stream
+        inflater.reset(false);
+        inflater.decompress(input, output, FlushDecompress::Finish)?;
+
+        if inflater.total_out() as usize != output.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "Unexpected decompressed chunk size in EDGE DCX."));
+        }
+
+        Ok(())
+    }
+
+    // Reads the Oodle level byte of a DCX_KRAK header or the format byte of a bare zlib stream,
+    // 0 for anything else.
+    pub(crate) fn compression_level(br: &mut BinaryReader) -> u8 {
+        if br.len() >= 0x31 && &br.memory[..4] == b"DCX\0" && &br.memory[0x28..0x2C] == b"KRAK" {
+            return br.memory[0x30];
+        }
+        if SFUtil::is_zlib(br) {
+            return br.memory[1];
+        }
+        0
+    }
+
+    pub(crate) fn compress(data: &[u8], compression: CompressionType, compression_level: u8) -> Result<Vec<u8>, Error> {
+        let mut bw = BinaryWriter::new(true);
+
+        match compression {
+            CompressionType::DCP_DFLT => DCX::compress_dcp_dflt(data, &mut bw)?,
+            CompressionType::DCP_EDGE => DCX::compress_dcp_edge(data, &mut bw)?,
+            CompressionType::DCX_EDGE => DCX::compress_dcx_edge(data, &mut bw)?,
+            CompressionType::DCX_DFLT_10000_24_9
+            | CompressionType::DCX_DFLT_10000_44_9
+            | CompressionType::DCX_DFLT_11000_44_8
+            | CompressionType::DCX_DFLT_11000_44_9
+            | CompressionType::DCX_DFLT_11000_44_9_15 => DCX::compress_dcx_dflt(data, &mut bw, compression)?,
+            CompressionType::DCX_KRAK => DCX::compress_dcx_krak(data, &mut bw, compression_level)?,
+            _ => {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} is not a DCX format.", compression)));
+            }
+        }
+
+        Ok(bw.finish())
+    }
+
+    fn compress_dcp_dflt(data: &[u8], bw: &mut BinaryWriter) -> Result<(), Error> {
+        bw.write_ascii("DCP\0");
+        bw.write_ascii("DFLT");
+        bw.write_i32(0x20);
+        bw.write_i32(0x9000000);
+        bw.write_i32(0);
+        bw.write_i32(0);
+        bw.write_i32(0);
+        bw.write_i32(0x00010100);
+
+        let compressed = SFUtil::write_zlib(data, 0xDA)?;
+        bw.write_ascii("DCS\0");
+        bw.write_i32(data.len() as i32);
+        bw.write_i32(compressed.len() as i32);
+        bw.write_bytes(&compressed);
+
+        bw.write_ascii("DCA\0");
+        bw.write_i32(8);
+        Ok(())
+    }
+
+    fn compress_dcp_edge(data: &[u8], bw: &mut BinaryWriter) -> Result<(), Error> {
+        let chunks = DCX::encode_edge_chunks(data)?;
+
+        bw.write_ascii("DCP\0");
+        bw.write_ascii("EDGE");
+        bw.write_i32(0x20);
+        bw.write_i32(0x9000000);
+        bw.write_i32(0x10000);
+        bw.write_i32(0x0);
+        bw.write_i32(0x0);
+        bw.write_i32(0x00100100);
+
+        bw.write_ascii("DCS\0");
+        bw.write_i32(data.len() as i32);
+        bw.reserve_i32("CompressedSize");
+        bw.write_i32(0);
+
+        // The data comes first, offsets in the chunk table are relative to its start
+        let data_start = bw.position();
+        let offsets = DCX::write_edge_chunk_data(bw, &chunks);
+        bw.fill_i32("CompressedSize", (bw.position() - data_start) as i32);
+
+        let dca_start = bw.position();
+        bw.write_ascii("DCA\0");
+        bw.reserve_i32("DCASize");
+        bw.write_ascii("EgdT");
+        bw.write_i32(0x00010000);
+        bw.write_i32(0x20);
+        bw.write_i32(0x10);
+        bw.write_i32(0x10000);
+        bw.write_i32(0x20 + chunks.len() as i32 * 0x10);
+        bw.write_i32(chunks.len() as i32);
+        bw.write_i32(0x100000);
+        DCX::write_edge_chunk_table(bw, &chunks);
+        DCX::fill_edge_chunk_offsets(bw, &offsets);
+        bw.fill_i32("DCASize", (bw.position() - dca_start) as i32);
+        Ok(())
+    }
+
+    fn compress_dcx_edge(data: &[u8], bw: &mut BinaryWriter) -> Result<(), Error> {
+        let chunks = DCX::encode_edge_chunks(data)?;
+        let chunk_count = chunks.len() as i32;
+
+        bw.write_ascii("DCX\0");
+        bw.write_i32(0x10000);
+        bw.write_i32(0x18);
+        bw.write_i32(0x24);
+        bw.write_i32(0x24);
+        bw.write_i32(0x50 + chunk_count * 0x10);
+
+        bw.write_ascii("DCS\0");
+        bw.write_i32(data.len() as i32);
+        bw.reserve_i32("CompressedSize");
+
+        bw.write_ascii("DCP\0");
+        bw.write_ascii("EDGE");
+        bw.write_i32(0x20);
+        bw.write_i32(0x9000000);
+        bw.write_i32(0x10000);
+        bw.write_i32(0x0);
+        bw.write_i32(0x0);
+        bw.write_i32(0x00100100);
+
+        // The chunk table lives inside the DCA block, in front of the data
+        let dca_start = bw.position();
+        bw.write_ascii("DCA\0");
+        bw.reserve_i32("DCASize");
+        bw.write_ascii("EgdT");
+        bw.write_i32(0x00010100);
+        bw.write_i32(0x24);
+        bw.write_i32(0x10);
+        bw.write_i32(0x10000);
+        let trailing_uncompressed_size = match data.len() % EDGE_CHUNK_SIZE {
+            0 if !data.is_empty() => EDGE_CHUNK_SIZE,
+            remainder => remainder,
+        };
+        bw.write_i32(trailing_uncompressed_size as i32);
+        bw.write_i32(0x24 + chunk_count * 0x10);
+        bw.write_i32(chunk_count);
+        bw.write_i32(0x100000);
+
+        DCX::write_edge_chunk_table(bw, &chunks);
+        bw.fill_i32("DCASize", (bw.position() - dca_start) as i32);
+
+        let data_start = bw.position();
+        let offsets = DCX::write_edge_chunk_data(bw, &chunks);
+        DCX::fill_edge_chunk_offsets(bw, &offsets);
+        bw.fill_i32("CompressedSize", (bw.position() - data_start) as i32);
+        Ok(())
+    }
+
+    fn compress_dcx_dflt(data: &[u8], bw: &mut BinaryWriter, compression: CompressionType) -> Result<(), Error> {
+        let unk04 = if compression == CompressionType::DCX_DFLT_10000_24_9 || compression == CompressionType::DCX_DFLT_10000_44_9 {  0x10000  } else { 0x11000 };
+        let unk10 = if compression == CompressionType::DCX_DFLT_10000_24_9 { 0x24 } else { 0x44 };
+        let unk14 = if compression == CompressionType::DCX_DFLT_10000_24_9 { 0x2C } else { 0x4C };
+        let unk30 = if compression == CompressionType::DCX_DFLT_11000_44_8 { 8 } else { 9 };
+        let unk38 = if compression == CompressionType::DCX_DFLT_11000_44_9_15 { 15 } else { 0 };
+
+        let compressed = SFUtil::write_zlib(data, 0xDA)?;
+
+        bw.write_ascii("DCX\0");
+        bw.write_i32(unk04);
+        bw.write_i32(0x18);
+        bw.write_i32(0x24);
+        bw.write_i32(unk10);
+        bw.write_i32(unk14);
+
+        bw.write_ascii("DCS\0");
+        bw.write_i32(data.len() as i32);
+        bw.write_i32(compressed.len() as i32);
+
+        bw.write_ascii("DCP\0");
+        bw.write_ascii("DFLT");
+        bw.write_i32(0x20);
+        bw.write_bytes(&[unk30, 0, 0, 0]);
+        bw.write_i32(0x0);
+        bw.write_bytes(&[unk38, 0, 0, 0]);
+        bw.write_i32(0x0);
+        bw.write_i32(0x00010100);
+
+        bw.write_ascii("DCA\0");
+        bw.write_i32(8);
+        bw.write_bytes(&compressed);
+        Ok(())
+    }
+
+    fn compress_dcx_krak(data: &[u8], bw: &mut BinaryWriter, compression_level: u8) -> Result<(), Error> {
+        let level = oodle_level(compression_level)?;
+        let compressed = Oodle::get_native_compressor()?.compress(data, OodleLZ_Compressor::OodleLZ_Compressor_Kraken, level)?;
+
+        bw.write_ascii("DCX\0");
+        bw.write_i32(0x11000);
+        bw.write_i32(0x18);
+        bw.write_i32(0x24);
+        bw.write_i32(0x44);
+        bw.write_i32(0x4C);
+
+        bw.write_ascii("DCS\0");
+        bw.write_i32(data.len() as i32);
+        bw.write_i32(compressed.len() as i32);
+
+        bw.write_ascii("DCP\0");
+        bw.write_ascii("KRAK");
+        bw.write_i32(0x20);
+        bw.write_bytes(&[compression_level, 0, 0, 0]);
+        bw.write_i32(0);
+        bw.write_i32(0);
+        bw.write_i32(0);
+        bw.write_i32(0x10100);
+
+        bw.write_ascii("DCA\0");
+        bw.write_i32(8);
+        bw.write_bytes(&compressed);
+        bw.pad(0x10);
+        Ok(())
+    }
+
+    // Deflates every 64 KiB chunk of the input on its own.
+    fn encode_edge_chunks(data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let deflate = |chunk: &[u8]| -> Result<Vec<u8>, Error> {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(chunk)?;
+            encoder.finish()
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            data.par_chunks(EDGE_CHUNK_SIZE).map(deflate).collect()
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            data.chunks(EDGE_CHUNK_SIZE).map(deflate).collect()
+        }
+    }
+
+    // Writes the compressed chunks, each aligned to 0x10, and returns their offsets from the first one.
+    fn write_edge_chunk_data(bw: &mut BinaryWriter, chunks: &[Vec<u8>]) -> Vec<usize> {
+        let data_start = bw.position();
+        chunks
+            .iter()
+            .map(|chunk| {
+                let offset = bw.position() - data_start;
+                bw.write_bytes(chunk);
+                bw.pad(0x10);
+                offset
+            })
+            .collect()
+    }
+
+    fn write_edge_chunk_table(bw: &mut BinaryWriter, chunks: &[Vec<u8>]) {
+        for (i, chunk) in chunks.iter().enumerate() {
+            bw.write_i32(0);
+            bw.reserve_i32(&format!("ChunkOffset{}", i));
+            bw.write_i32(chunk.len() as i32);
+            bw.write_i32(1);
+        }
+    }
+
+    fn fill_edge_chunk_offsets(bw: &mut BinaryWriter, offsets: &[usize]) {
+        for (i, offset) in offsets.iter().enumerate() {
+            bw.fill_i32(&format!("ChunkOffset{}", i), *offset as i32);
+        }
+    }
+}
+
+// The DCX level byte is the Oodle compression level it was written with.
+fn oodle_level(compression_level: u8) -> Result<OodleLZ_CompressionLevel, Error> {
+    use OodleLZ_CompressionLevel::*;
+
+    Ok(match compression_level {
+        0 => OodleLZ_CompressionLevel_None,
+        1 => OodleLZ_CompressionLevel_SuperFast,
+        2 => OodleLZ_CompressionLevel_VeryFast,
+        3 => OodleLZ_CompressionLevel_Fast,
+        4 => OodleLZ_CompressionLevel_Normal,
+        5 => OodleLZ_CompressionLevel_Optimal1,
+        6 => OodleLZ_CompressionLevel_Optimal2,
+        7 => OodleLZ_CompressionLevel_Optimal3,
+        8 => OodleLZ_CompressionLevel_Optimal4,
+        9 => OodleLZ_CompressionLevel_Optimal5,
+        _ => return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown Oodle level {} in DCX_KRAK.", compression_level))),
+    })
 }
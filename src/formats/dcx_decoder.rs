@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Error, ErrorKind, Read, Take};
+
+use flate2::read::ZlibDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+
+use crate::formats::{CompressionType, DCX};
+use crate::formats::dcx::{EdgeChunk, EDGE_CHUNK_SIZE};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::oodle::Oodle;
+
+// Every DCX container shares the same 0x4C byte header up to and including the DCA size.
+const DCX_HEADER_SIZE: usize = 0x4C;
+// DCP containers are 0x20 bytes of DCP block followed by the DCS block.
+const DCP_HEADER_SIZE: usize = 0x20;
+
+/// Streaming DCX decompressor.
+///
+/// Wraps any reader positioned at the start of a DCX or DCP container and yields the
+/// decompressed payload through `Read`. DFLT is inflated as a single zlib stream and EDGE one
+/// chunk at a time, so memory use stays bounded regardless of the file size. KRAK has to be
+/// handed to Oodle in one piece and is therefore decompressed on construction.
+pub struct DcxDecoder<R: Read> {
+    compression: CompressionType,
+    uncompressed_size: u64,
+    body: Body<R>,
+}
+
+enum Body<R: Read> {
+    Deflate(ZlibDecoder<Take<R>>),
+    Edge(EdgeStream<R>),
+    BufferedEdge(EdgeStream<Cursor<Vec<u8>>>),
+    Buffered(Cursor<Vec<u8>>),
+}
+
+impl<R: Read> DcxDecoder<R> {
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        match &magic {
+            b"DCX\0" => DcxDecoder::new_dcx(reader, magic),
+            b"DCP\0" => DcxDecoder::new_dcp(reader, magic),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Not a DCX file.")),
+        }
+    }
+
+    /// The container variant detected from the header.
+    pub fn compression(&self) -> CompressionType {
+        self.compression
+    }
+
+    /// The decompressed size recorded in the header.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    fn new_dcx(mut reader: R, magic: [u8; 4]) -> Result<Self, Error> {
+        let mut br = read_header(&mut reader, magic, DCX_HEADER_SIZE)?;
+        let compression = DCX::detect(&mut br)?;
+
+        br.position = 0x18;
+        br.assert_ascii(&["DCS\0"])?;
+        let uncompressed_size = br.read_i32() as u32 as u64;
+        let compressed_size = br.read_i32() as u32 as u64;
+        br.assert_ascii(&["DCP\0"])?;
+        br.position = 0x30;
+        let compression_level = br.read_byte();
+        br.position = 0x44;
+        br.assert_ascii(&["DCA\0"])?;
+        let dca_size = br.read_i32() as u32 as usize;
+
+        let body = match compression {
+            CompressionType::DCX_DFLT_10000_24_9
+            | CompressionType::DCX_DFLT_10000_44_9
+            | CompressionType::DCX_DFLT_11000_44_8
+            | CompressionType::DCX_DFLT_11000_44_9
+            | CompressionType::DCX_DFLT_11000_44_9_15 => {
+                Body::Deflate(ZlibDecoder::new(reader.take(compressed_size)))
+            }
+            CompressionType::DCX_EDGE => {
+                // The chunk table lives inside the DCA block, in front of the data.
+                let mut egdt = read_header(&mut reader, [0; 0], 0x24)?;
+                egdt.assert_ascii(&["EgdT"])?;
+                egdt.assert_i32(&[0x00010100]);
+                egdt.assert_i32(&[0x24]);
+                egdt.assert_i32(&[0x10]);
+                egdt.assert_i32(&[0x10000]);
+                egdt.read_i32();
+                let egdt_size = egdt.read_i32();
+                let chunk_count = egdt.read_i32();
+                egdt.assert_i32(&[0x100000]);
+
+                let chunk_count = DCX::edge_chunk_count(chunk_count, egdt_size, 0x24, uncompressed_size)?;
+                let chunks = read_edge_chunks(&mut reader, chunk_count)?;
+
+                // Skip whatever padding remains between the table and the data.
+                let consumed = DCX_HEADER_SIZE + 0x24 + chunk_count * 0x10;
+                let data_start = 0x44 + dca_size;
+                if data_start < consumed {
+                    return Err(Error::new(ErrorKind::InvalidData, "Unexpected DCA size in EDGE DCX."));
+                }
+                skip(&mut reader, (data_start - consumed) as u64)?;
+
+                Body::Edge(EdgeStream::new(reader, chunks))
+            }
+            CompressionType::DCX_KRAK => {
+                let compressed = read_sized(&mut reader, compressed_size)?;
+
+                let compressor = Oodle::get_oodle_compressor(compression_level as i32)?;
+                let decompressed = compressor.decompress(&compressed, uncompressed_size as usize)?;
+                Body::Buffered(Cursor::new(decompressed))
+            }
+            _ => {
+                return Err(Error::new(ErrorKind::InvalidData, "Unknown DCX format."));
+            }
+        };
+
+        Ok(Self {
+            compression,
+            uncompressed_size,
+            body,
+        })
+    }
+
+    fn new_dcp(mut reader: R, magic: [u8; 4]) -> Result<Self, Error> {
+        let mut br = read_header(&mut reader, magic, DCP_HEADER_SIZE)?;
+        let compression = DCX::detect(&mut br)?;
+
+        match compression {
+            CompressionType::DCP_DFLT => {
+                let mut dcs = read_header(&mut reader, [0; 0], 0xC)?;
+                dcs.assert_ascii(&["DCS\0"])?;
+                let uncompressed_size = dcs.read_i32() as u32 as u64;
+                let compressed_size = dcs.read_i32() as u32 as u64;
+
+                Ok(Self {
+                    compression,
+                    uncompressed_size,
+                    body: Body::Deflate(ZlibDecoder::new(reader.take(compressed_size))),
+                })
+            }
+            CompressionType::DCP_EDGE => {
+                let mut dcs = read_header(&mut reader, [0; 0], 0x10)?;
+                dcs.assert_ascii(&["DCS\0"])?;
+                let uncompressed_size = dcs.read_i32() as u32 as u64;
+                let compressed_size = dcs.read_i32() as u32 as u64;
+                dcs.assert_i32(&[0]);
+
+                // DCP puts the chunk table after the data, so the compressed data has to be
+                // buffered before the table can be read. Output is still produced per chunk.
+                let data = read_sized(&mut reader, compressed_size)?;
+
+                let mut egdt = read_header(&mut reader, [0; 0], 0x28)?;
+                egdt.assert_ascii(&["DCA\0"])?;
+                egdt.read_i32();
+                egdt.assert_ascii(&["EgdT"])?;
+                egdt.assert_i32(&[0x00010000]);
+                egdt.assert_i32(&[0x20]);
+                egdt.assert_i32(&[0x10]);
+                egdt.assert_i32(&[0x10000]);
+                let egdt_size = egdt.read_i32();
+                let chunk_count = egdt.read_i32();
+                egdt.assert_i32(&[0x100000]);
+
+                let chunk_count = DCX::edge_chunk_count(chunk_count, egdt_size, 0x20, uncompressed_size)?;
+                let chunks = read_edge_chunks(&mut reader, chunk_count)?;
+
+                Ok(Self {
+                    compression,
+                    uncompressed_size,
+                    body: Body::BufferedEdge(EdgeStream::new(Cursor::new(data), chunks)),
+                })
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "Unknown DCX format.")),
+        }
+    }
+}
+
+impl<R: Read> Read for DcxDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            Body::Deflate(decoder) => decoder.read(buf),
+            Body::Edge(stream) => stream.read(buf),
+            Body::BufferedEdge(stream) => stream.read(buf),
+            Body::Buffered(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+// Decodes EDGE chunks lazily, keeping only a single chunk in memory at a time.
+struct EdgeStream<S: Read> {
+    source: S,
+    source_position: u64,
+    chunks: VecDeque<EdgeChunk>,
+    inflater: Decompress,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    output_position: usize,
+}
+
+impl<S: Read> EdgeStream<S> {
+    fn new(source: S, chunks: VecDeque<EdgeChunk>) -> Self {
+        Self {
+            source,
+            source_position: 0,
+            chunks,
+            inflater: Decompress::new(false),
+            input: Vec::new(),
+            output: Vec::with_capacity(EDGE_CHUNK_SIZE),
+            output_position: 0,
+        }
+    }
+
+    // Reads and decodes the next chunk into the output buffer.
+    // Returns false once every chunk has been consumed.
+    fn next_chunk(&mut self) -> Result<bool, Error> {
+        let chunk = match self.chunks.pop_front() {
+            Some(chunk) => chunk,
+            None => return Ok(false),
+        };
+
+        // Chunks are stored in order, but may be separated by padding.
+        let offset = chunk.offset as u64;
+        if offset < self.source_position {
+            return Err(Error::new(ErrorKind::InvalidData, "EDGE chunks are not stored in order."));
+        }
+        skip(&mut self.source, offset - self.source_position)?;
+
+        self.input.resize(chunk.size, 0);
+        self.source.read_exact(&mut self.input)?;
+        self.source_position = offset + chunk.size as u64;
+
+        self.output.clear();
+        self.output_position = 0;
+
+        if !chunk.compressed {
+            self.output.extend_from_slice(&self.input);
+            return Ok(true);
+        }
+
+        // Reuse the same inflater for every chunk, each one is an independent raw deflate stream.
+        self.inflater.reset(false);
+        loop {
+            if self.output.len() == self.output.capacity() {
+                self.output.reserve(EDGE_CHUNK_SIZE);
+            }
+
+            let total_in = self.inflater.total_in() as usize;
+            let total_out = self.inflater.total_out();
+            let status = self.inflater.decompress_vec(&self.input[total_in..], &mut self.output, FlushDecompress::Finish)?;
+
+            let made_progress = self.inflater.total_in() as usize != total_in || self.inflater.total_out() != total_out;
+            if status == Status::StreamEnd || (!made_progress && self.output.len() < self.output.capacity()) {
+                break;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<S: Read> Read for EdgeStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_position == self.output.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.output[self.output_position..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.output_position += count;
+        Ok(count)
+    }
+}
+
+// Reads a fixed size header block into a big endian reader, prefixed with any bytes already consumed.
+fn read_header<R: Read, const N: usize>(reader: &mut R, prefix: [u8; N], size: usize) -> Result<BinaryReader, Error> {
+    let mut bytes = vec![0u8; size];
+    bytes[..N].copy_from_slice(&prefix);
+    reader.read_exact(&mut bytes[N..])?;
+    Ok(BinaryReader::new(true, bytes))
+}
+
+fn read_edge_chunks<R: Read>(reader: &mut R, chunk_count: usize) -> Result<VecDeque<EdgeChunk>, Error> {
+    let mut br = read_header(reader, [0; 0], chunk_count * 0x10)?;
+    Ok(DCX::read_edge_chunks(&mut br, chunk_count, 0).into())
+}
+
+// Reads `size` bytes, growing the buffer as data arrives instead of trusting the size up front.
+fn read_sized<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    reader.take(size).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < size {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Reached end of stream before the end of the compressed data."));
+    }
+    Ok(bytes)
+}
+
+fn skip<R: Read>(reader: &mut R, count: u64) -> Result<(), Error> {
+    let skipped = io::copy(&mut reader.take(count), &mut io::sink())?;
+    if skipped != count {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Reached end of stream while skipping."));
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::io::{Error, Read, Seek};
+use sha2::{Digest, Sha256};
+use crate::formats::bhd5::FileHeader;
+use crate::formats::{AnyBinder, ArchiveDictionary, Binder, BinderFile, CompressionType, FileFlags, BDT, BHD5};
+use crate::util::path_hash::normalize_path;
+use crate::util::Compressed;
+
+/// Settings for `diff_binders` and `diff_archives`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiffOptions {
+    /// Whether modified entries that are binders themselves, compressed or not, are compared entry by entry too.
+    pub recursive: bool,
+}
+
+/// An entry of either side of a diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// The entry name, or its ID or hash when it has none, after the names of the binders it is nested in.
+    pub path: String,
+    /// The ID of a binder entry.
+    pub id: Option<i32>,
+    /// The path hash of an archive entry.
+    pub file_name_hash: Option<u64>,
+    /// The flags of a binder entry.
+    pub flags: Option<FileFlags>,
+    /// The compression of a binder entry.
+    pub compression: Option<CompressionType>,
+    /// Size of the contents, decompressed for binder entries and decrypted for archive entries.
+    pub size: u64,
+    /// SHA-256 of the contents.
+    pub sha256: [u8; 32],
+}
+
+/// A difference between two versions of a binder or archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryChange {
+    Added(EntryInfo),
+    Removed(EntryInfo),
+    /// The entry exists in both versions but its contents, ID, flags or compression differ.
+    Modified { old: EntryInfo, new: EntryInfo },
+}
+
+impl EntryChange {
+    pub fn path(&self) -> &str {
+        match self {
+            EntryChange::Added(entry) | EntryChange::Removed(entry) => &entry.path,
+            EntryChange::Modified { new, .. } => &new.path,
+        }
+    }
+
+    pub fn content_changed(&self) -> bool {
+        match self {
+            EntryChange::Modified { old, new } => old.size != new.size || old.sha256 != new.sha256,
+            _ => true,
+        }
+    }
+
+    /// Whether the flags or compression of an entry found in both versions differ.
+    pub fn flags_changed(&self) -> bool {
+        match self {
+            EntryChange::Modified { old, new } => old.flags != new.flags || old.compression != new.compression,
+            _ => false,
+        }
+    }
+}
+
+/// Compares two binders of any kind.
+///
+/// Entries are matched by name, ignoring case and separators, and by ID when they have no name.
+/// Removed and modified entries are listed in the order of `old`, followed by added entries in the
+/// order of `new`.
+pub fn diff_binders<A: Binder, B: Binder>(old: &A, new: &B, options: &DiffOptions) -> Vec<EntryChange> {
+    let mut changes = Vec::new();
+    diff_binder_files(old.files(), new.files(), "", options, &mut changes);
+    changes
+}
+
+/// Compares two dvdbnd archives, matching their files by path hash.
+///
+/// The contents of every file found in both archives are read to compare them. Files the
+/// dictionary has no name for are reported by their hash.
+pub fn diff_archives<R: Read + Seek, S: Read + Seek>(
+    old: &BHD5,
+    old_bdt: &mut BDT<R>,
+    new: &BHD5,
+    new_bdt: &mut BDT<S>,
+    dictionary: Option<&ArchiveDictionary>,
+    options: &DiffOptions,
+) -> Result<Vec<EntryChange>, Error> {
+    let new_headers: HashMap<u64, &FileHeader> = new.files().map(|header| (header.file_name_hash, header)).collect();
+    let mut changes = Vec::new();
+
+    for old_header in old.files() {
+        let path = archive_path(old_header.file_name_hash, dictionary);
+        let old_data = old_bdt.read_file(old_header)?;
+        let old_entry = archive_entry(&path, old_header, &old_data);
+        let Some(new_header) = new_headers.get(&old_header.file_name_hash) else {
+            changes.push(EntryChange::Removed(old_entry));
+            continue;
+        };
+
+        let new_data = new_bdt.read_file(new_header)?;
+        let new_entry = archive_entry(&path, new_header, &new_data);
+        if old_entry != new_entry {
+            changes.push(EntryChange::Modified { old: old_entry, new: new_entry });
+            if options.recursive {
+                diff_nested(&old_data, &new_data, &path, options, &mut changes);
+            }
+        }
+    }
+
+    for new_header in new.files() {
+        if old.file(new_header.file_name_hash).is_none() {
+            let path = archive_path(new_header.file_name_hash, dictionary);
+            let data = new_bdt.read_file(new_header)?;
+            changes.push(EntryChange::Added(archive_entry(&path, new_header, &data)));
+        }
+    }
+
+    Ok(changes)
+}
+
+fn diff_binder_files(old: &[BinderFile], new: &[BinderFile], parent: &str, options: &DiffOptions, changes: &mut Vec<EntryChange>) {
+    let old_keys = binder_keys(old);
+    let new_keys = binder_keys(new);
+    let new_indices: HashMap<&str, usize> = new_keys.iter().enumerate().map(|(i, key)| (key.as_str(), i)).collect();
+    let old_indices: HashMap<&str, usize> = old_keys.iter().enumerate().map(|(i, key)| (key.as_str(), i)).collect();
+
+    for (old_file, key) in old.iter().zip(&old_keys) {
+        let old_entry = binder_entry(parent, old_file);
+        let Some(&i) = new_indices.get(key.as_str()) else {
+            changes.push(EntryChange::Removed(old_entry));
+            continue;
+        };
+
+        let new_file = &new[i];
+        let new_entry = binder_entry(parent, new_file);
+        if old_entry != new_entry {
+            let path = new_entry.path.clone();
+            changes.push(EntryChange::Modified { old: old_entry, new: new_entry });
+            if options.recursive {
+                diff_nested(&old_file.data, &new_file.data, &path, options, changes);
+            }
+        }
+    }
+
+    for (new_file, key) in new.iter().zip(&new_keys) {
+        if !old_indices.contains_key(key.as_str()) {
+            changes.push(EntryChange::Added(binder_entry(parent, new_file)));
+        }
+    }
+}
+
+// Compares the entries of two versions of a file if both are binders, and does nothing otherwise.
+fn diff_nested(old: &[u8], new: &[u8], path: &str, options: &DiffOptions, changes: &mut Vec<EntryChange>) {
+    if let (Some(old_files), Some(new_files)) = (nested_files(old), nested_files(new)) {
+        diff_binder_files(&old_files, &new_files, path, options, changes);
+    }
+}
+
+fn nested_files(data: &[u8]) -> Option<Vec<BinderFile>> {
+    Compressed::<AnyBinder>::from_bytes(data.to_vec()).ok().map(|binder| binder.into_inner().into_files())
+}
+
+// Keys entries by name or ID. Repeated keys are numbered so the nth duplicate matches the nth duplicate.
+fn binder_keys(files: &[BinderFile]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    files
+        .iter()
+        .map(|file| {
+            let key = match file.name.as_deref().filter(|name| !name.is_empty()) {
+                Some(name) => normalize_path(name),
+                None => format!("#{}", file.id),
+            };
+            let count = seen.entry(key.clone()).or_default();
+            *count += 1;
+            if *count == 1 { key } else { format!("{}#{}", key, count) }
+        })
+        .collect()
+}
+
+fn binder_entry(parent: &str, file: &BinderFile) -> EntryInfo {
+    let name = match file.name.as_deref().filter(|name| !name.is_empty()) {
+        Some(name) => name.to_string(),
+        None => file.id.to_string(),
+    };
+    EntryInfo {
+        path: if parent.is_empty() { name } else { format!("{}/{}", parent, name) },
+        id: Some(file.id),
+        file_name_hash: None,
+        flags: Some(file.flags),
+        compression: Some(file.compression),
+        size: file.data.len() as u64,
+        sha256: Sha256::digest(&file.data).into(),
+    }
+}
+
+fn archive_entry(path: &str, header: &FileHeader, data: &[u8]) -> EntryInfo {
+    EntryInfo {
+        path: path.to_string(),
+        id: None,
+        file_name_hash: Some(header.file_name_hash),
+        flags: None,
+        compression: None,
+        size: data.len() as u64,
+        sha256: Sha256::digest(data).into(),
+    }
+}
+
+fn archive_path(file_name_hash: u64, dictionary: Option<&ArchiveDictionary>) -> String {
+    match dictionary.and_then(|dictionary| dictionary.name(file_name_hash)) {
+        Some(name) => name.to_string(),
+        None => format!("{:016x}", file_name_hash),
+    }
+}
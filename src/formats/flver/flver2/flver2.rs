@@ -1,3 +1,4 @@
+use std::io::Error;
 use crate::util::binary_reader::BinaryReader;
 use crate::util::SoulsFile;
 
@@ -9,8 +10,9 @@ impl SoulsFile for FLVER2 {
         todo!()
     }
 
-    fn specific_read(&self, br: &mut BinaryReader) {
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error> {
         FLVER2::default();
+        Ok(())
     }
 }
 
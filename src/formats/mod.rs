@@ -1,5 +1,27 @@
+pub mod binder;
+pub mod bhd5;
 mod flver;
-mod dcx;
+pub(crate) mod dcx;
+mod dcx_decoder;
+pub mod diff;
+pub mod tpf;
 
-pub(crate) use dcx::{DCX, CompressionType};
+pub(crate) use dcx::DCX;
+pub use binder::{AnyBinder, Binder, BinderFile, FileFlags, Format};
+pub use binder::bnd3::BND3;
+pub use binder::bnd4::BND4;
+pub use binder::bxf3::BXF3;
+pub use binder::bxf4::BXF4;
+pub use binder::lazy::{LazyBinder, LazyEntry};
+pub use binder::layout::{repack_binder, unpack_binder, unpack_split_binder, BinderLayout, PackedBinder};
+pub use bhd5::{Game, BHD5};
+pub use bhd5::bdt::BDT;
+pub use bhd5::dictionary::ArchiveDictionary;
+pub use bhd5::lazy::{ArchiveEntry, LazyArchive};
+pub use bhd5::pack::ArchiveBuilder;
+pub use bhd5::unpack::{unpack_game, UnpackOptions};
+pub use dcx::CompressionType;
+pub use dcx_decoder::DcxDecoder;
+pub use diff::{diff_archives, diff_binders, DiffOptions, EntryChange, EntryInfo};
 pub use flver::flver2::flver2::FLVER2;
+pub use tpf::TPF;
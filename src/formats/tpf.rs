@@ -0,0 +1,165 @@
+use std::io::{Error, ErrorKind};
+use crate::formats::{CompressionType, DCX};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::SoulsFile;
+
+/// The console a texture container was built for, which decides its byte order and texture headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TPFPlatform {
+    #[default]
+    PC,
+    Xbox360,
+    PS3,
+    PS4,
+    XboxOne,
+}
+
+/// A container of textures, stored as DDS files on PC.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TPF {
+    pub platform: TPFPlatform,
+    /// 0, 1, 2 or 3. Changes the texture headers of PS3 files.
+    pub flag2: u8,
+    /// 1 for UTF-16 names, 0 or 2 for Shift-JIS names.
+    pub encoding: u8,
+    pub textures: Vec<TPFTexture>,
+}
+
+/// A single texture of a TPF.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TPFTexture {
+    pub name: String,
+    /// The texture format code, which maps to a DXGI format.
+    pub format: u8,
+    /// 0 for 2D textures, 1 for cubemaps and 2 for volume textures.
+    pub texture_type: u8,
+    pub mipmaps: u8,
+    /// 0 to 3. A value of 2 or 3 means the data is DCX compressed.
+    pub flags1: u8,
+    /// The texture data, decompressed if `flags1` says it is compressed.
+    pub data: Vec<u8>,
+    /// Image properties stored by console platforms in place of a DDS header.
+    pub header: Option<TexHeader>,
+    pub float_struct: Option<FloatStruct>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TexHeader {
+    pub width: i16,
+    pub height: i16,
+    pub unk1: i32,
+    pub unk2: i32,
+    pub texture_count: i32,
+    pub dxgi_format: i32,
+}
+
+/// Extra values attached to some textures, of unknown purpose.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FloatStruct {
+    pub unk00: i32,
+    pub values: Vec<f32>,
+}
+
+impl SoulsFile for TPF {
+    fn is(&self, br: &mut BinaryReader) -> bool {
+        br.len() >= 4 && &br.memory[..4] == b"TPF\0"
+    }
+
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error> {
+        br.position = 0;
+        br.big_endian = false;
+        br.assert_ascii(&["TPF\0"])?;
+        self.platform = match br.get_byte(0xC) {
+            0 => TPFPlatform::PC,
+            1 => TPFPlatform::Xbox360,
+            2 => TPFPlatform::PS3,
+            4 => TPFPlatform::PS4,
+            5 => TPFPlatform::XboxOne,
+            platform => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown TPF platform: {}", platform))),
+        };
+        br.big_endian = matches!(self.platform, TPFPlatform::Xbox360 | TPFPlatform::PS3);
+
+        br.read_i32(); // Data size
+        let file_count = br.read_i32();
+        br.skip(1); // Platform
+        self.flag2 = br.assert_byte(&[0, 1, 2, 3]);
+        self.encoding = br.assert_byte(&[0, 1, 2]);
+        br.assert_byte(&[0]);
+
+        if file_count < 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid TPF file count: {}", file_count)));
+        }
+
+        self.textures = Vec::with_capacity(file_count.min(0x10000) as usize);
+        for _ in 0..file_count {
+            let texture = TPFTexture::read(br, self.platform, self.flag2, self.encoding)?;
+            self.textures.push(texture);
+        }
+
+        Ok(())
+    }
+}
+
+impl TPFTexture {
+    fn read(br: &mut BinaryReader, platform: TPFPlatform, flag2: u8, encoding: u8) -> Result<TPFTexture, Error> {
+        let mut texture = TPFTexture::default();
+        let file_offset = br.read_u32() as usize;
+        let file_size = br.read_i32();
+        texture.format = br.read_byte();
+        texture.texture_type = br.assert_byte(&[0, 1, 2]);
+        texture.mipmaps = br.read_byte();
+        texture.flags1 = br.assert_byte(&[0, 1, 2, 3]);
+
+        if platform != TPFPlatform::PC {
+            let mut header = TexHeader {
+                width: br.read_i16(),
+                height: br.read_i16(),
+                ..TexHeader::default()
+            };
+            match platform {
+                TPFPlatform::Xbox360 => {
+                    br.assert_i32(&[0]);
+                }
+                TPFPlatform::PS3 => {
+                    header.unk1 = br.read_i32();
+                    if flag2 != 0 {
+                        header.unk2 = br.assert_i32(&[0, 0x69E0, 0xAAE4]);
+                    }
+                }
+                _ => {
+                    header.texture_count = br.assert_i32(&[1, 6]);
+                    header.unk2 = br.assert_i32(&[0xD]);
+                }
+            }
+            texture.header = Some(header);
+        }
+
+        let name_offset = br.read_u32() as usize;
+        let has_float_struct = br.assert_i32(&[0, 1]) == 1;
+        if let Some(header) = texture.header.as_mut().filter(|_| matches!(platform, TPFPlatform::PS4 | TPFPlatform::XboxOne)) {
+            header.dxgi_format = br.read_i32();
+        }
+        if has_float_struct {
+            let unk00 = br.read_i32();
+            let length = br.read_i32().max(0) as usize;
+            let values = (0..length / 4).map(|_| f32::from_bits(br.read_u32())).collect();
+            texture.float_struct = Some(FloatStruct { unk00, values });
+        }
+
+        texture.name = if encoding == 1 { br.get_utf16(name_offset)? } else { br.get_shift_jis(name_offset)? };
+
+        let data = usize::try_from(file_size)
+            .ok()
+            .and_then(|size| br.memory.get(file_offset..file_offset.checked_add(size)?))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("TPF texture {:?} lies outside of the file.", texture.name)))?
+            .to_vec();
+        texture.data = if texture.flags1 == 2 || texture.flags1 == 3 {
+            let mut compression = CompressionType::Unknown;
+            DCX::decompress(&mut BinaryReader::new(false, data), &mut compression)?
+        } else {
+            data
+        };
+
+        Ok(texture)
+    }
+}
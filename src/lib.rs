@@ -1,6 +1,7 @@
 pub mod util;
 pub mod formats;
+pub mod vfs;
 
 pub mod prelude {
-    pub use super::util::{SoulsFile, MountedSoulsFile};
+    pub use super::util::{Compressed, SoulsFile, MountedSoulsFile};
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use encoding_rs::SHIFT_JIS;
 use std::io::{self, Error, ErrorKind};
 use std::str;
 use std::string::FromUtf8Error;
@@ -171,6 +172,18 @@ impl BinaryReader {
     }
 
 
+    //************ Boolean **************/ 
+
+    pub(crate) fn read_boolean(&mut self) -> bool {
+        self.assert_byte(&[0, 1]) == 1
+    }
+
+    pub(crate) fn get_boolean(&mut self, offset: usize) -> bool {
+        let read_value = |reader: &mut BinaryReader| reader.read_boolean();
+        self.get_value(offset, read_value)
+    }
+
+
     //************ String **************/ 
     pub(crate) fn assert_ascii(&mut self, values: &[&str]) -> Result<String, Error> {
         // Read an ASCII string from the binary reader.
@@ -219,6 +232,66 @@ impl BinaryReader {
         Ok(result)
     }
 
+    // Reads a Shift-JIS string of fixed length, cut off at the first null byte.
+    pub(crate) fn read_fixstr(&mut self, length: usize) -> Result<String, Error> {
+        let bytes = self.read_bytes(length);
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(length);
+        decode_shift_jis(&bytes[..end])
+    }
+
+    // Reads a null-terminated Shift-JIS string.
+    pub(crate) fn read_shift_jis(&mut self) -> Result<String, Error> {
+        let bytes = self.read_terminated(1)?;
+        decode_shift_jis(&bytes)
+    }
+
+    pub(crate) fn get_shift_jis(&mut self, offset: usize) -> Result<String, Error> {
+        self.step_in(offset);
+        let result = self.read_shift_jis();
+        self.step_out()?;
+        result
+    }
+
+    // Reads a null-terminated UTF-16 string in the reader's endianness.
+    pub(crate) fn read_utf16(&mut self) -> Result<String, Error> {
+        let bytes = self.read_terminated(2)?;
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|unit| if self.big_endian { u16::from_be_bytes([unit[0], unit[1]]) } else { u16::from_le_bytes([unit[0], unit[1]]) })
+            .collect();
+        String::from_utf16(&units).map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid UTF-16 string."))
+    }
+
+    pub(crate) fn get_utf16(&mut self, offset: usize) -> Result<String, Error> {
+        self.step_in(offset);
+        let result = self.read_utf16();
+        self.step_out()?;
+        result
+    }
+
+    // Reads up to a terminator of `width` zero bytes aligned to `width`, consuming the terminator.
+    fn read_terminated(&mut self, width: usize) -> Result<Vec<u8>, Error> {
+        let start = self.position;
+        let remaining = self.memory.get(start..).unwrap_or_default();
+        let length = remaining
+            .chunks_exact(width)
+            .position(|unit| unit.iter().all(|&b| b == 0))
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Unterminated string."))?
+            * width;
+        self.position = start + length + width;
+        Ok(self.memory[start..start + length].to_vec())
+    }
+
+
+    //************ i16 **************/ 
+    pub(crate) fn read_i16(&mut self) -> i16 {
+        if self.big_endian {
+            let i = self.read::<i16>();
+            return i.to_be();
+        }
+        self.read::<i16>()
+    }
+
 
     //************ i32 **************/ 
     pub(crate) fn assert_i32(&mut self, options: &[i32]) -> i32 {
@@ -239,4 +312,46 @@ impl BinaryReader {
         self.get_value(offset, read_value)
     }
 
-}
\ No newline at end of file
+
+    //************ u32 **************/ 
+    pub(crate) fn read_u32(&mut self) -> u32 {
+        if self.big_endian {
+            let i = self.read::<u32>();
+            return i.to_be();
+        }
+        self.read::<u32>()
+    }
+
+
+    //************ i64 **************/ 
+    pub(crate) fn assert_i64(&mut self, options: &[i64]) -> i64 {
+        let value = self.read_i64();
+        self.assert_value(value, options)
+    }
+
+    pub(crate) fn read_i64(&mut self) -> i64 {
+        if self.big_endian {
+            let i = self.read::<i64>();
+            return i.to_be();
+        }
+        self.read::<i64>()
+    }
+
+
+    //************ u64 **************/ 
+    pub(crate) fn read_u64(&mut self) -> u64 {
+        if self.big_endian {
+            let i = self.read::<u64>();
+            return i.to_be();
+        }
+        self.read::<u64>()
+    }
+
+}
+
+fn decode_shift_jis(bytes: &[u8]) -> Result<String, Error> {
+    SHIFT_JIS
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|s| s.into_owned())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid Shift-JIS string."))
+}
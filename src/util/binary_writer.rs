@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use encoding_rs::SHIFT_JIS;
+
+pub struct BinaryWriter {
+    pub(crate) big_endian: bool,
+    pub(crate) memory: Vec<u8>,
+    // Offsets of values that are written as placeholders and filled in later.
+    reservations: HashMap<String, usize>,
+}
+
+impl BinaryWriter {
+    pub(crate) fn new(big_endian: bool) -> BinaryWriter {
+        BinaryWriter {
+            big_endian,
+            memory: Vec::new(),
+            reservations: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.memory.len()
+    }
+
+    // Pads with zeros until the position is a multiple of `align`.
+    pub(crate) fn pad(&mut self, align: usize) {
+        while !self.memory.len().is_multiple_of(align) {
+            self.memory.push(0);
+        }
+    }
+
+    // Returns the written bytes. Every reservation must have been filled.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        if let Some(name) = self.reservations.keys().next() {
+            panic!("Reservation was never filled: {}", name);
+        }
+        self.memory
+    }
+
+    fn reserve(&mut self, name: &str, size: usize) {
+        if self.reservations.insert(name.to_string(), self.memory.len()).is_some() {
+            panic!("Reservation already exists: {}", name);
+        }
+        self.memory.extend(std::iter::repeat_n(0xFE, size));
+    }
+
+    fn fill(&mut self, name: &str, bytes: &[u8]) {
+        let offset = self
+            .reservations
+            .remove(name)
+            .unwrap_or_else(|| panic!("Reservation not found: {}", name));
+        self.memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+
+    //************ Byte **************/
+
+    pub(crate) fn write_byte(&mut self, value: u8) {
+        self.memory.push(value);
+    }
+
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.memory.extend_from_slice(bytes);
+    }
+
+
+    //************ Boolean **************/
+
+    pub(crate) fn write_boolean(&mut self, value: bool) {
+        self.memory.push(value as u8);
+    }
+
+
+    //************ String **************/
+
+    pub(crate) fn write_ascii(&mut self, value: &str) {
+        self.memory.extend_from_slice(value.as_bytes());
+    }
+
+    // Writes a Shift-JIS string padded with nulls to `length` bytes.
+    pub(crate) fn write_fixstr(&mut self, value: &str, length: usize) -> Result<(), Error> {
+        let bytes = encode_shift_jis(value)?;
+        if bytes.len() > length {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("String is longer than {} bytes: {}", length, value)));
+        }
+        self.memory.extend_from_slice(&bytes);
+        self.memory.extend(std::iter::repeat_n(0, length - bytes.len()));
+        Ok(())
+    }
+
+    // Writes a null-terminated Shift-JIS string.
+    pub(crate) fn write_shift_jis(&mut self, value: &str) -> Result<(), Error> {
+        let bytes = encode_shift_jis(value)?;
+        self.memory.extend_from_slice(&bytes);
+        self.memory.push(0);
+        Ok(())
+    }
+
+    // Writes a null-terminated UTF-16 string in the writer's endianness.
+    pub(crate) fn write_utf16(&mut self, value: &str) {
+        for unit in value.encode_utf16().chain(std::iter::once(0)) {
+            let bytes = if self.big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() };
+            self.memory.extend_from_slice(&bytes);
+        }
+    }
+
+
+    //************ i32 **************/
+
+    pub(crate) fn write_i32(&mut self, value: i32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.memory.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn reserve_i32(&mut self, name: &str) {
+        self.reserve(name, 4);
+    }
+
+    pub(crate) fn fill_i32(&mut self, name: &str, value: i32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.fill(name, &bytes);
+    }
+
+
+    //************ u32 **************/
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.memory.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn reserve_u32(&mut self, name: &str) {
+        self.reserve(name, 4);
+    }
+
+    pub(crate) fn fill_u32(&mut self, name: &str, value: u32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.fill(name, &bytes);
+    }
+
+
+    //************ i64 **************/
+
+    pub(crate) fn write_i64(&mut self, value: i64) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.memory.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn reserve_i64(&mut self, name: &str) {
+        self.reserve(name, 8);
+    }
+
+    pub(crate) fn fill_i64(&mut self, name: &str, value: i64) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.fill(name, &bytes);
+    }
+}
+
+fn encode_shift_jis(value: &str) -> Result<Vec<u8>, Error> {
+    let (bytes, _, unmappable) = SHIFT_JIS.encode(value);
+    if unmappable {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("String cannot be encoded as Shift-JIS: {}", value)));
+    }
+    Ok(bytes.into_owned())
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::io::Error;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use crate::formats::{CompressionType, DCX};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
+use crate::util::sf_util::SFUtil;
+use crate::util::souls_file::SoulsFile;
+
+/// A file together with the compression it was read with.
+///
+/// Writing applies the same DCX variant and Oodle level again, so a modified file is stored
+/// the way the game expects it for that path.
+pub struct Compressed<T> {
+    pub file: T,
+    pub compression: CompressionType,
+    /// The Oodle level byte of a DCX_KRAK header or the format byte of a bare zlib stream,
+    /// ignored by every other compression type.
+    pub compression_level: u8,
+}
+
+impl<T: SoulsFile> Compressed<T> {
+    pub fn new(file: T, compression: CompressionType, compression_level: u8) -> Self {
+        Self {
+            file,
+            compression,
+            compression_level,
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(file_path: P) -> Result<Self, Error> {
+        Self::from_bytes(fs::read(file_path)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        let (mut br, compression, compression_level) = decompress(bytes)?;
+        let mut file = T::default();
+        file.specific_read(&mut br)?;
+
+        Ok(Self::new(file, compression, compression_level))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, file_path: P) -> Result<(), Error> {
+        fs::write(file_path, self.to_bytes()?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bw = BinaryWriter::new(false);
+        self.file.specific_write(&mut bw)?;
+        SFUtil::compress(&bw.finish(), self.compression, self.compression_level)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.file
+    }
+}
+
+// Decompresses `bytes` if needed, returning the payload with the compression and level it was stored with.
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<(BinaryReader, CompressionType, u8), Error> {
+    let mut br = BinaryReader::new(false, bytes);
+    let mut compression = CompressionType::Unknown;
+
+    // The level has to be taken from the header before it is replaced by the payload
+    let compression_level = DCX::compression_level(&mut br);
+    SFUtil::decompress_if_neccessary(&mut br, &mut compression)?;
+    Ok((br, compression, compression_level))
+}
+
+impl<T> Deref for Compressed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.file
+    }
+}
+
+impl<T> DerefMut for Compressed<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.file
+    }
+}
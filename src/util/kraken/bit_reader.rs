@@ -0,0 +1,295 @@
+// Bit readers used by the Kraken family entropy decoders.
+//
+// The main reader keeps up to 32 bits MSB aligned in `bits`. `bitpos` counts down from 24 as
+// bytes are loaded, so `24 - bitpos` is the number of valid bits currently buffered. Reads past
+// the end of the stream yield zero bits, the callers validate the final position instead.
+pub(crate) struct BitReader<'a> {
+    src: &'a [u8],
+    // Next byte to load. For backwards readers this is one past the next byte to load.
+    pub(crate) p: isize,
+    // Exclusive end for forward readers, inclusive start for backwards readers.
+    limit: isize,
+    pub(crate) bits: u32,
+    pub(crate) bitpos: i32,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(src: &'a [u8], start: usize, end: usize) -> Self {
+        let mut br = Self {
+            src,
+            p: start as isize,
+            limit: end as isize,
+            bits: 0,
+            bitpos: 24,
+        };
+        br.refill();
+        br
+    }
+
+    pub(crate) fn new_backwards(src: &'a [u8], start: usize, end: usize) -> Self {
+        let mut br = Self {
+            src,
+            p: end as isize,
+            limit: start as isize,
+            bits: 0,
+            bitpos: 24,
+        };
+        br.refill_backwards();
+        br
+    }
+
+    pub(crate) fn source(&self) -> &'a [u8] {
+        self.src
+    }
+
+    // Restarts a forward reader at the given byte and bit offset.
+    pub(crate) fn reset(&mut self, p: usize, bitpos: u32) {
+        self.bitpos = 24;
+        self.p = p as isize;
+        self.bits = 0;
+        self.refill();
+        self.bits <<= bitpos;
+        self.bitpos += bitpos as i32;
+    }
+
+    // Position of the first byte that has not been fully consumed by a forward reader.
+    pub(crate) fn position(&self) -> isize {
+        self.p - ((24 - self.bitpos) >> 3) as isize
+    }
+
+    // Position just past the last byte consumed by a backwards reader.
+    pub(crate) fn position_backwards(&self) -> isize {
+        self.p + ((24 - self.bitpos) >> 3) as isize
+    }
+
+    // Byte offset and bit offset of the next unread bit of a forward reader.
+    pub(crate) fn split_position(&self) -> (usize, u32) {
+        let p = self.p - ((24 - self.bitpos + 7) >> 3) as isize;
+        (p.max(0) as usize, ((self.bitpos - 24) & 7) as u32)
+    }
+
+    pub(crate) fn refill(&mut self) {
+        while self.bitpos > 0 {
+            let byte = if self.p < self.limit { self.src[self.p as usize] } else { 0 };
+            self.bits |= (byte as u32) << self.bitpos;
+            self.bitpos -= 8;
+            self.p += 1;
+        }
+    }
+
+    pub(crate) fn refill_backwards(&mut self) {
+        while self.bitpos > 0 {
+            self.p -= 1;
+            let byte = if self.p >= self.limit && self.p >= 0 { self.src[self.p as usize] } else { 0 };
+            self.bits |= (byte as u32) << self.bitpos;
+            self.bitpos -= 8;
+        }
+    }
+
+    pub(crate) fn read_bit(&mut self) -> u32 {
+        self.refill();
+        self.read_bit_no_refill()
+    }
+
+    pub(crate) fn read_bit_no_refill(&mut self) -> u32 {
+        let r = self.bits >> 31;
+        self.bits <<= 1;
+        self.bitpos += 1;
+        r
+    }
+
+    // Reads 1 to 31 bits.
+    pub(crate) fn read_bits_no_refill(&mut self, n: u32) -> u32 {
+        let r = self.bits >> (32 - n);
+        self.bits <<= n;
+        self.bitpos += n as i32;
+        r
+    }
+
+    // Reads 0 to 31 bits.
+    pub(crate) fn read_bits_no_refill_zero(&mut self, n: u32) -> u32 {
+        let r = (self.bits >> 1) >> (31 - n);
+        self.bits <<= n;
+        self.bitpos += n as i32;
+        r
+    }
+
+    pub(crate) fn read_more_than_24_bits(&mut self, n: u32) -> u32 {
+        let r = self.read_long_bits(n, BitReader::refill);
+        self.refill();
+        r
+    }
+
+    pub(crate) fn read_more_than_24_bits_backwards(&mut self, n: u32) -> u32 {
+        let r = self.read_long_bits(n, BitReader::refill_backwards);
+        self.refill_backwards();
+        r
+    }
+
+    fn read_long_bits(&mut self, n: u32, refill: fn(&mut Self)) -> u32 {
+        if n <= 24 {
+            self.read_bits_no_refill_zero(n)
+        } else {
+            let high = self.read_bits_no_refill(24) << (n - 24);
+            refill(self);
+            high + self.read_bits_no_refill(n - 24)
+        }
+    }
+
+    pub(crate) fn leading_zeros(&self) -> u32 {
+        self.bits.leading_zeros()
+    }
+
+    // Reads the number of extra symbols in a symbol range list.
+    pub(crate) fn read_fluff(&mut self, num_symbols: u32) -> u32 {
+        if num_symbols == 256 {
+            return 0;
+        }
+
+        let x = (257 - num_symbols).min(num_symbols) * 2;
+        let y = 32 - (x - 1).leading_zeros();
+        let v = self.bits >> (32 - y);
+        let z = (1 << y) - x;
+
+        if (v >> 1) >= z {
+            self.bits <<= y;
+            self.bitpos += y as i32;
+            v - z
+        } else {
+            self.bits <<= y - 1;
+            self.bitpos += y as i32 - 1;
+            v >> 1
+        }
+    }
+
+    pub(crate) fn read_distance(&mut self, v: u32) -> u32 {
+        self.read_distance_with(v, BitReader::refill)
+    }
+
+    pub(crate) fn read_distance_backwards(&mut self, v: u32) -> u32 {
+        self.read_distance_with(v, BitReader::refill_backwards)
+    }
+
+    fn read_distance_with(&mut self, v: u32, refill: fn(&mut Self)) -> u32 {
+        let r;
+        if v < 0xF0 {
+            let n = (v >> 4) + 4;
+            let w = (self.bits | 1).rotate_left(n);
+            self.bitpos += n as i32;
+            let m = (2u32 << n) - 1;
+            self.bits = w & !m;
+            r = ((w & m) << 4).wrapping_add(v & 0xF).wrapping_sub(248);
+        } else {
+            let n = v - 0xF0 + 4;
+            let w = (self.bits | 1).rotate_left(n);
+            self.bitpos += n as i32;
+            let m = (2u32 << n) - 1;
+            self.bits = w & !m;
+            let high = 8322816u32.wrapping_add((w & m) << 12);
+            refill(self);
+            r = high.wrapping_add(self.bits >> 20);
+            self.bitpos += 12;
+            self.bits <<= 12;
+        }
+        refill(self);
+        r
+    }
+
+    pub(crate) fn read_length(&mut self) -> Option<u32> {
+        self.read_length_with(BitReader::refill)
+    }
+
+    pub(crate) fn read_length_backwards(&mut self) -> Option<u32> {
+        self.read_length_with(BitReader::refill_backwards)
+    }
+
+    fn read_length_with(&mut self, refill: fn(&mut Self)) -> Option<u32> {
+        let mut n = self.bits.leading_zeros();
+        if n > 12 {
+            return None;
+        }
+        self.bitpos += n as i32;
+        self.bits <<= n;
+        refill(self);
+        n += 7;
+        self.bitpos += n as i32;
+        let v = (self.bits >> (32 - n)).wrapping_sub(64);
+        self.bits <<= n;
+        refill(self);
+        Some(v)
+    }
+}
+
+// Byte oriented MSB-first reader used for the Golomb-Rice coded code lengths.
+pub(crate) struct BitReader2<'a> {
+    pub(crate) src: &'a [u8],
+    pub(crate) p: usize,
+    pub(crate) p_end: usize,
+    pub(crate) bitpos: u32,
+}
+
+impl BitReader2<'_> {
+    // Decodes `count` unary values, each being the number of zero bits before the next one bit.
+    pub(crate) fn decode_golomb_rice_lengths(&mut self, dst: &mut [u8], count: usize) -> bool {
+        let mut written = 0;
+        let mut zeros: u32 = 0;
+        let mut bitpos = self.bitpos;
+        let mut p = self.p;
+
+        while written < count {
+            if p >= self.p_end {
+                return false;
+            }
+
+            let byte = self.src[p];
+            let bit = (byte >> (7 - bitpos)) & 1;
+            bitpos += 1;
+            if bitpos == 8 {
+                bitpos = 0;
+                p += 1;
+            }
+
+            if bit == 0 {
+                zeros += 1;
+            } else {
+                if zeros > 255 {
+                    return false;
+                }
+                dst[written] = zeros as u8;
+                written += 1;
+                zeros = 0;
+            }
+        }
+
+        self.p = p;
+        self.bitpos = bitpos;
+        true
+    }
+
+    // Appends `bitcount` low bits to each of the values.
+    pub(crate) fn decode_golomb_rice_bits(&mut self, dst: &mut [u8], count: usize, bitcount: u32) -> bool {
+        if bitcount == 0 {
+            return true;
+        }
+
+        let bits_required = self.bitpos as usize + bitcount as usize * count;
+        if bits_required.div_ceil(8) > self.p_end - self.p {
+            return false;
+        }
+
+        let mut position = self.p * 8 + self.bitpos as usize;
+        for value in dst.iter_mut().take(count) {
+            let mut extra = 0u32;
+            for _ in 0..bitcount {
+                let bit = (self.src[position >> 3] >> (7 - (position & 7))) & 1;
+                extra = (extra << 1) | bit as u32;
+                position += 1;
+            }
+            *value = ((*value as u32) << bitcount | extra) as u8;
+        }
+
+        self.p = position >> 3;
+        self.bitpos = (position & 7) as u32;
+        true
+    }
+}
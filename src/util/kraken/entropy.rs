@@ -0,0 +1,1142 @@
+use std::io::Error;
+
+use crate::util::kraken::bit_reader::{BitReader, BitReader2};
+use crate::util::kraken::invalid;
+
+// Start offset of each code length bucket in the symbol array built while reading code lengths.
+const CODE_PREFIX_ORG: [u32; 12] = [0x0, 0x0, 0x2, 0x6, 0xE, 0x1E, 0x3E, 0x7E, 0xFE, 0x1FE, 0x2FE, 0x3FE];
+
+// Decodes one entropy coded block starting at `src[0]`.
+// Returns the decoded bytes and the number of source bytes consumed.
+pub(crate) fn decode_bytes(src: &[u8], output_capacity: usize) -> Result<(Vec<u8>, usize), Error> {
+    if src.len() < 2 {
+        return Err(invalid("Truncated entropy block header."));
+    }
+
+    let chunk_type = (src[0] >> 4) & 0x7;
+
+    if chunk_type == 0 {
+        // Stored, with a 12 bit or 18 bit length
+        let (src_size, header_size) = if src[0] >= 0x80 {
+            (((src[0] as usize) << 8 | src[1] as usize) & 0xFFF, 2)
+        } else {
+            if src.len() < 3 {
+                return Err(invalid("Truncated entropy block header."));
+            }
+            let size = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+            if size & !0x3FFFF != 0 {
+                return Err(invalid("Reserved bits set in stored block header."));
+            }
+            (size, 3)
+        };
+
+        if src_size > output_capacity || src.len() - header_size < src_size {
+            return Err(invalid("Stored block does not fit."));
+        }
+
+        return Ok((src[header_size..header_size + src_size].to_vec(), header_size + src_size));
+    }
+
+    let (src_size, dst_size, header_size) = read_block_sizes(src)?;
+    if src.len() - header_size < src_size || dst_size > output_capacity {
+        return Err(invalid("Entropy block does not fit."));
+    }
+
+    let block = &src[header_size..header_size + src_size];
+    let mut dst = vec![0u8; dst_size];
+
+    let src_used = match chunk_type {
+        2 | 4 => decode_huffman(block, &mut dst, chunk_type >> 1)?,
+        5 => decode_recursive(block, &mut dst)?,
+        3 => decode_rle(block, &mut dst)?,
+        1 => decode_tans(block, &mut dst)?,
+        _ => return Err(invalid("Unknown entropy block type.")),
+    };
+
+    if src_used != src_size {
+        return Err(invalid("Entropy block size mismatch."));
+    }
+
+    Ok((dst, header_size + src_size))
+}
+
+// Peeks at the decoded size of the next entropy block without decoding it.
+fn get_block_size(src: &[u8], capacity: usize) -> Result<usize, Error> {
+    if src.len() < 2 {
+        return Err(invalid("Truncated entropy block header."));
+    }
+
+    let chunk_type = (src[0] >> 4) & 0x7;
+
+    if chunk_type == 0 {
+        let (src_size, header_size) = if src[0] >= 0x80 {
+            (((src[0] as usize) << 8 | src[1] as usize) & 0xFFF, 2)
+        } else {
+            if src.len() < 3 {
+                return Err(invalid("Truncated entropy block header."));
+            }
+            let size = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+            if size & !0x3FFFF != 0 {
+                return Err(invalid("Reserved bits set in stored block header."));
+            }
+            (size, 3)
+        };
+
+        if src_size > capacity || src.len() - header_size < src_size {
+            return Err(invalid("Stored block does not fit."));
+        }
+        return Ok(src_size);
+    }
+
+    if chunk_type >= 6 {
+        return Err(invalid("Unknown entropy block type."));
+    }
+
+    let (src_size, dst_size, header_size) = read_block_sizes(src)?;
+    if src.len() - header_size < src_size || dst_size > capacity {
+        return Err(invalid("Entropy block does not fit."));
+    }
+    Ok(dst_size)
+}
+
+// Reads the compressed and decompressed size of an entropy block, either in the 10 bit short
+// form or the 18 bit long form.
+fn read_block_sizes(src: &[u8]) -> Result<(usize, usize, usize), Error> {
+    if src[0] >= 0x80 {
+        if src.len() < 3 {
+            return Err(invalid("Truncated entropy block header."));
+        }
+        let bits = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+        let src_size = bits & 0x3FF;
+        let dst_size = src_size + ((bits >> 10) & 0x3FF) + 1;
+        Ok((src_size, dst_size, 3))
+    } else {
+        if src.len() < 5 {
+            return Err(invalid("Truncated entropy block header."));
+        }
+        let bits = (src[1] as usize) << 24 | (src[2] as usize) << 16 | (src[3] as usize) << 8 | src[4] as usize;
+        let src_size = bits & 0x3FFFF;
+        let dst_size = (((bits >> 18) | (src[0] as usize) << 14) & 0x3FFFF) + 1;
+        if src_size >= dst_size {
+            return Err(invalid("Entropy block does not shrink."));
+        }
+        Ok((src_size, dst_size, 5))
+    }
+}
+
+//************ Huffman **************/
+
+// Lookup table indexed by the next 11 bits of an LSB-first stream.
+struct HuffLut {
+    bits2len: [u8; 2048],
+    bits2sym: [u8; 2048],
+}
+
+fn decode_huffman(src: &[u8], output: &mut [u8], kind: u8) -> Result<usize, Error> {
+    let src_end = src.len();
+    let mut br = BitReader::new(src, 0, src_end);
+
+    let mut code_prefix = CODE_PREFIX_ORG;
+    let mut syms = [0u8; 1280];
+
+    let num_syms = if br.read_bit_no_refill() == 0 {
+        read_code_lengths_old(&mut br, &mut syms, &mut code_prefix)?
+    } else if br.read_bit_no_refill() == 0 {
+        read_code_lengths_new(&mut br, &mut syms, &mut code_prefix)?
+    } else {
+        return Err(invalid("Unknown Huffman table encoding."));
+    };
+
+    if num_syms < 1 {
+        return Err(invalid("Empty Huffman table."));
+    }
+
+    let position = br.position();
+    if position < 0 || position as usize > src_end {
+        return Err(invalid("Huffman table runs past the end of the block."));
+    }
+    let mut pos = position as usize;
+
+    if num_syms == 1 {
+        output.fill(syms[0]);
+        return Ok(pos);
+    }
+
+    let lut = make_lut(&code_prefix, &syms)?;
+
+    if kind == 1 {
+        // Three interleaved streams
+        if pos + 3 > src_end {
+            return Err(invalid("Truncated Huffman block."));
+        }
+        let split_mid = u16::from_le_bytes([src[pos], src[pos + 1]]) as usize;
+        pos += 2;
+        decode_huffman_streams(&lut, src, pos, pos + split_mid, src_end, output)?;
+    } else {
+        // Two halves of three interleaved streams each
+        if pos + 6 > src_end {
+            return Err(invalid("Truncated Huffman block."));
+        }
+        let half_output_size = output.len().div_ceil(2);
+        let split_mid = (src[pos] as usize) | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+        pos += 3;
+        if split_mid > src_end - pos {
+            return Err(invalid("Invalid Huffman split."));
+        }
+        let src_mid = pos + split_mid;
+        let split_left = u16::from_le_bytes([src[pos], src[pos + 1]]) as usize;
+        pos += 2;
+        if src_mid - pos < split_left + 2 || src_end - src_mid < 3 {
+            return Err(invalid("Invalid Huffman split."));
+        }
+        let split_right = u16::from_le_bytes([src[src_mid], src[src_mid + 1]]) as usize;
+        if src_end - (src_mid + 2) < split_right + 2 {
+            return Err(invalid("Invalid Huffman split."));
+        }
+
+        let (left, right) = output.split_at_mut(half_output_size);
+        decode_huffman_streams(&lut, src, pos, pos + split_left, src_mid, left)?;
+        decode_huffman_streams(&lut, src, src_mid + 2, src_mid + 2 + split_right, src_end, right)?;
+    }
+
+    Ok(src_end)
+}
+
+// Decodes three LSB-first streams: one forward from `start`, one backwards from `end` and one
+// forward from `mid`. Symbols are taken from each in turn, and all three must end up exactly at
+// their boundaries.
+fn decode_huffman_streams(lut: &HuffLut, src: &[u8], start: usize, mid: usize, end: usize, output: &mut [u8]) -> Result<(), Error> {
+    if start > mid || mid > end {
+        return Err(invalid("Invalid Huffman split."));
+    }
+
+    let mut forward = LsbReader::new(src, start, mid, false);
+    let mut backward = LsbReader::new(src, mid, end, true);
+    let mut middle = LsbReader::new(src, mid, end, false);
+
+    let mut readers = [&mut forward, &mut backward, &mut middle];
+    for (i, out) in output.iter_mut().enumerate() {
+        let reader = &mut readers[i % 3];
+        let k = reader.peek(11) as usize;
+        reader.consume(lut.bits2len[k] as u32);
+        *out = lut.bits2sym[k];
+    }
+
+    if forward.byte_position() != mid {
+        return Err(invalid("Huffman stream size mismatch."));
+    }
+    if middle.byte_position() != end - backward.bytes_consumed() {
+        return Err(invalid("Huffman stream size mismatch."));
+    }
+
+    Ok(())
+}
+
+fn read_code_lengths_old(br: &mut BitReader, syms: &mut [u8; 1280], code_prefix: &mut [u32; 12]) -> Result<u32, Error> {
+    if br.read_bit_no_refill() != 0 {
+        let mut sym: u32 = 0;
+        let mut num_symbols: u32 = 0;
+        let mut avg_bits_x4: i32 = 32;
+        let forced_bits = br.read_bits_no_refill(2);
+
+        let thres_for_valid_gamma_bits = 1u32 << (31 - (20 >> forced_bits));
+        let mut skip_initial_zeros = br.read_bit() == 0;
+
+        loop {
+            if !skip_initial_zeros {
+                // Run of zeros
+                if br.bits & 0xFF000000 == 0 {
+                    return Err(invalid("Invalid Huffman code lengths."));
+                }
+                let n = 2 * (br.leading_zeros() + 1);
+                sym += br.read_bits_no_refill(n) - 2 + 1;
+                if sym >= 256 {
+                    break;
+                }
+            }
+            skip_initial_zeros = false;
+
+            br.refill();
+            // Read out the gamma value for the number of symbols
+            if br.bits & 0xFF000000 == 0 {
+                return Err(invalid("Invalid Huffman code lengths."));
+            }
+            let bits = 2 * (br.leading_zeros() + 1);
+            let mut n = br.read_bits_no_refill(bits) - 2 + 1;
+            if sym + n > 256 {
+                return Err(invalid("Invalid Huffman code lengths."));
+            }
+            br.refill();
+            num_symbols += n;
+
+            while n > 0 {
+                if br.bits < thres_for_valid_gamma_bits {
+                    return Err(invalid("Invalid Huffman code lengths."));
+                }
+
+                let lz = br.leading_zeros();
+                let v = br.read_bits_no_refill(lz + forced_bits + 1) as i32 + ((lz as i32 - 1) << forced_bits);
+                let codelen = (-(v & 1) ^ (v >> 1)) + ((avg_bits_x4 + 2) >> 2);
+                if !(1..=11).contains(&codelen) {
+                    return Err(invalid("Invalid Huffman code length."));
+                }
+                avg_bits_x4 = codelen + ((3 * avg_bits_x4 + 2) >> 2);
+                br.refill();
+
+                let slot = &mut code_prefix[codelen as usize];
+                syms[*slot as usize] = sym as u8;
+                *slot += 1;
+                sym += 1;
+                n -= 1;
+            }
+
+            if sym == 256 {
+                break;
+            }
+        }
+
+        Ok(num_symbols)
+    } else {
+        // Sparse symbol encoding
+        let num_symbols = br.read_bits_no_refill(8);
+        if num_symbols == 0 {
+            return Err(invalid("Empty Huffman table."));
+        }
+        if num_symbols == 1 {
+            syms[0] = br.read_bits_no_refill(8) as u8;
+        } else {
+            let codelen_bits = br.read_bits_no_refill(3);
+            if codelen_bits > 4 {
+                return Err(invalid("Invalid Huffman code lengths."));
+            }
+            for _ in 0..num_symbols {
+                br.refill();
+                let sym = br.read_bits_no_refill(8);
+                let codelen = br.read_bits_no_refill_zero(codelen_bits) + 1;
+                if codelen > 11 {
+                    return Err(invalid("Invalid Huffman code length."));
+                }
+                let slot = &mut code_prefix[codelen as usize];
+                syms[*slot as usize] = sym as u8;
+                *slot += 1;
+            }
+        }
+        Ok(num_symbols)
+    }
+}
+
+fn read_code_lengths_new(br: &mut BitReader, syms: &mut [u8; 1280], code_prefix: &mut [u32; 12]) -> Result<u32, Error> {
+    let forced_bits = br.read_bits_no_refill(2);
+    let num_symbols = br.read_bits_no_refill(8) + 1;
+    let fluff = br.read_fluff(num_symbols);
+
+    let mut code_len = [0u8; 512 + 16];
+    let (p, bitpos) = br.split_position();
+    let mut br2 = BitReader2 {
+        src: br.source(),
+        p,
+        p_end: br.source().len(),
+        bitpos,
+    };
+
+    let total = (num_symbols + fluff) as usize;
+    if total > 512 || !br2.decode_golomb_rice_lengths(&mut code_len, total) {
+        return Err(invalid("Invalid Huffman code lengths."));
+    }
+    if !br2.decode_golomb_rice_bits(&mut code_len, num_symbols as usize, forced_bits) {
+        return Err(invalid("Invalid Huffman code lengths."));
+    }
+
+    // Switch back to the main bit reader
+    br.reset(br2.p, br2.bitpos);
+
+    let mut running_sum: i32 = 0x1E;
+    for len in code_len.iter_mut().take(num_symbols as usize) {
+        let v = *len as i32;
+        let v = -(v & 1) ^ (v >> 1);
+        let codelen = v + (running_sum >> 2) + 1;
+        if !(1..=11).contains(&codelen) {
+            return Err(invalid("Invalid Huffman code length."));
+        }
+        *len = codelen as u8;
+        running_sum += v;
+    }
+
+    let ranges = convert_to_ranges(num_symbols, fluff, &code_len[num_symbols as usize..], br)?;
+
+    let mut lengths = code_len.iter();
+    for range in ranges {
+        for sym in range.symbol..range.symbol + range.num {
+            let codelen = *lengths.next().unwrap_or(&0) as usize;
+            let slot = &mut code_prefix[codelen];
+            syms[*slot as usize] = sym as u8;
+            *slot += 1;
+        }
+    }
+
+    Ok(num_symbols)
+}
+
+struct HuffRange {
+    symbol: u32,
+    num: u32,
+}
+
+// Expands the alternating run lengths of used and unused symbols into ranges of used symbols.
+fn convert_to_ranges(num_symbols: u32, fluff: u32, symlen: &[u8], br: &mut BitReader) -> Result<Vec<HuffRange>, Error> {
+    let num_ranges = fluff >> 1;
+    let mut sym_idx: u32 = 0;
+    let mut symlen = symlen.iter();
+
+    // Start with space?
+    if fluff & 1 != 0 {
+        br.refill();
+        let v = *symlen.next().unwrap_or(&0) as u32;
+        if v >= 8 {
+            return Err(invalid("Invalid symbol range."));
+        }
+        sym_idx = br.read_bits_no_refill(v + 1) + (1 << (v + 1)) - 1;
+    }
+
+    let mut ranges = Vec::with_capacity(num_ranges as usize + 1);
+    let mut syms_used = 0;
+
+    for _ in 0..num_ranges {
+        br.refill();
+        let v = *symlen.next().unwrap_or(&0) as u32;
+        if v >= 9 {
+            return Err(invalid("Invalid symbol range."));
+        }
+        let num = br.read_bits_no_refill_zero(v) + (1 << v);
+        let v = *symlen.next().unwrap_or(&0) as u32;
+        if v >= 8 {
+            return Err(invalid("Invalid symbol range."));
+        }
+        let space = br.read_bits_no_refill(v + 1) + (1 << (v + 1)) - 1;
+        ranges.push(HuffRange { symbol: sym_idx, num });
+        syms_used += num;
+        sym_idx += num + space;
+    }
+
+    if sym_idx >= 256 || syms_used >= num_symbols || sym_idx + num_symbols - syms_used > 256 {
+        return Err(invalid("Invalid symbol range."));
+    }
+
+    ranges.push(HuffRange { symbol: sym_idx, num: num_symbols - syms_used });
+    Ok(ranges)
+}
+
+// Builds the canonical code table and converts it to be indexed by LSB-first bits.
+fn make_lut(code_prefix: &[u32; 12], syms: &[u8; 1280]) -> Result<HuffLut, Error> {
+    let mut bits2len = [0u8; 2048];
+    let mut bits2sym = [0u8; 2048];
+    let mut currslot = 0usize;
+
+    for i in 1..11 {
+        let start = CODE_PREFIX_ORG[i] as usize;
+        let count = code_prefix[i] as usize - start;
+        if count != 0 {
+            let stepsize = 1 << (11 - i);
+            let num_to_set = count << (11 - i);
+            if currslot + num_to_set > 2048 {
+                return Err(invalid("Oversubscribed Huffman table."));
+            }
+            bits2len[currslot..currslot + num_to_set].fill(i as u8);
+            for j in 0..count {
+                let slot = currslot + j * stepsize;
+                bits2sym[slot..slot + stepsize].fill(syms[start + j]);
+            }
+            currslot += num_to_set;
+        }
+    }
+
+    let start = CODE_PREFIX_ORG[11] as usize;
+    let num_to_set = code_prefix[11] as usize - start;
+    if num_to_set != 0 {
+        if currslot + num_to_set > 2048 {
+            return Err(invalid("Oversubscribed Huffman table."));
+        }
+        bits2len[currslot..currslot + num_to_set].fill(11);
+        bits2sym[currslot..currslot + num_to_set].copy_from_slice(&syms[start..start + num_to_set]);
+        currslot += num_to_set;
+    }
+
+    if currslot != 2048 {
+        return Err(invalid("Incomplete Huffman table."));
+    }
+
+    let mut lut = HuffLut {
+        bits2len: [0; 2048],
+        bits2sym: [0; 2048],
+    };
+    for i in 0..2048 {
+        let reversed = (i as u16).reverse_bits() as usize >> 5;
+        lut.bits2len[reversed] = bits2len[i];
+        lut.bits2sym[reversed] = bits2sym[i];
+    }
+    Ok(lut)
+}
+
+// LSB-first bit reader that walks bytes forwards or backwards, padding with zeros.
+struct LsbReader<'a> {
+    src: &'a [u8],
+    start: usize,
+    end: usize,
+    backwards: bool,
+    bit_position: usize,
+}
+
+impl<'a> LsbReader<'a> {
+    fn new(src: &'a [u8], start: usize, end: usize, backwards: bool) -> Self {
+        Self {
+            src,
+            start,
+            end,
+            backwards,
+            bit_position: 0,
+        }
+    }
+
+    fn byte(&self, index: usize) -> u32 {
+        let length = self.end - self.start;
+        if index >= length {
+            return 0;
+        }
+        if self.backwards {
+            self.src[self.end - 1 - index] as u32
+        } else {
+            self.src[self.start + index] as u32
+        }
+    }
+
+    fn peek(&self, n: u32) -> u32 {
+        let mut value = 0u32;
+        let first = self.bit_position >> 3;
+        let shift = (self.bit_position & 7) as u32;
+        for i in 0..4 {
+            value |= self.byte(first + i) << (8 * i);
+        }
+        (value >> shift) & ((1u32 << n) - 1)
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.bit_position += n as usize;
+    }
+
+    fn bytes_consumed(&self) -> usize {
+        self.bit_position.div_ceil(8)
+    }
+
+    fn byte_position(&self) -> usize {
+        self.start + self.bytes_consumed()
+    }
+}
+
+//************ tANS **************/
+
+struct TansData {
+    a: Vec<u8>,
+    b: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct TansLutEnt {
+    x: u32,
+    bits_x: u8,
+    symbol: u8,
+    w: u16,
+}
+
+fn decode_tans(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    if src.len() < 8 || dst.len() < 5 {
+        return Err(invalid("Truncated tANS block."));
+    }
+
+    let mut br = BitReader::new(src, 0, src.len());
+
+    // Reserved bit
+    if br.read_bit_no_refill() != 0 {
+        return Err(invalid("Reserved bit set in tANS block."));
+    }
+
+    let l_bits = br.read_bits_no_refill(2) + 8;
+    let tans_data = decode_tans_table(&mut br, l_bits)?;
+
+    let position = br.position();
+    if position < 0 || position as usize >= src.len() {
+        return Err(invalid("tANS table runs past the end of the block."));
+    }
+    let start = position as usize;
+
+    let lut = init_tans_lut(&tans_data, l_bits);
+    let l_mask = (1u32 << l_bits) - 1;
+
+    let mut forward = LsbReader::new(src, start, src.len(), false);
+    let mut backward = LsbReader::new(src, start, src.len(), true);
+
+    // Read out the initial states, alternating between both ends
+    let mut states = [0u32; 5];
+    states[0] = forward.peek(l_bits) & l_mask;
+    forward.consume(l_bits);
+    states[1] = backward.peek(l_bits) & l_mask;
+    backward.consume(l_bits);
+    states[2] = forward.peek(l_bits) & l_mask;
+    forward.consume(l_bits);
+    states[3] = backward.peek(l_bits) & l_mask;
+    backward.consume(l_bits);
+    states[4] = forward.peek(l_bits) & l_mask;
+    forward.consume(l_bits);
+
+    // Five states are decoded from the forward stream, then five from the backward stream.
+    // The final state values hold the last five bytes directly.
+    let body = dst.len() - 5;
+    for (i, out) in dst[..body].iter_mut().enumerate() {
+        let state = &mut states[i % 5];
+        let reader = if (i / 5) % 2 == 0 { &mut forward } else { &mut backward };
+
+        let e = lut.get(*state as usize).ok_or_else(|| invalid("Invalid tANS state."))?;
+        *out = e.symbol;
+        *state = (reader.peek(e.bits_x as u32) & e.x) + e.w as u32;
+        reader.consume(e.bits_x as u32);
+    }
+
+    if forward.byte_position() + backward.bytes_consumed() != src.len() {
+        return Err(invalid("tANS stream size mismatch."));
+    }
+
+    for (i, state) in states.iter().enumerate() {
+        if *state & !0xFF != 0 {
+            return Err(invalid("Invalid final tANS state."));
+        }
+        dst[body + i] = *state as u8;
+    }
+
+    Ok(src.len())
+}
+
+fn decode_tans_table(br: &mut BitReader, l_bits: u32) -> Result<TansData, Error> {
+    br.refill();
+    let l = 1u32 << l_bits;
+
+    if br.read_bit_no_refill() != 0 {
+        let q = br.read_bits_no_refill(3);
+        let num_symbols = br.read_bits_no_refill(8) + 1;
+        if num_symbols < 2 {
+            return Err(invalid("Invalid tANS table."));
+        }
+        let fluff = br.read_fluff(num_symbols);
+        let total_rice_values = (fluff + num_symbols) as usize;
+        let mut rice = [0u8; 512 + 16];
+
+        let (p, bitpos) = br.split_position();
+        let mut br2 = BitReader2 {
+            src: br.source(),
+            p,
+            p_end: br.source().len(),
+            bitpos,
+        };
+        if total_rice_values > 512 || !br2.decode_golomb_rice_lengths(&mut rice, total_rice_values) {
+            return Err(invalid("Invalid tANS table."));
+        }
+
+        // Switch back to the main bit reader
+        br.reset(br2.p, br2.bitpos);
+
+        let ranges = convert_to_ranges(num_symbols, fluff, &rice[num_symbols as usize..], br)?;
+        br.refill();
+
+        let mut rice_values = rice.iter();
+        let mut average: i32 = 6;
+        let mut somesum: u32 = 0;
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+
+        for range in ranges {
+            for symbol in range.symbol..range.symbol + range.num {
+                br.refill();
+
+                let nextra = q + *rice_values.next().unwrap_or(&0) as u32;
+                if nextra > 15 {
+                    return Err(invalid("Invalid tANS table."));
+                }
+                let mut v = br.read_bits_no_refill_zero(nextra) as i32 + (1 << nextra) - (1 << q);
+
+                let average_div4 = average >> 2;
+                let mut limit = 2 * average_div4;
+                if v <= limit {
+                    v = average_div4 + (-(v & 1) ^ ((v as u32 >> 1) as i32));
+                }
+                if limit > v {
+                    limit = v;
+                }
+                v += 1;
+                average += limit - average_div4;
+
+                if v == 1 {
+                    a.push(symbol as u8);
+                } else if v >= 2 {
+                    b.push((symbol << 16) + v as u32);
+                }
+                somesum = somesum.wrapping_add(v as u32);
+            }
+        }
+
+        if somesum != l {
+            return Err(invalid("Invalid tANS table weights."));
+        }
+        Ok(TansData { a, b })
+    } else {
+        let mut seen = [false; 256];
+        let mut count = br.read_bits_no_refill(3) + 1;
+
+        let bits_per_sym = 32 - l_bits.leading_zeros();
+        let max_delta_bits = br.read_bits_no_refill(bits_per_sym);
+        if max_delta_bits == 0 || max_delta_bits > l_bits {
+            return Err(invalid("Invalid tANS table."));
+        }
+
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        let mut weight: u32 = 0;
+        let mut total_weights: u32 = 0;
+
+        while count > 0 {
+            br.refill();
+
+            let sym = br.read_bits_no_refill(8);
+            if seen[sym as usize] {
+                return Err(invalid("Invalid tANS table."));
+            }
+
+            let delta = br.read_bits_no_refill(max_delta_bits);
+            weight += delta;
+            if weight == 0 {
+                return Err(invalid("Invalid tANS table."));
+            }
+
+            seen[sym as usize] = true;
+            if weight == 1 {
+                a.push(sym as u8);
+            } else {
+                b.push((sym << 16) + weight);
+            }
+            total_weights += weight;
+            count -= 1;
+        }
+
+        br.refill();
+        let sym = br.read_bits_no_refill(8);
+        if seen[sym as usize] {
+            return Err(invalid("Invalid tANS table."));
+        }
+        if l < total_weights || l - total_weights < weight || l - total_weights <= 1 {
+            return Err(invalid("Invalid tANS table weights."));
+        }
+        b.push((sym << 16) + (l - total_weights));
+
+        a.sort_unstable();
+        b.sort_unstable();
+        Ok(TansData { a, b })
+    }
+}
+
+fn init_tans_lut(tans_data: &TansData, l_bits: u32) -> Vec<TansLutEnt> {
+    let l = 1usize << l_bits;
+    let mut lut = vec![TansLutEnt::default(); l];
+    let a_used = tans_data.a.len();
+
+    let slots_left_to_alloc = l - a_used;
+    let sa = slots_left_to_alloc >> 2;
+
+    // Symbols with weight above one are spread round robin over four interleaved regions
+    let mut pointers = [0usize; 4];
+    let mut sb = sa + ((slots_left_to_alloc & 3) > 0) as usize;
+    pointers[1] = sb;
+    sb += sa + ((slots_left_to_alloc & 3) > 1) as usize;
+    pointers[2] = sb;
+    sb += sa + ((slots_left_to_alloc & 3) > 2) as usize;
+    pointers[3] = sb;
+
+    // Setup the single entries with weight one
+    for (i, symbol) in tans_data.a.iter().enumerate() {
+        lut[slots_left_to_alloc + i] = TansLutEnt {
+            x: (1 << l_bits) - 1,
+            bits_x: l_bits as u8,
+            symbol: *symbol,
+            w: 0,
+        };
+    }
+
+    // Setup the entries with weight two or more
+    let mut weights_sum: u32 = 0;
+    for entry in &tans_data.b {
+        let weight = entry & 0xFFFF;
+        let symbol = (entry >> 16) as u8;
+
+        if weight > 4 {
+            let sym_bits = 31 - weight.leading_zeros();
+            let mut z = l_bits - sym_bits;
+            let mut le = TansLutEnt {
+                symbol,
+                bits_x: z as u8,
+                x: (1 << z) - 1,
+                w: ((l as u32 - 1) & (weight << z)) as u16,
+            };
+            let mut what_to_add = 1u32 << z;
+            let mut x = (1i32 << (sym_bits + 1)) - weight as i32;
+
+            for (j, pointer) in pointers.iter_mut().enumerate() {
+                let y = ((weight as i32 + ((weights_sum as i32 - j as i32 - 1) & 3)) >> 2) as usize;
+                let mut dst = *pointer;
+
+                if x >= y as i32 {
+                    for _ in 0..y {
+                        lut[dst] = le;
+                        dst += 1;
+                        le.w = le.w.wrapping_add(what_to_add as u16);
+                    }
+                    x -= y as i32;
+                } else {
+                    for _ in 0..x {
+                        lut[dst] = le;
+                        dst += 1;
+                        le.w = le.w.wrapping_add(what_to_add as u16);
+                    }
+                    z -= 1;
+
+                    what_to_add >>= 1;
+                    le.bits_x = z as u8;
+                    le.w = 0;
+                    le.x >>= 1;
+                    for _ in 0..(y as i32 - x) {
+                        lut[dst] = le;
+                        dst += 1;
+                        le.w = le.w.wrapping_add(what_to_add as u16);
+                    }
+                    x = weight as i32;
+                }
+                *pointer = dst;
+            }
+        } else {
+            let mut bits = ((1u32 << weight) - 1) << (weights_sum & 3);
+            bits |= bits >> 4;
+            for ww in weight..weight * 2 {
+                let idx = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let dst = pointers[idx];
+                pointers[idx] += 1;
+                let weight_bits = 31 - ww.leading_zeros();
+                lut[dst] = TansLutEnt {
+                    symbol,
+                    bits_x: (l_bits - weight_bits) as u8,
+                    x: (1 << (l_bits - weight_bits)) - 1,
+                    w: ((l as u32 - 1) & (ww << (l_bits - weight_bits))) as u16,
+                };
+            }
+        }
+        weights_sum += weight;
+    }
+
+    lut
+}
+
+//************ RLE **************/
+
+fn decode_rle(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    if src.len() <= 1 {
+        if src.len() != 1 {
+            return Err(invalid("Empty RLE block."));
+        }
+        dst.fill(src[0]);
+        return Ok(1);
+    }
+
+    // The command buffer may itself be entropy coded
+    let commands: Vec<u8> = if src[0] != 0 {
+        let (mut decoded, n) = decode_bytes(src, 0x40000)?;
+        decoded.extend_from_slice(&src[n..]);
+        decoded
+    } else {
+        src[1..].to_vec()
+    };
+
+    let mut cmd_ptr = 0usize;
+    let mut cmd_ptr_end = commands.len();
+    let mut dst_pos = 0usize;
+    let mut rle_byte = 0u8;
+
+    let read_u16 = |at: usize| u16::from_le_bytes([commands[at], commands[at + 1]]) as usize;
+
+    while cmd_ptr < cmd_ptr_end {
+        let cmd = commands[cmd_ptr_end - 1] as usize;
+        let (bytes_to_copy, bytes_to_rle);
+
+        if cmd.wrapping_sub(1) >= 0x2F {
+            cmd_ptr_end -= 1;
+            bytes_to_copy = (!cmd) & 0xF;
+            bytes_to_rle = cmd >> 4;
+        } else if cmd >= 0x10 {
+            if cmd_ptr_end - cmd_ptr < 2 {
+                return Err(invalid("Truncated RLE command."));
+            }
+            let data = read_u16(cmd_ptr_end - 2) - 4096;
+            cmd_ptr_end -= 2;
+            bytes_to_copy = data & 0x3F;
+            bytes_to_rle = data >> 6;
+        } else if cmd == 1 {
+            rle_byte = commands[cmd_ptr];
+            cmd_ptr += 1;
+            cmd_ptr_end -= 1;
+            continue;
+        } else if cmd >= 9 {
+            if cmd_ptr_end - cmd_ptr < 2 {
+                return Err(invalid("Truncated RLE command."));
+            }
+            bytes_to_copy = 0;
+            bytes_to_rle = (read_u16(cmd_ptr_end - 2) - 0x8FF) * 128;
+            cmd_ptr_end -= 2;
+        } else {
+            if cmd_ptr_end - cmd_ptr < 2 {
+                return Err(invalid("Truncated RLE command."));
+            }
+            bytes_to_copy = (read_u16(cmd_ptr_end - 2) - 511) * 64;
+            bytes_to_rle = 0;
+            cmd_ptr_end -= 2;
+        }
+
+        if dst.len() - dst_pos < bytes_to_copy + bytes_to_rle || cmd_ptr_end < cmd_ptr || cmd_ptr_end - cmd_ptr < bytes_to_copy {
+            return Err(invalid("RLE run does not fit."));
+        }
+        dst[dst_pos..dst_pos + bytes_to_copy].copy_from_slice(&commands[cmd_ptr..cmd_ptr + bytes_to_copy]);
+        cmd_ptr += bytes_to_copy;
+        dst_pos += bytes_to_copy;
+        dst[dst_pos..dst_pos + bytes_to_rle].fill(rle_byte);
+        dst_pos += bytes_to_rle;
+    }
+
+    if cmd_ptr_end != cmd_ptr || dst_pos != dst.len() {
+        return Err(invalid("RLE size mismatch."));
+    }
+
+    Ok(src.len())
+}
+
+//************ Recursive **************/
+
+fn decode_recursive(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    if src.len() < 6 {
+        return Err(invalid("Truncated recursive block."));
+    }
+
+    let n = src[0] & 0x7F;
+    if n < 2 {
+        return Err(invalid("Invalid recursive block."));
+    }
+
+    if src[0] & 0x80 == 0 {
+        // A plain sequence of blocks
+        let mut pos = 1;
+        let mut out = 0;
+        for _ in 0..n {
+            let (decoded, used) = decode_bytes(&src[pos..], dst.len() - out)?;
+            dst[out..out + decoded.len()].copy_from_slice(&decoded);
+            out += decoded.len();
+            pos += used;
+        }
+        if out != dst.len() {
+            return Err(invalid("Recursive block size mismatch."));
+        }
+        Ok(pos)
+    } else {
+        let (arrays, used) = decode_multi_array(src, dst.len(), 1)?;
+        if arrays[0].len() != dst.len() {
+            return Err(invalid("Recursive block size mismatch."));
+        }
+        dst.copy_from_slice(&arrays[0]);
+        Ok(used)
+    }
+}
+
+// Decodes several entropy coded arrays and splices intervals of them together into
+// `array_count` output arrays.
+pub(crate) fn decode_multi_array(src: &[u8], dst_capacity: usize, array_count: usize) -> Result<(Vec<Vec<u8>>, usize), Error> {
+    if src.len() < 4 {
+        return Err(invalid("Truncated multi-array block."));
+    }
+
+    let mut pos = 0;
+    let num_arrays_in_file = src[pos];
+    pos += 1;
+    if num_arrays_in_file & 0x80 == 0 {
+        return Err(invalid("Invalid multi-array block."));
+    }
+    let num_arrays_in_file = (num_arrays_in_file & 0x3F) as usize;
+
+    let mut total_out = 0;
+
+    if num_arrays_in_file == 0 {
+        let mut arrays = Vec::with_capacity(array_count);
+        for _ in 0..array_count {
+            let (decoded, used) = decode_bytes(&src[pos..], dst_capacity - total_out)?;
+            total_out += decoded.len();
+            pos += used;
+            arrays.push(decoded);
+        }
+        return Ok((arrays, pos));
+    }
+
+    let mut entropy_arrays = Vec::with_capacity(num_arrays_in_file);
+    let mut total_size = 0;
+    for _ in 0..num_arrays_in_file {
+        let (decoded, used) = decode_bytes(&src[pos..], 0x40000)?;
+        total_size += decoded.len();
+        pos += used;
+        entropy_arrays.push(decoded);
+    }
+
+    if src.len() - pos < 3 {
+        return Err(invalid("Truncated multi-array block."));
+    }
+
+    let q = u16::from_le_bytes([src[pos], src[pos + 1]]) as usize;
+    pos += 2;
+
+    let num_indexes = get_block_size(&src[pos..], total_size)?;
+    let mut num_lens = num_indexes as isize - array_count as isize;
+    if num_lens < 1 {
+        return Err(invalid("Invalid multi-array block."));
+    }
+
+    let mut interval_indexes;
+    let mut interval_lenlog2;
+
+    if q & 0x8000 != 0 {
+        let (decoded, used) = decode_bytes(&src[pos..], num_indexes)?;
+        if decoded.len() != num_indexes {
+            return Err(invalid("Invalid multi-array block."));
+        }
+        pos += used;
+
+        interval_lenlog2 = decoded.iter().map(|t| t >> 4).collect::<Vec<u8>>();
+        interval_indexes = decoded.iter().map(|t| t & 0xF).collect::<Vec<u8>>();
+        num_lens = num_indexes as isize;
+    } else {
+        let lenlog2_chunksize = num_indexes - array_count;
+
+        let (decoded, used) = decode_bytes(&src[pos..], num_indexes)?;
+        if decoded.len() != num_indexes {
+            return Err(invalid("Invalid multi-array block."));
+        }
+        pos += used;
+        interval_indexes = decoded;
+
+        let (decoded, used) = decode_bytes(&src[pos..], lenlog2_chunksize)?;
+        if decoded.len() != lenlog2_chunksize {
+            return Err(invalid("Invalid multi-array block."));
+        }
+        pos += used;
+        interval_lenlog2 = decoded;
+
+        if interval_lenlog2.iter().any(|v| *v > 16) {
+            return Err(invalid("Invalid multi-array block."));
+        }
+    }
+    let num_lens = num_lens as usize;
+    interval_lenlog2.resize(num_lens.max(interval_lenlog2.len()), 0);
+    interval_indexes.resize(num_indexes, 0);
+
+    let varbits_complen = q & 0x3FFF;
+    if src.len() - pos < varbits_complen {
+        return Err(invalid("Truncated multi-array block."));
+    }
+
+    // Interval lengths are stored with an implicit leading one, alternating between a forward
+    // stream and a backwards stream.
+    let varbits = &src[pos..pos + varbits_complen];
+    let mut forward = MsbReader::new(varbits, false);
+    let mut backward = MsbReader::new(varbits, true);
+    let mut decoded_intervals = Vec::with_capacity(num_lens);
+    for (i, lenlog2) in interval_lenlog2.iter().take(num_lens).enumerate() {
+        let reader = if i % 2 == 0 { &mut forward } else { &mut backward };
+        let n = *lenlog2 as u32;
+        decoded_intervals.push((1usize << n) | reader.read(n) as usize);
+    }
+
+    if interval_indexes[num_indexes - 1] != 0 {
+        return Err(invalid("Invalid multi-array block."));
+    }
+
+    let mut remaining: Vec<&[u8]> = entropy_arrays.iter().map(|array| array.as_slice()).collect();
+    let mut arrays = Vec::with_capacity(array_count);
+    let mut indi = 0;
+    let mut leni = 0;
+    let increment_leni = (q & 0x8000 != 0) as usize;
+
+    for _ in 0..array_count {
+        let mut array = Vec::new();
+        loop {
+            if indi >= num_indexes {
+                return Err(invalid("Invalid multi-array block."));
+            }
+            let source = interval_indexes[indi] as usize;
+            indi += 1;
+            if source == 0 {
+                break;
+            }
+            if source > num_arrays_in_file || leni >= num_lens {
+                return Err(invalid("Invalid multi-array block."));
+            }
+            let cur_len = decoded_intervals[leni];
+            leni += 1;
+            let block = remaining[source - 1];
+            if cur_len > block.len() || cur_len > dst_capacity - total_out {
+                return Err(invalid("Invalid multi-array interval."));
+            }
+            array.extend_from_slice(&block[..cur_len]);
+            remaining[source - 1] = &block[cur_len..];
+            total_out += cur_len;
+        }
+        leni += increment_leni;
+        arrays.push(array);
+    }
+
+    if indi != num_indexes || leni != num_lens || remaining.iter().any(|block| !block.is_empty()) {
+        return Err(invalid("Invalid multi-array block."));
+    }
+
+    Ok((arrays, pos + varbits_complen))
+}
+
+// MSB-first bit reader that walks bytes forwards or backwards, padding with zeros.
+struct MsbReader<'a> {
+    src: &'a [u8],
+    backwards: bool,
+    bit_position: usize,
+}
+
+impl<'a> MsbReader<'a> {
+    fn new(src: &'a [u8], backwards: bool) -> Self {
+        Self {
+            src,
+            backwards,
+            bit_position: 0,
+        }
+    }
+
+    fn read(&mut self, n: u32) -> u32 {
+        let mut value = 0u32;
+        for _ in 0..n {
+            let index = self.bit_position >> 3;
+            let byte = if index >= self.src.len() {
+                0
+            } else if self.backwards {
+                self.src[self.src.len() - 1 - index]
+            } else {
+                self.src[index]
+            };
+            value = (value << 1) | ((byte >> (7 - (self.bit_position & 7))) & 1) as u32;
+            self.bit_position += 1;
+        }
+        value
+    }
+}
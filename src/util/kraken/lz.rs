@@ -0,0 +1,337 @@
+use std::io::Error;
+
+use crate::util::kraken::bit_reader::BitReader;
+use crate::util::kraken::entropy::decode_bytes;
+use crate::util::kraken::invalid;
+
+// The decoded streams of a single Kraken LZ chunk.
+struct KrakenLzTable {
+    // Packed literal lengths, match lengths and which recent offset to use.
+    cmd_stream: Vec<u8>,
+    // Distances for the commands that do not reuse a recent offset, stored negated.
+    offs_stream: Vec<i32>,
+    // Literal bytes. All literal copying happens from here.
+    lit_stream: Vec<u8>,
+    // Literal and match lengths that do not fit in the command byte.
+    len_stream: Vec<i32>,
+}
+
+// Decodes a Kraken quantum of up to 256 KiB into `dst[start..end]`. Matches may reference
+// anything already written to `dst`.
+pub(crate) fn decode_quantum(dst: &mut [u8], start: usize, end: usize, src: &[u8]) -> Result<usize, Error> {
+    let mut pos = 0;
+    let mut dst_pos = start;
+
+    while dst_pos != end {
+        let dst_count = (end - dst_pos).min(0x20000);
+        if src.len() - pos < 4 {
+            return Err(invalid("Truncated Kraken chunk."));
+        }
+
+        let chunkhdr = (src[pos] as usize) << 16 | (src[pos + 1] as usize) << 8 | src[pos + 2] as usize;
+        let src_used;
+
+        if chunkhdr & 0x800000 == 0 {
+            // Stored as entropy without any match copying
+            let (decoded, used) = decode_bytes(&src[pos..], dst_count)?;
+            if decoded.len() != dst_count {
+                return Err(invalid("Kraken chunk size mismatch."));
+            }
+            dst[dst_pos..dst_pos + dst_count].copy_from_slice(&decoded);
+            src_used = used;
+        } else {
+            pos += 3;
+            src_used = chunkhdr & 0x7FFFF;
+            let mode = (chunkhdr >> 19) & 0xF;
+            if src.len() - pos < src_used {
+                return Err(invalid("Truncated Kraken chunk."));
+            }
+
+            if src_used < dst_count {
+                let chunk = &src[pos..pos + src_used];
+                let table = read_lz_table(mode, chunk, dst, dst_pos, dst_count)?;
+                process_lz_runs(mode, dst, dst_pos, dst_count, &table)?;
+            } else if src_used > dst_count || mode != 0 {
+                return Err(invalid("Invalid Kraken chunk."));
+            } else {
+                dst[dst_pos..dst_pos + dst_count].copy_from_slice(&src[pos..pos + dst_count]);
+            }
+        }
+
+        pos += src_used;
+        dst_pos += dst_count;
+    }
+
+    Ok(pos)
+}
+
+fn read_lz_table(mode: usize, src: &[u8], dst: &mut [u8], dst_pos: usize, dst_size: usize) -> Result<KrakenLzTable, Error> {
+    if mode > 1 {
+        return Err(invalid("Unknown Kraken LZ mode."));
+    }
+    if src.len() < 13 {
+        return Err(invalid("Truncated Kraken LZ table."));
+    }
+
+    let mut pos = 0;
+
+    // The very first eight bytes of the output are stored raw
+    if dst_pos == 0 {
+        dst[..8].copy_from_slice(&src[..8]);
+        pos += 8;
+    }
+
+    if src[pos] & 0x80 != 0 {
+        return Err(invalid("Kraken excess bytes are not supported."));
+    }
+
+    // Decode lit stream, bounded by dst_size
+    let (lit_stream, n) = decode_bytes(&src[pos..], dst_size)?;
+    pos += n;
+
+    // Decode command stream, bounded by dst_size
+    let (cmd_stream, n) = decode_bytes(&src[pos..], dst_size)?;
+    pos += n;
+
+    if src.len() - pos < 3 {
+        return Err(invalid("Truncated Kraken LZ table."));
+    }
+
+    let mut offs_scaling = 0;
+    let mut packed_offs_stream_extra = Vec::new();
+    let packed_offs_stream;
+
+    if src[pos] & 0x80 != 0 {
+        // Distances are coded with two tables
+        offs_scaling = src[pos] as i32 - 127;
+        pos += 1;
+
+        let (decoded, n) = decode_bytes(&src[pos..], cmd_stream.len())?;
+        packed_offs_stream = decoded;
+        pos += n;
+
+        if offs_scaling != 1 {
+            let (decoded, n) = decode_bytes(&src[pos..], packed_offs_stream.len())?;
+            if decoded.len() != packed_offs_stream.len() {
+                return Err(invalid("Kraken offset table size mismatch."));
+            }
+            packed_offs_stream_extra = decoded;
+            pos += n;
+        }
+    } else {
+        // Decode packed offset stream, it's bounded by the command length
+        let (decoded, n) = decode_bytes(&src[pos..], cmd_stream.len())?;
+        packed_offs_stream = decoded;
+        pos += n;
+    }
+
+    // Decode packed litlen stream, it's bounded by 1/4 of dst_size
+    let (packed_len_stream, n) = decode_bytes(&src[pos..], dst_size >> 2)?;
+    pos += n;
+
+    let (offs_stream, len_stream) = unpack_offsets(
+        &src[pos..],
+        &packed_offs_stream,
+        &packed_offs_stream_extra,
+        offs_scaling,
+        &packed_len_stream,
+    )?;
+
+    Ok(KrakenLzTable {
+        cmd_stream,
+        offs_stream,
+        lit_stream,
+        len_stream,
+    })
+}
+
+// Reads the variable length offsets and lengths from a forward and a backwards bit stream that
+// together fill the rest of the chunk.
+fn unpack_offsets(
+    src: &[u8],
+    packed_offs_stream: &[u8],
+    packed_offs_stream_extra: &[u8],
+    multi_dist_scale: i32,
+    packed_litlen_stream: &[u8],
+) -> Result<(Vec<i32>, Vec<i32>), Error> {
+    let mut bits_a = BitReader::new(src, 0, src.len());
+    let mut bits_b = BitReader::new_backwards(src, 0, src.len());
+
+    // Number of lengths too long for the litlen stream
+    if bits_b.bits < 0x2000 {
+        return Err(invalid("Invalid Kraken length count."));
+    }
+    let mut n = bits_b.leading_zeros();
+    bits_b.bitpos += n as i32;
+    bits_b.bits <<= n;
+    bits_b.refill_backwards();
+    n += 1;
+    let u32_len_stream_size = ((bits_b.bits >> (32 - n)) - 1) as usize;
+    bits_b.bitpos += n as i32;
+    bits_b.bits <<= n;
+    bits_b.refill_backwards();
+
+    let mut offs_stream = Vec::with_capacity(packed_offs_stream.len());
+
+    if multi_dist_scale == 0 {
+        // Traditional way of coding offsets
+        for (i, packed) in packed_offs_stream.iter().enumerate() {
+            let distance = if i % 2 == 0 {
+                bits_a.read_distance(*packed as u32)
+            } else {
+                bits_b.read_distance_backwards(*packed as u32)
+            };
+            offs_stream.push((distance as i32).wrapping_neg());
+        }
+    } else {
+        // New way of coding offsets
+        for (i, packed) in packed_offs_stream.iter().enumerate() {
+            let cmd = *packed as u32;
+            if (cmd >> 3) > 26 {
+                return Err(invalid("Invalid Kraken offset."));
+            }
+            let extra = if i % 2 == 0 {
+                bits_a.read_more_than_24_bits(cmd >> 3)
+            } else {
+                bits_b.read_more_than_24_bits_backwards(cmd >> 3)
+            };
+            let offs = ((8 + (cmd & 7)) << (cmd >> 3)) | extra;
+            offs_stream.push(8i32.wrapping_sub(offs as i32));
+        }
+
+        if multi_dist_scale != 1 {
+            for (offset, low_bits) in offs_stream.iter_mut().zip(packed_offs_stream_extra) {
+                *offset = multi_dist_scale.wrapping_mul(*offset).wrapping_sub(*low_bits as i32);
+            }
+        }
+    }
+
+    // Max count is 128 KiB / 256
+    if u32_len_stream_size > 512 {
+        return Err(invalid("Invalid Kraken length count."));
+    }
+
+    let mut u32_len_stream = Vec::with_capacity(u32_len_stream_size);
+    for i in 0..u32_len_stream_size {
+        let length = if i % 2 == 0 { bits_a.read_length() } else { bits_b.read_length_backwards() };
+        u32_len_stream.push(length.ok_or_else(|| invalid("Invalid Kraken length."))?);
+    }
+
+    if bits_a.position() != bits_b.position_backwards() {
+        return Err(invalid("Kraken offset stream size mismatch."));
+    }
+
+    let mut long_lengths = u32_len_stream.iter();
+    let mut len_stream = Vec::with_capacity(packed_litlen_stream.len());
+    for packed in packed_litlen_stream {
+        let mut v = *packed as u32;
+        if v == 255 {
+            v = long_lengths.next().ok_or_else(|| invalid("Kraken length stream exhausted."))?.wrapping_add(255);
+        }
+        len_stream.push(v.wrapping_add(3) as i32);
+    }
+    if long_lengths.next().is_some() {
+        return Err(invalid("Kraken length stream size mismatch."));
+    }
+
+    Ok((offs_stream, len_stream))
+}
+
+// Replays the commands of a chunk. Mode 0 adds each literal to the byte at the last match
+// offset, mode 1 stores literals as is.
+fn process_lz_runs(mode: usize, dst: &mut [u8], dst_pos: usize, dst_size: usize, table: &KrakenLzTable) -> Result<(), Error> {
+    let delta_literals = mode == 0;
+    let dst_end = dst_pos + dst_size;
+    let mut pos = dst_pos + if dst_pos == 0 { 8 } else { 0 };
+
+    let mut lit = 0;
+    let mut len_index = 0;
+    let mut offs_index = 0;
+    let mut recent_offs: [i32; 7] = [0, 0, 0, -8, -8, -8, 0];
+    let mut last_offset: i32 = -8;
+
+    let lit_stream = &table.lit_stream;
+    let next_len = |len_index: &mut usize| -> Result<usize, Error> {
+        let length = *table.len_stream.get(*len_index).ok_or_else(|| invalid("Kraken length stream exhausted."))?;
+        *len_index += 1;
+        Ok(length as usize)
+    };
+
+    for f in &table.cmd_stream {
+        let f = *f as usize;
+        let mut litlen = f & 3;
+        let recent_index = f >> 6;
+        let matchlen = (f >> 2) & 0xF;
+
+        if litlen == 3 {
+            litlen = next_len(&mut len_index)?;
+        }
+        recent_offs[6] = table.offs_stream.get(offs_index).copied().unwrap_or(0);
+
+        if litlen > dst_end - pos || litlen > lit_stream.len() - lit {
+            return Err(invalid("Kraken literal run does not fit."));
+        }
+        copy_literals(dst, pos, &lit_stream[lit..lit + litlen], last_offset, delta_literals)?;
+        pos += litlen;
+        lit += litlen;
+
+        // Move the chosen offset to the front of the recent offsets
+        let offset = recent_offs[recent_index + 3];
+        recent_offs[recent_index + 3] = recent_offs[recent_index + 2];
+        recent_offs[recent_index + 2] = recent_offs[recent_index + 1];
+        recent_offs[recent_index + 1] = recent_offs[recent_index];
+        recent_offs[3] = offset;
+        last_offset = offset;
+
+        if recent_index == 3 {
+            offs_index += 1;
+        }
+
+        let matchlen = if matchlen != 15 { matchlen + 2 } else { 14 + next_len(&mut len_index)? };
+        copy_match(dst, pos, dst_end, offset, matchlen)?;
+        pos += matchlen;
+    }
+
+    // Check for incorrect input
+    if offs_index != table.offs_stream.len() || len_index != table.len_stream.len() {
+        return Err(invalid("Kraken stream size mismatch."));
+    }
+
+    let final_len = dst_end - pos;
+    if final_len != lit_stream.len() - lit {
+        return Err(invalid("Kraken literal stream size mismatch."));
+    }
+    copy_literals(dst, pos, &lit_stream[lit..], last_offset, delta_literals)
+}
+
+pub(crate) fn copy_literals(dst: &mut [u8], pos: usize, literals: &[u8], last_offset: i32, delta: bool) -> Result<(), Error> {
+    if !delta {
+        dst[pos..pos + literals.len()].copy_from_slice(literals);
+        return Ok(());
+    }
+
+    if (pos as i64) + (last_offset as i64) < 0 {
+        return Err(invalid("Literal delta reaches before the start of the output."));
+    }
+    let base = (pos as i64 + last_offset as i64) as usize;
+    for (i, literal) in literals.iter().enumerate() {
+        dst[pos + i] = literal.wrapping_add(dst[base + i]);
+    }
+    Ok(())
+}
+
+// Copies an LZ match byte by byte so that overlapping matches repeat correctly.
+pub(crate) fn copy_match(dst: &mut [u8], pos: usize, dst_end: usize, offset: i32, length: usize) -> Result<(), Error> {
+    if offset >= 0 || (-(offset as i64)) as usize > pos {
+        return Err(invalid("Match offset reaches before the start of the output."));
+    }
+    if length > dst_end - pos {
+        return Err(invalid("Match runs past the end of the output."));
+    }
+
+    let source = pos - (-(offset as i64)) as usize;
+    for i in 0..length {
+        dst[pos + i] = dst[source + i];
+    }
+    Ok(())
+}
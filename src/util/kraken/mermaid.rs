@@ -0,0 +1,361 @@
+use std::io::Error;
+
+use crate::util::kraken::entropy::decode_bytes;
+use crate::util::kraken::invalid;
+use crate::util::kraken::lz::{copy_literals, copy_match};
+
+// The decoded streams of a single Mermaid/Selkie LZ chunk.
+struct MermaidLzTable {
+    lit_stream: Vec<u8>,
+    cmd_stream: Vec<u8>,
+    // Commands before this index belong to the first 64 KiB of the chunk.
+    cmd_stream_2_offs: usize,
+    off16_stream: Vec<u16>,
+    // Far offsets for the first and second 64 KiB, relative to the start of each half.
+    off32_stream_1: Vec<u32>,
+    off32_stream_2: Vec<u32>,
+    // Offset of the long length stream, which fills the rest of the chunk.
+    length_stream: usize,
+}
+
+// Decodes a Mermaid or Selkie quantum of up to 256 KiB into `dst[start..end]`.
+pub(crate) fn decode_quantum(dst: &mut [u8], start: usize, end: usize, src: &[u8]) -> Result<usize, Error> {
+    let mut pos = 0;
+    let mut dst_pos = start;
+
+    while dst_pos != end {
+        let dst_count = (end - dst_pos).min(0x20000);
+        if src.len() - pos < 4 {
+            return Err(invalid("Truncated Mermaid chunk."));
+        }
+
+        let chunkhdr = (src[pos] as usize) << 16 | (src[pos + 1] as usize) << 8 | src[pos + 2] as usize;
+        let src_used;
+
+        if chunkhdr & 0x800000 == 0 {
+            // Stored as entropy without any match copying
+            let (decoded, used) = decode_bytes(&src[pos..], dst_count)?;
+            if decoded.len() != dst_count {
+                return Err(invalid("Mermaid chunk size mismatch."));
+            }
+            dst[dst_pos..dst_pos + dst_count].copy_from_slice(&decoded);
+            src_used = used;
+        } else {
+            pos += 3;
+            src_used = chunkhdr & 0x7FFFF;
+            let mode = (chunkhdr >> 19) & 0xF;
+            if src.len() - pos < src_used {
+                return Err(invalid("Truncated Mermaid chunk."));
+            }
+
+            if src_used < dst_count {
+                let chunk = &src[pos..pos + src_used];
+                let table = read_lz_table(mode, chunk, dst, dst_pos, dst_count)?;
+                process_lz_runs(mode, chunk, dst, dst_pos, dst_count, table)?;
+            } else if src_used > dst_count || mode != 0 {
+                return Err(invalid("Invalid Mermaid chunk."));
+            } else {
+                dst[dst_pos..dst_pos + dst_count].copy_from_slice(&src[pos..pos + dst_count]);
+            }
+        }
+
+        pos += src_used;
+        dst_pos += dst_count;
+    }
+
+    Ok(pos)
+}
+
+fn read_lz_table(mode: usize, src: &[u8], dst: &mut [u8], dst_pos: usize, dst_size: usize) -> Result<MermaidLzTable, Error> {
+    if mode > 1 {
+        return Err(invalid("Unknown Mermaid LZ mode."));
+    }
+    if src.len() < 10 {
+        return Err(invalid("Truncated Mermaid LZ table."));
+    }
+
+    let mut pos = 0;
+
+    // The very first eight bytes of the output are stored raw
+    if dst_pos == 0 {
+        dst[..8].copy_from_slice(&src[..8]);
+        pos += 8;
+    }
+
+    // Decode lit stream
+    let (lit_stream, n) = decode_bytes(&src[pos..], dst_size)?;
+    pos += n;
+
+    // Decode flag stream
+    let (cmd_stream, n) = decode_bytes(&src[pos..], dst_size)?;
+    pos += n;
+
+    let read_u16 = |at: usize| -> Result<usize, Error> {
+        if src.len() < at + 2 {
+            return Err(invalid("Truncated Mermaid LZ table."));
+        }
+        Ok(u16::from_le_bytes([src[at], src[at + 1]]) as usize)
+    };
+
+    let cmd_stream_2_offs = if dst_size <= 0x10000 {
+        cmd_stream.len()
+    } else {
+        let offs = read_u16(pos)?;
+        pos += 2;
+        if offs > cmd_stream.len() {
+            return Err(invalid("Invalid Mermaid command split."));
+        }
+        offs
+    };
+
+    let off16_count = read_u16(pos)?;
+    let off16_stream = if off16_count == 0xFFFF {
+        // The 16 bit offsets are entropy coded as separate high and low bytes
+        pos += 2;
+        let (off16_hi, n) = decode_bytes(&src[pos..], dst_size >> 1)?;
+        pos += n;
+        let (off16_lo, n) = decode_bytes(&src[pos..], dst_size >> 1)?;
+        pos += n;
+
+        if off16_lo.len() != off16_hi.len() {
+            return Err(invalid("Mermaid offset table size mismatch."));
+        }
+        off16_lo.iter().zip(&off16_hi).map(|(lo, hi)| *lo as u16 | (*hi as u16) << 8).collect()
+    } else {
+        pos += 2;
+        if src.len() - pos < off16_count * 2 {
+            return Err(invalid("Truncated Mermaid LZ table."));
+        }
+        let stream = src[pos..pos + off16_count * 2]
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        pos += off16_count * 2;
+        stream
+    };
+
+    if src.len() - pos < 3 {
+        return Err(invalid("Truncated Mermaid LZ table."));
+    }
+    let tmp = src[pos] as usize | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+    pos += 3;
+
+    let mut off32_stream_1 = Vec::new();
+    let mut off32_stream_2 = Vec::new();
+
+    if tmp != 0 {
+        let mut off32_size_1 = tmp >> 12;
+        let mut off32_size_2 = tmp & 0xFFF;
+        if off32_size_1 == 4095 {
+            off32_size_1 = read_u16(pos)?;
+            pos += 2;
+        }
+        if off32_size_2 == 4095 {
+            off32_size_2 = read_u16(pos)?;
+            pos += 2;
+        }
+
+        pos += decode_far_offsets(&src[pos..], &mut off32_stream_1, off32_size_1, dst_pos)?;
+        pos += decode_far_offsets(&src[pos..], &mut off32_stream_2, off32_size_2, dst_pos + 0x10000)?;
+    }
+
+    Ok(MermaidLzTable {
+        lit_stream,
+        cmd_stream,
+        cmd_stream_2_offs,
+        off16_stream,
+        off32_stream_1,
+        off32_stream_2,
+        length_stream: pos,
+    })
+}
+
+// Far offsets are stored as 24 bit values, extended to 30 bits once the output is large enough.
+fn decode_far_offsets(src: &[u8], output: &mut Vec<u32>, output_size: usize, offset: usize) -> Result<usize, Error> {
+    let mut pos = 0;
+
+    for _ in 0..output_size {
+        if src.len() - pos < 3 {
+            return Err(invalid("Truncated Mermaid far offsets."));
+        }
+        let mut off = src[pos] as usize | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+        pos += 3;
+
+        if offset >= 0xC00000 - 1 && off >= 0xC00000 {
+            if pos == src.len() {
+                return Err(invalid("Truncated Mermaid far offsets."));
+            }
+            off += (src[pos] as usize) << 22;
+            pos += 1;
+        }
+
+        if off > offset {
+            return Err(invalid("Mermaid far offset reaches before the start of the output."));
+        }
+        output.push(off as u32);
+    }
+
+    Ok(pos)
+}
+
+// The chunk is processed as two halves of up to 64 KiB, each with its own far offsets.
+fn process_lz_runs(mode: usize, src: &[u8], dst: &mut [u8], dst_pos: usize, dst_size: usize, table: MermaidLzTable) -> Result<(), Error> {
+    let mut state = MermaidState {
+        delta_literals: mode == 0,
+        recent_offs: -8,
+        lit: 0,
+        off16: 0,
+        length_stream: table.length_stream,
+    };
+
+    let mut dst_cur = dst_pos;
+    let mut dst_remaining = dst_size;
+
+    for iteration in 0..2 {
+        let dst_size_cur = dst_remaining.min(0x10000);
+
+        let (commands, off32_stream) = if iteration == 0 {
+            (&table.cmd_stream[..table.cmd_stream_2_offs], &table.off32_stream_1)
+        } else {
+            (&table.cmd_stream[table.cmd_stream_2_offs..], &table.off32_stream_2)
+        };
+
+        let startoff = if dst_pos == 0 && iteration == 0 { 8 } else { 0 };
+        state.process(src, dst, dst_cur, dst_size_cur, startoff, commands, off32_stream, &table)?;
+
+        dst_cur += dst_size_cur;
+        dst_remaining -= dst_size_cur;
+        if dst_remaining == 0 {
+            break;
+        }
+    }
+
+    if state.length_stream != src.len() {
+        return Err(invalid("Mermaid length stream size mismatch."));
+    }
+
+    Ok(())
+}
+
+// Decoder state that carries over between the two halves of a chunk.
+struct MermaidState {
+    delta_literals: bool,
+    recent_offs: i32,
+    lit: usize,
+    off16: usize,
+    length_stream: usize,
+}
+
+impl MermaidState {
+    #[allow(clippy::too_many_arguments)]
+    fn process(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        dst_begin: usize,
+        dst_size: usize,
+        startoff: usize,
+        commands: &[u8],
+        off32_stream: &[u32],
+        table: &MermaidLzTable,
+    ) -> Result<(), Error> {
+        let dst_end = dst_begin + dst_size;
+        let mut pos = dst_begin + startoff;
+        let mut off32 = 0;
+
+        for cmd in commands {
+            let cmd = *cmd as usize;
+
+            if cmd >= 24 {
+                // Short literal run followed by a short match
+                let litlen = cmd & 7;
+                self.copy_literals(dst, pos, dst_end, litlen, table)?;
+                pos += litlen;
+
+                if cmd < 128 {
+                    let new_dist = *table.off16_stream.get(self.off16).ok_or_else(|| invalid("Mermaid offset stream exhausted."))?;
+                    self.recent_offs = -(new_dist as i32);
+                    self.off16 += 1;
+                }
+
+                let matchlen = (cmd >> 3) & 0xF;
+                copy_match(dst, pos, dst_end, self.recent_offs, matchlen)?;
+                pos += matchlen;
+            } else if cmd > 2 {
+                // Medium match with a far offset
+                let length = cmd + 5;
+                let far = *off32_stream.get(off32).ok_or_else(|| invalid("Mermaid far offset stream exhausted."))?;
+                off32 += 1;
+                self.recent_offs = far_offset(dst_begin, pos, far)?;
+                copy_match(dst, pos, dst_end, self.recent_offs, length)?;
+                pos += length;
+            } else if cmd == 0 {
+                // Long literal run
+                let length = self.read_length(src)? + 64;
+                self.copy_literals(dst, pos, dst_end, length, table)?;
+                pos += length;
+            } else if cmd == 1 {
+                // Long match with a near offset
+                let length = self.read_length(src)? + 91;
+                let new_dist = *table.off16_stream.get(self.off16).ok_or_else(|| invalid("Mermaid offset stream exhausted."))?;
+                self.off16 += 1;
+                self.recent_offs = -(new_dist as i32);
+                copy_match(dst, pos, dst_end, self.recent_offs, length)?;
+                pos += length;
+            } else {
+                // Long match with a far offset
+                let length = self.read_length(src)? + 29;
+                let far = *off32_stream.get(off32).ok_or_else(|| invalid("Mermaid far offset stream exhausted."))?;
+                off32 += 1;
+                self.recent_offs = far_offset(dst_begin, pos, far)?;
+                copy_match(dst, pos, dst_end, self.recent_offs, length)?;
+                pos += length;
+            }
+        }
+
+        if off32 != off32_stream.len() {
+            return Err(invalid("Mermaid far offset stream size mismatch."));
+        }
+
+        // Whatever is left of this half is literals
+        let length = dst_end - pos;
+        self.copy_literals(dst, pos, dst_end, length, table)
+    }
+
+    fn copy_literals(&mut self, dst: &mut [u8], pos: usize, dst_end: usize, length: usize, table: &MermaidLzTable) -> Result<(), Error> {
+        if length > dst_end - pos || length > table.lit_stream.len() - self.lit {
+            return Err(invalid("Mermaid literal run does not fit."));
+        }
+        copy_literals(dst, pos, &table.lit_stream[self.lit..self.lit + length], self.recent_offs, self.delta_literals)?;
+        self.lit += length;
+        Ok(())
+    }
+
+    // Lengths above 251 are extended by a 16 bit value.
+    fn read_length(&mut self, src: &[u8]) -> Result<usize, Error> {
+        let pos = self.length_stream;
+        if pos >= src.len() {
+            return Err(invalid("Mermaid length stream exhausted."));
+        }
+
+        let mut length = src[pos] as usize;
+        if length > 251 {
+            if src.len() - pos < 3 {
+                return Err(invalid("Mermaid length stream exhausted."));
+            }
+            length += u16::from_le_bytes([src[pos + 1], src[pos + 2]]) as usize * 4;
+            self.length_stream += 2;
+        }
+        self.length_stream += 1;
+        Ok(length)
+    }
+}
+
+// Far offsets are measured back from the start of the current half.
+fn far_offset(dst_begin: usize, pos: usize, far: u32) -> Result<i32, Error> {
+    let far = far as usize;
+    if far > dst_begin {
+        return Err(invalid("Mermaid far offset reaches before the start of the output."));
+    }
+    Ok(-((pos - (dst_begin - far)) as i64) as i32)
+}
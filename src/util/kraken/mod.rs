@@ -0,0 +1,141 @@
+mod bit_reader;
+mod entropy;
+mod lz;
+mod mermaid;
+
+use std::io::{Error, ErrorKind};
+
+use crate::util::oodle::{OodleCompressor, OodleLZ_CompressionLevel, OodleLZ_Compressor};
+
+// Every block is 256 KiB of output and starts with its own two byte header.
+const BLOCK_SIZE: usize = 0x40000;
+
+// Decoder types as stored in the block header.
+const DECODER_KRAKEN: u8 = 6;
+const DECODER_MERMAID: u8 = 10;
+const DECODER_LEVIATHAN: u8 = 12;
+
+pub(crate) fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Pure Rust decompressor for Oodle Kraken and Mermaid streams.
+///
+/// Used in place of the native oo2core library when it is not available. Leviathan and the older
+/// codecs are not supported. Selkie shares its block type with Mermaid and goes down the same path,
+/// but is not advertised as it has not been checked against real Selkie data. Checksums are not
+/// verified.
+pub struct Kraken {}
+
+impl OodleCompressor for Kraken {
+    fn decompress(&self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+        let mut dst = vec![0u8; uncompressed_size];
+        let mut src_pos = 0;
+        let mut dst_pos = 0;
+        let mut decoder_type = 0;
+        let mut use_checksums = false;
+
+        while dst_pos < uncompressed_size {
+            // Parse the block header
+            if dst_pos % BLOCK_SIZE == 0 {
+                if source.len() - src_pos < 2 {
+                    return Err(invalid("Truncated Kraken block header."));
+                }
+                let b0 = source[src_pos];
+                let b1 = source[src_pos + 1];
+                if b0 & 0xF != 0xC || (b0 >> 4) & 3 != 0 {
+                    return Err(invalid("Invalid Kraken block header."));
+                }
+                decoder_type = b1 & 0x7F;
+                use_checksums = b1 >> 7 != 0;
+                src_pos += 2;
+
+                if (b0 >> 6) & 1 != 0 {
+                    // The whole block is stored uncompressed
+                    let count = (uncompressed_size - dst_pos).min(BLOCK_SIZE);
+                    if source.len() - src_pos < count {
+                        return Err(invalid("Truncated Kraken block."));
+                    }
+                    dst[dst_pos..dst_pos + count].copy_from_slice(&source[src_pos..src_pos + count]);
+                    src_pos += count;
+                    dst_pos += count;
+                    continue;
+                }
+            }
+
+            let dst_bytes_left = (uncompressed_size - dst_pos).min(BLOCK_SIZE - dst_pos % BLOCK_SIZE);
+
+            // Parse the quantum header
+            if source.len() - src_pos < 3 {
+                return Err(invalid("Truncated Kraken quantum header."));
+            }
+            let v = (source[src_pos] as usize) << 16 | (source[src_pos + 1] as usize) << 8 | source[src_pos + 2] as usize;
+            let size = v & 0x3FFFF;
+
+            if size == 0x3FFFF {
+                // A quantum made of a single repeated byte
+                if v >> 18 != 1 || source.len() - src_pos < 4 {
+                    return Err(invalid("Invalid Kraken quantum header."));
+                }
+                dst[dst_pos..dst_pos + dst_bytes_left].fill(source[src_pos + 3]);
+                src_pos += 4;
+                dst_pos += dst_bytes_left;
+                continue;
+            }
+
+            src_pos += if use_checksums { 6 } else { 3 };
+            let compressed_size = size + 1;
+            if compressed_size > dst_bytes_left || source.len() < src_pos || source.len() - src_pos < compressed_size {
+                return Err(invalid("Invalid Kraken quantum size."));
+            }
+
+            let src = &source[src_pos..src_pos + compressed_size];
+            if compressed_size == dst_bytes_left {
+                dst[dst_pos..dst_pos + dst_bytes_left].copy_from_slice(src);
+            } else {
+                let end = dst_pos + dst_bytes_left;
+                let used = match decoder_type {
+                    DECODER_KRAKEN => lz::decode_quantum(&mut dst, dst_pos, end, src)?,
+                    DECODER_MERMAID => mermaid::decode_quantum(&mut dst, dst_pos, end, src)?,
+                    DECODER_LEVIATHAN => return Err(Error::new(ErrorKind::Unsupported, "Leviathan is not supported by the Kraken decoder.")),
+                    _ => return Err(Error::new(ErrorKind::Unsupported, "Unsupported Oodle decoder type.")),
+                };
+                if used != compressed_size {
+                    return Err(invalid("Kraken quantum size mismatch."));
+                }
+            }
+
+            src_pos += compressed_size;
+            dst_pos += dst_bytes_left;
+        }
+
+        Ok(dst)
+    }
+
+    fn compress(&self, _source: &[u8], compressor: OodleLZ_Compressor, _level: OodleLZ_CompressionLevel) -> Result<Vec<u8>, Error> {
+        Err(Error::new(ErrorKind::Unsupported, format!("The Kraken decoder cannot compress {:?}, a native oo2core is required.", compressor)))
+    }
+
+    fn supported_compressors(&self) -> Vec<OodleLZ_Compressor> {
+        Vec::new()
+    }
+
+    fn supported_decompressors(&self) -> Vec<OodleLZ_Compressor> {
+        vec![
+            OodleLZ_Compressor::OodleLZ_Compressor_Kraken,
+            OodleLZ_Compressor::OodleLZ_Compressor_Mermaid,
+        ]
+    }
+}
+
+impl Kraken {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for Kraken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,20 @@
 pub mod binary_reader;
+pub mod binary_writer;
+pub mod compressed;
+#[cfg(feature = "kraken")]
+pub mod kraken;
 pub mod mounted_souls_file;
 pub mod oodle;
 pub mod oodle26;
 pub mod oodle28;
+pub(crate) mod path;
+pub mod path_hash;
+pub mod rsa;
 pub mod sf_util;
 pub mod souls_file;
 
 pub use souls_file::SoulsFile;
-pub use mounted_souls_file::MountedSoulsFile;
\ No newline at end of file
+pub use mounted_souls_file::MountedSoulsFile;
+pub use compressed::Compressed;
+pub use path_hash::{normalize_path, path_hash_32, path_hash_64};
+pub use rsa::RsaPublicKey;
\ No newline at end of file
@@ -11,16 +11,16 @@ pub trait MountedSoulsFile: SoulsFile {
     fn is(&self, br: &mut BinaryReader) -> bool;
     fn read(file_path: &PathBuf) -> Self {
         // Create an instance of the specified type using the default constructor.
-        let format = Self::default();
+        let mut format = Self::default();
 
         // Read and initialize the instance from the provided file path.
         
-        MountedSoulsFile::common_read(&format,file_path);
+        MountedSoulsFile::common_read(&mut format, file_path);
 
         // Return the initialized instance.
         return format;
     }
-    fn common_read(&self, file_path: &PathBuf) {
+    fn common_read(&mut self, file_path: &PathBuf) {
         // Open the file and handle potential errors
         let file = File::open(file_path).expect("Failed to open file");
 
@@ -40,5 +40,5 @@ pub trait MountedSoulsFile: SoulsFile {
         // Delegate to the specific implementation for the provided reader
         MountedSoulsFile::specific_read(self, &mut br);
     }
-    fn specific_read(&self, br: &mut BinaryReader);
+    fn specific_read(&mut self, br: &mut BinaryReader);
 }
@@ -1,99 +1,438 @@
 use std::env;
-use std::io::Error;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use dlopen::wrapper::{Container, WrapperApi};
 use crate::util::oodle26::Oodle26;
 use crate::util::oodle28::Oodle28;
+#[cfg(feature = "kraken")]
+use crate::util::kraken::Kraken;
 
 
-pub trait OodleCompressor {
-    fn decompress(&mut self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error>;
+/// A loaded decompressor. Implementations are cheap to share and safe to use from many threads at once.
+pub trait OodleCompressor: Send + Sync {
+    fn decompress(&self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error>;
+
+    /// Compresses `source` with the given codec and level.
+    fn compress(&self, source: &[u8], compressor: OodleLZ_Compressor, level: OodleLZ_CompressionLevel) -> Result<Vec<u8>, Error>;
+
+    /// Codecs this backend can compress with. Native libraries find out by compressing a sample
+    /// with each codec, on the first call.
+    fn supported_compressors(&self) -> Vec<OodleLZ_Compressor>;
+
+    /// Codecs this backend can decompress.
+    fn supported_decompressors(&self) -> Vec<OodleLZ_Compressor>;
+}
+
+// The codecs shipped by every oo2core since 2.6, the older ones are decode only or removed.
+const MODERN_COMPRESSORS: [OodleLZ_Compressor; 5] = [
+    OodleLZ_Compressor::OodleLZ_Compressor_Kraken,
+    OodleLZ_Compressor::OodleLZ_Compressor_Mermaid,
+    OodleLZ_Compressor::OodleLZ_Compressor_Selkie,
+    OodleLZ_Compressor::OodleLZ_Compressor_Leviathan,
+    OodleLZ_Compressor::OodleLZ_Compressor_Hydra,
+];
+
+// Extra places to look for oo2core, configured by the caller.
+struct SearchPaths {
+    library_path: Option<PathBuf>,
+    game_directory: Option<PathBuf>,
 }
 
-static mut  OODLE6_EXISTS: bool = false;
-static mut  OODLE8_EXISTS: bool = false;
+static SEARCH_PATHS: Mutex<SearchPaths> = Mutex::new(SearchPaths {
+    library_path: None,
+    game_directory: None,
+});
+
+// Each library version is searched for and opened at most once per process. None means it was not
+// found, a load error is kept as its message so every caller gets the same answer.
+static OODLE6: OnceLock<Option<Result<Oodle26, String>>> = OnceLock::new();
+static OODLE8: OnceLock<Option<Result<Oodle28, String>>> = OnceLock::new();
+
+thread_local! {
+    // Scratch memory handed to OodleLZ_Decompress, so that it never allocates and
+    // concurrent calls on different threads never share state.
+    static DECODER_MEMORY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
 
 pub struct Oodle {
     
 }
 
 impl Oodle {
-    pub fn get_oodle_compressor(compression_level: i32) -> Result<Box<dyn OodleCompressor>, Error> {
-        
-        if compression_level != -1 {
+    /// Sets a file or directory that is searched for oo2core before any other location.
+    /// Must be called before the first decompression, the library is only looked up once.
+    pub fn set_library_path<P: Into<PathBuf>>(path: P) {
+        SEARCH_PATHS.lock().unwrap_or_else(PoisonError::into_inner).library_path = Some(path.into());
+    }
 
-            if compression_level == 9 {
+    /// Sets the game install directory, which usually ships its own copy of oo2core.
+    pub fn set_game_directory<P: Into<PathBuf>>(path: P) {
+        SEARCH_PATHS.lock().unwrap_or_else(PoisonError::into_inner).game_directory = Some(path.into());
+    }
 
-                if can_use_oodle8() {
-                    return Ok(Box::new(Oodle28::new()));
-                }
-                
-                if can_use_oodle6() {
-                    return Ok(Box::new(Oodle26::new()));
-                }
+    /// Returns a decompressor for DCX files. `compression_level` is the level byte from the DCX
+    /// header and only decides which oo2core version is preferred.
+    pub fn get_oodle_compressor(compression_level: i32) -> Result<Box<dyn OodleCompressor>, Error> {
 
-            }
-            else if compression_level == 6 {
-
-                if can_use_oodle6() {
-                    return Ok(Box::new(Oodle26::new()));
-                }
-                
-                if can_use_oodle8() {
-                    return Ok(Box::new(Oodle28::new()));
-                }
-            }
+        // Prefer the version that matches the compression level the file was written with,
+        // and the newest one for any other level
+        let versions: &[u32] = match compression_level {
+            6 | -1 => &[6, 8],
+            _ => &[8, 6],
+        };
+
+        let native = load_first(versions);
+        #[cfg(feature = "kraken")]
+        if native.is_err() {
+            return Ok(Box::new(Kraken::new()));
         }
-        else {
-            if can_use_oodle6() {
-                return Ok(Box::new(Oodle26::new()));
-            }
-            if can_use_oodle8() {
-                return Ok(Box::new(Oodle28::new()));
-            }
+        native
+    }
+
+    /// Returns the newest native oo2core found, for compressing or picking a specific codec.
+    /// Unlike `get_oodle_compressor` this never falls back to the pure Rust decoder.
+    pub fn get_native_compressor() -> Result<Box<dyn OodleCompressor>, Error> {
+        load_first(&[8, 6])
+    }
+}
+
+// Returns the first of the given oo2core versions that loads. A library that is found but fails
+// to load is skipped like a missing one, its error is only returned if no other version loads.
+fn load_first(versions: &[u32]) -> Result<Box<dyn OodleCompressor>, Error> {
+    let mut load_error = None;
+    for version in versions {
+        let loaded = match version {
+            6 => load_cached(&OODLE6, 6, Oodle26::new),
+            _ => load_cached(&OODLE8, 8, Oodle28::new),
+        };
+        match loaded {
+            Some(Ok(compressor)) => return Ok(compressor),
+            Some(Err(error)) => load_error = Some(error),
+            None => {}
         }
-        
-        return Err(Error::new(std::io::ErrorKind::NotFound, "Could not find a supported version of oo2core.\n \
-            Please copy oo2core_6_win64.dll or oo2core_8_win64.dll into the program directory"))
     }
+    Err(load_error.unwrap_or_else(not_found_error))
 }
 
-fn can_use_oodle6() -> bool {
-    unsafe {
-        if OODLE6_EXISTS  {
-            return true;
+// Lists every searched location, so the user knows where to put the library.
+fn not_found_error() -> Error {
+    let searched: Vec<String> = search_locations().iter().map(|location| format!("    {}", location.display())).collect();
+    Error::new(ErrorKind::NotFound, format!("Could not find a supported version of oo2core. Searched:\n{}\n\
+        Copy the oo2core library from the game directory into one of these locations or set OODLE_PATH.", searched.join("\n")))
+}
+
+// Returns a handle to the cached library of the given version, loading it on first use.
+fn load_cached<T: OodleCompressor + Clone + 'static>(
+    cell: &OnceLock<Option<Result<T, String>>>,
+    version: u32,
+    load: fn(&Path) -> Result<T, Error>,
+) -> Option<Result<Box<dyn OodleCompressor>, Error>> {
+    let loaded = cell.get_or_init(|| {
+        find_oodle(&search_locations(), version).map(|path| load(&path).map_err(|e| e.to_string()))
+    });
+
+    loaded.as_ref().map(|result| match result {
+        Ok(library) => Ok(Box::new(library.clone()) as Box<dyn OodleCompressor>),
+        Err(message) => Err(Error::other(message.clone())),
+    })
+}
+
+// Runs `f` with at least `size` bytes of this thread's decoder scratch memory.
+fn with_decoder_memory<R>(size: usize, f: impl FnOnce(&mut [u8]) -> R) -> R {
+    DECODER_MEMORY.with(|memory| {
+        let mut memory = memory.borrow_mut();
+        if memory.len() < size {
+            memory.resize(size, 0);
+        }
+        f(&mut memory[..size])
+    })
+}
+
+// Every file or directory that may hold oo2core, in search order.
+fn search_locations() -> Vec<PathBuf> {
+    let search_paths = SEARCH_PATHS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut locations = Vec::new();
+
+    if let Some(path) = &search_paths.library_path {
+        locations.push(path.clone());
+    }
+    if let Some(paths) = env::var_os("OODLE_PATH") {
+        locations.extend(env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty()));
+    }
+    if let Ok(current_dir) = env::current_dir() {
+        locations.push(current_dir);
+    }
+    if let Some(path) = &search_paths.game_directory {
+        locations.push(path.clone());
+    }
+    if let Ok(mut exe_dir) = env::current_exe() {
+        exe_dir.pop();
+        locations.push(exe_dir);
+    }
+
+    let mut unique = Vec::with_capacity(locations.len());
+    for location in locations {
+        if !unique.contains(&location) {
+            unique.push(location);
         }
-        
-        if let Ok(mut current_dir) = env::current_exe() {
-            current_dir.pop();
-            let current_dir_string = current_dir.to_str();
-            println!("{:?}",current_dir_string);
-            let oodle6 = current_dir.join("oo2core_6_win64.dll");
-            if oodle6.exists()  {
-                OODLE6_EXISTS = true;
-                return true;
+    }
+    unique
+}
+
+// Finds the first library of the given version. Files named explicitly are accepted for any
+// version if their name does not say otherwise.
+fn find_oodle(locations: &[PathBuf], version: u32) -> Option<PathBuf> {
+    for location in locations {
+        if location.is_file() {
+            match location.file_name().and_then(|name| name.to_str()).and_then(library_version) {
+                Some(found) if found != version => continue,
+                _ => return Some(location.clone()),
             }
         }
-        return false;
+
+        let Ok(entries) = fs::read_dir(location) else {
+            continue;
+        };
+
+        // Take the highest named match so that e.g. liboo2corelinux64.so.9.1 wins over .so.9
+        let mut matches: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).and_then(library_version) == Some(version))
+            .collect();
+        matches.sort();
+        if let Some(path) = matches.pop() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+// Parses the oo2core major version out of a library file name for the current platform.
+// Accepts oo2core_8_win64.dll, liboo2corelinux64.so.8 and liboo2coremac64.2.8.dylib style names.
+fn library_version(file_name: &str) -> Option<u32> {
+    let version = if cfg!(windows) {
+        file_name.strip_prefix("oo2core_")?.strip_suffix("_win64.dll")?
+    } else if cfg!(target_os = "macos") {
+        let rest = file_name.strip_prefix("liboo2coremac64.")?.strip_suffix(".dylib")?;
+        rest.strip_prefix("2.").unwrap_or(rest).split('.').next()?
+    } else {
+        file_name.strip_prefix("liboo2corelinux64.so.")?.split('.').next()?
+    };
+    version.parse().ok()
+}
+
+// Asks the library which codecs it can round trip, compressing a small sample with each.
+fn probe_compressors(compressor: &dyn OodleCompressor) -> Vec<OodleLZ_Compressor> {
+    let sample: Vec<u8> = (0..256u32).map(|i| (i * i / 7) as u8).collect();
+    MODERN_COMPRESSORS
+        .into_iter()
+        .filter(|codec| {
+            compressor
+                .compress(&sample, *codec, OodleLZ_CompressionLevel::OodleLZ_CompressionLevel_SuperFast)
+                .and_then(|compressed| compressor.decompress(&compressed, sample.len()))
+                .is_ok_and(|decompressed| decompressed == sample)
+        })
+        .collect()
+}
+
+// Rejects the sentinel values that only exist to size the C enums.
+fn check_compress_arguments(compressor: OodleLZ_Compressor, level: OodleLZ_CompressionLevel) -> Result<(), Error> {
+    use OodleLZ_CompressionLevel::*;
+    use OodleLZ_Compressor::*;
+
+    if matches!(compressor, OodleLZ_Compressor_Invalid | OodleLZ_Compressor_Count | OodleLZ_Compressor_Force32) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} is not a compressor.", compressor)));
+    }
+    if matches!(level, OodleLZ_CompressionLevel_Force32 | OodleLZ_CompressionLevel_Invalid) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} is not a compression level.", level)));
+    }
+    Ok(())
+}
+
+// Oodle returns the number of bytes written, or 0 on failure. Anything other than the expected
+// size means the data is corrupt, so the buffer is only returned trimmed to the exact length.
+fn check_decompressed_size(mut buffer: Vec<u8>, written: isize, uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+    if written <= 0 || written as usize != uncompressed_size {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "Oodle decompressed {} bytes, expected {}.", written.max(0), uncompressed_size)));
+    }
+    buffer.truncate(uncompressed_size);
+    Ok(buffer)
+}
+
+// Error for a library that was found but could not be opened or is missing symbols.
+fn load_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::other(format!("Could not load {}: {}", path.display(), error))
+}
+
+/// The oo2core calls whose signatures changed between versions, bound once per supported version.
+pub trait OodleVersionApi: WrapperApi + Send + Sync + 'static {
+    /// `OodleLZ_GetCompressedBufferSizeNeeded`, the buffer size compressing `raw_size` bytes may need.
+    fn compressed_buffer_size_needed(&self, compressor: OodleLZ_Compressor, raw_size: isize) -> isize;
+
+    /// `OodleLZ_GetDecodeBufferSize`, the buffer size decompressing to `raw_size` bytes may write to.
+    fn decode_buffer_size(&self, compressor: OodleLZ_Compressor, raw_size: isize, corruption_possible: bool) -> isize;
+}
+
+/// A native oo2core library. Only the calls in `V` differ between versions, see `Oodle26` and `Oodle28`.
+pub struct NativeOodle<V: OodleVersionApi> {
+    core: Arc<Container<ffi::OodleCoreApi>>,
+    version: Arc<Container<V>>,
+    decoder_memory_size: usize,
+    // Codecs that round trip, only probed when asked for.
+    compressors: Arc<OnceLock<Vec<OodleLZ_Compressor>>>,
+}
+
+impl<V: OodleVersionApi> NativeOodle<V> {
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        // Load by full path so the dynamic loader does not fall back to its own search paths
+        let core: Container<ffi::OodleCoreApi> = unsafe { Container::load(path) }.map_err(|e| load_error(path, e))?;
+        let version: Container<V> = unsafe { Container::load(path) }.map_err(|e| load_error(path, e))?;
+
+        // Enough scratch memory for any compressor and size
+        let decoder_memory_size = unsafe {
+            core.OodleLZDecoder_MemorySizeNeeded(OodleLZ_Compressor::OodleLZ_Compressor_Invalid, -1)
+        };
+
+        Ok(Self {
+            core: Arc::new(core),
+            version: Arc::new(version),
+            decoder_memory_size: decoder_memory_size.max(0) as usize,
+            compressors: Arc::default(),
+        })
     }
 }
 
-fn can_use_oodle8() -> bool {
-    unsafe {
-        if OODLE8_EXISTS {
-            return true;
+impl<V: OodleVersionApi> Clone for NativeOodle<V> {
+    fn clone(&self) -> Self {
+        Self {
+            core: Arc::clone(&self.core),
+            version: Arc::clone(&self.version),
+            decoder_memory_size: self.decoder_memory_size,
+            compressors: Arc::clone(&self.compressors),
         }
-        
-        if let Ok(mut current_dir) = env::current_exe() {
-            current_dir.pop();
-            let oodle8 = current_dir.join("oo2core_8_win64.dll");
-            if oodle8.exists()  {
-                OODLE8_EXISTS = true;
-                return true;
-            }
+    }
+}
+
+impl<V: OodleVersionApi> OodleCompressor for NativeOodle<V> {
+    fn decompress(&self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
+        let decoded_buffer_size = self.version.decode_buffer_size(
+            OodleLZ_Compressor::OodleLZ_Compressor_Kraken, uncompressed_size as isize, true);
+
+        // Allocate a destination buffer, Oodle may write slightly past the raw length
+        let mut raw_buf: Vec<u8> = vec![0; (decoded_buffer_size.max(0) as usize).max(uncompressed_size)];
+
+        let result = with_decoder_memory(self.decoder_memory_size, |decoder_memory| unsafe {
+            self.core.OodleLZ_Decompress(
+                source.as_ptr(),
+                source.len() as isize,
+                raw_buf.as_mut_ptr(),
+                uncompressed_size as isize,
+                FuzzSafe::OodleLZ_FuzzSafe_Yes,
+                OodleLZ_CheckCRC::OodleLZ_CheckCRC_No,
+                OodleLZ_Verbosity::OodleLZ_Verbosity_None,
+                0,
+                0,
+                0,
+                0,
+                decoder_memory.as_mut_ptr(),
+                decoder_memory.len() as isize,
+                OodleLZ_Decode_ThreadPhase::OodleLZ_Decode_ThreadPhaseAll,
+            )
+        });
+
+        check_decompressed_size(raw_buf, result, uncompressed_size)
+    }
+
+    fn compress(&self, source: &[u8], compressor: OodleLZ_Compressor, level: OodleLZ_CompressionLevel) -> Result<Vec<u8>, Error> {
+        check_compress_arguments(compressor, level)?;
+
+        let compressed_buffer_size = self.version.compressed_buffer_size_needed(compressor, source.len() as isize);
+        let mut comp_buf: Vec<u8> = vec![0; compressed_buffer_size.max(0) as usize];
+
+        // Default options, no dictionary and let Oodle allocate its own scratch memory
+        let result = unsafe {
+            self.core.OodleLZ_Compress(
+                compressor,
+                source.as_ptr(),
+                source.len() as isize,
+                comp_buf.as_mut_ptr(),
+                level,
+                0,
+                0,
+                0,
+                0,
+                0,
+            )
+        };
+
+        if result <= 0 || result as usize > comp_buf.len() {
+            return Err(Error::other(format!("Oodle could not compress with {:?}.", compressor)));
         }
-        return false;
+        comp_buf.truncate(result as usize);
+        Ok(comp_buf)
+    }
+
+    // Compressing a sample with each codec takes a moment, so it only happens on first use.
+    fn supported_compressors(&self) -> Vec<OodleLZ_Compressor> {
+        self.compressors.get_or_init(|| probe_compressors(self)).clone()
+    }
+
+    // Every oo2core since 2.6 decodes all of the modern codecs, whichever it can encode.
+    fn supported_decompressors(&self) -> Vec<OodleLZ_Compressor> {
+        MODERN_COMPRESSORS.to_vec()
     }
 }
 
+// The calls every supported oo2core version exports with the same signature.
+mod ffi {
+    // Bindings keep the C names from the Oodle headers.
+    #![allow(non_snake_case, clippy::too_many_arguments)]
+
+    use dlopen::wrapper::WrapperApi;
+    use dlopen_derive::WrapperApi;
+    use libc::c_int;
+    use super::*;
+
+    #[derive(WrapperApi)]
+    pub(super) struct OodleCoreApi {
+        OodleLZ_Decompress: unsafe extern "C" fn(
+            compBuf: *const u8,
+            compBufSize: isize,
+            rawBuf: *mut u8,
+            rawLen: isize,
+            fuzzSafe: FuzzSafe,
+            checkCRC: OodleLZ_CheckCRC,
+            verbosity: OodleLZ_Verbosity,
+            decBufBase: usize,
+            decBufSize: isize,
+            fpCallback: usize,
+            callbackUserData: usize,
+            decoderMemory: *mut u8,
+            decoderMemorySize: isize,
+            threadPhase: OodleLZ_Decode_ThreadPhase,
+        ) -> isize,
+
+        OodleLZ_Compress: unsafe extern "C" fn(
+            compressor: OodleLZ_Compressor,
+            rawBuf: *const u8,
+            rawLen: isize,
+            compBuf: *mut u8,
+            level: OodleLZ_CompressionLevel,
+            pOptions: usize,
+            dictionaryBase: usize,
+            lrm: usize,
+            scratchMem: usize,
+            scratchSize: isize,
+        ) -> isize,
+
+        OodleLZDecoder_MemorySizeNeeded: unsafe extern "C" fn(compressor: OodleLZ_Compressor, rawLen: isize) -> c_int,
+    }
+}
 
 #[repr(u32)]
 #[derive(Debug)]
@@ -104,9 +443,9 @@ pub(crate) enum FuzzSafe {
 }
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
-pub(crate) enum OodleLZ_CompressionLevel {
+pub enum OodleLZ_CompressionLevel {
     OodleLZ_CompressionLevel_None = 0,
     OodleLZ_CompressionLevel_SuperFast = 1,
     OodleLZ_CompressionLevel_VeryFast = 2,
@@ -129,9 +468,9 @@ pub(crate) enum OodleLZ_CompressionLevel {
 }
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
-pub(crate) enum OodleLZ_Compressor {
+pub enum OodleLZ_Compressor {
     OodleLZ_Compressor_Invalid = -1,
     OodleLZ_Compressor_None = 3,
 
@@ -1,79 +1,27 @@
-use dlopen::wrapper::{Container, WrapperApi};
-use dlopen_derive::WrapperApi;
-use crate::util::oodle::*;
+// Bindings keep the C names from the Oodle headers.
+#![allow(non_snake_case)]
 
-use std::io::Error;
-use libc::{c_ulong, c_int, c_uint};
+use dlopen::wrapper::WrapperApi;
+use dlopen_derive::WrapperApi;
+use libc::c_int;
+use crate::util::oodle::{NativeOodle, OodleLZ_Compressor, OodleVersionApi};
 
+/// The calls of oo2core 2.6, which size buffers for the worst case of any compressor.
 #[derive(WrapperApi)]
-#[allow(non_snake_case)]
-struct OodleApi {
-    OodleLZ_Decompress: unsafe extern "C" fn(
-        compBuf: *const u8,
-        compBufSize: usize,
-        rawBuf: *mut u8,
-        rawLen: usize,
-        fuzzSafe: FuzzSafe,
-        checkCRC: OodleLZ_CheckCRC,
-        verbosity: OodleLZ_Verbosity,
-        decBufBase: usize,
-        decBufSize: usize,
-        fpCallback: usize,
-        callbackUserData: usize,
-        decoderMemory: usize,
-        decoderMemorySize: usize,
-        threadPhase: OodleLZ_Decode_ThreadPhase,
-    ) -> usize,
+pub struct Oodle26Api {
+    OodleLZ_GetCompressedBufferSizeNeeded: unsafe extern "C" fn(rawSize: isize) -> isize,
 
-    OodleLZ_GetDecodeBufferSize: unsafe extern "C" fn(rawSize: c_ulong, corruptionPossible: c_int ) -> c_uint,
+    OodleLZ_GetDecodeBufferSize: unsafe extern "C" fn(rawSize: isize, corruptionPossible: c_int) -> isize,
 }
 
-pub struct Oodle26 {
-    container: Container<OodleApi>
-}
-
-impl OodleCompressor for Oodle26 {
-    fn decompress(&mut self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
-        
-        let decoded_buffer_size = unsafe {
-            self.container.OodleLZ_GetDecodeBufferSize(uncompressed_size as c_ulong, true as c_int)
-        };
-
-        // Allocate a destination buffer
-        let mut rawBuf: Vec<u8> = vec![0; decoded_buffer_size as usize];
-
-        // Decompress the data
-        let result = unsafe {
-            self.container.OodleLZ_Decompress(
-                source.as_ptr(),
-                source.len(),
-                rawBuf.as_mut_ptr(),
-                uncompressed_size ,
-                FuzzSafe::OodleLZ_FuzzSafe_Yes,
-                OodleLZ_CheckCRC::OodleLZ_CheckCRC_No ,
-                OodleLZ_Verbosity::OodleLZ_Verbosity_None ,
-                0,
-                0 ,
-                0,
-                0,
-                0,
-                0 ,
-                OodleLZ_Decode_ThreadPhase::OodleLZ_Decode_ThreadPhaseAll ,
-            )
-        };
+impl OodleVersionApi for Oodle26Api {
+    fn compressed_buffer_size_needed(&self, _compressor: OodleLZ_Compressor, raw_size: isize) -> isize {
+        unsafe { self.OodleLZ_GetCompressedBufferSizeNeeded(raw_size) }
+    }
 
-        Ok(rawBuf)
+    fn decode_buffer_size(&self, _compressor: OodleLZ_Compressor, raw_size: isize, corruption_possible: bool) -> isize {
+        unsafe { self.OodleLZ_GetDecodeBufferSize(raw_size, corruption_possible as c_int) }
     }
 }
 
-impl Oodle26 {
-    pub fn new() -> Self {
-        let mut cont: Container<OodleApi> =
-        unsafe { Container::load("oo2core_6_win64.dll") }.expect("Could not open oo2core_6_win64 or load symbols");
-
-        Self {
-            container: cont
-        }
-        
-    }
-}
\ No newline at end of file
+pub type Oodle26 = NativeOodle<Oodle26Api>;
@@ -1,50 +1,27 @@
-use dlopen::wrapper::{Container, WrapperApi};
-use dlopen_derive::WrapperApi;
-use crate::util::oodle::*;
+// Bindings keep the C names from the Oodle headers.
+#![allow(non_snake_case)]
 
-use std::io::Error;
-use libc::{c_ulong, c_int, c_uint};
+use dlopen::wrapper::WrapperApi;
+use dlopen_derive::WrapperApi;
+use libc::c_int;
+use crate::util::oodle::{NativeOodle, OodleLZ_Compressor, OodleVersionApi};
 
+/// The calls of oo2core 2.8, which size buffers for the given compressor.
 #[derive(WrapperApi)]
-struct OodleApi {
-    OodleLZ_Decompress: unsafe extern "C" fn(
-        compBuf: *const u8,
-        compBufSize: usize,
-        rawBuf: *mut u8,
-        rawLen: usize,
-        fuzzSafe: FuzzSafe,
-        checkCRC: OodleLZ_CheckCRC,
-        verbosity: OodleLZ_Verbosity,
-        decBufBase: usize,
-        decBufSize: usize,
-        fpCallback: usize,
-        callbackUserData: usize,
-        decoderMemory: usize,
-        decoderMemorySize: usize,
-        threadPhase: OodleLZ_Decode_ThreadPhase,
-    ) -> usize,
+pub struct Oodle28Api {
+    OodleLZ_GetCompressedBufferSizeNeeded: unsafe extern "C" fn(compressor: OodleLZ_Compressor, rawSize: isize) -> isize,
 
-    OodleLZ_GetDecodeBufferSize: unsafe extern "C" fn(rawSize: c_ulong, corruptionPossible: c_int ) -> c_uint,
+    OodleLZ_GetDecodeBufferSize: unsafe extern "C" fn(compressor: OodleLZ_Compressor, rawSize: isize, corruptionPossible: c_int) -> isize,
 }
 
-pub struct Oodle28 {
-    container: Container<OodleApi>
-}
+impl OodleVersionApi for Oodle28Api {
+    fn compressed_buffer_size_needed(&self, compressor: OodleLZ_Compressor, raw_size: isize) -> isize {
+        unsafe { self.OodleLZ_GetCompressedBufferSizeNeeded(compressor, raw_size) }
+    }
 
-impl OodleCompressor for Oodle28 {
-    fn decompress(&mut self, source: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, Error> {
-        todo!()
+    fn decode_buffer_size(&self, compressor: OodleLZ_Compressor, raw_size: isize, corruption_possible: bool) -> isize {
+        unsafe { self.OodleLZ_GetDecodeBufferSize(compressor, raw_size, corruption_possible as c_int) }
     }
 }
 
-impl Oodle28 {
-    pub fn new() -> Self {
-        let mut cont: Container<OodleApi> =
-        unsafe { Container::load("oo2core_8_win64.dll") }.expect("Could not open oo2core_8_win64 or load symbols");
-
-        Self {
-            container: cont
-        }
-        
-    }
-}
\ No newline at end of file
+pub type Oodle28 = NativeOodle<Oodle28Api>;
@@ -0,0 +1,12 @@
+use std::path::{Component, Path, PathBuf};
+
+// Turns a game path or entry name into a relative path that cannot leave the directory it is joined to.
+pub(crate) fn relative_path(path: &str) -> PathBuf {
+    Path::new(&path.replace('\\', "/"))
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,15 @@
+/// Brings a path into the form it is hashed in: trimmed, lowercase, with forward slashes and a leading slash.
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim().replace('\\', "/").to_lowercase();
+    if path.starts_with('/') { path } else { format!("/{}", path) }
+}
+
+/// The path hash used by BND4 hash tables and by BHD5 archives before Elden Ring.
+pub fn path_hash_32(path: &str) -> u32 {
+    normalize_path(path).encode_utf16().fold(0u32, |hash, c| hash.wrapping_mul(37).wrapping_add(c as u32))
+}
+
+/// The path hash used by Elden Ring BHD5 archives.
+pub fn path_hash_64(path: &str) -> u64 {
+    normalize_path(path).encode_utf16().fold(0u64, |hash, c| hash.wrapping_mul(133).wrapping_add(c as u64))
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use num_bigint::BigUint;
+
+/// An RSA public key, used to decrypt the headers of retail dvdbnd archives.
+///
+/// The games encrypt each BHD5 with the private half of a per-archive key, so the public key is
+/// enough to read them. Keys are not shipped with this crate and have to be supplied by the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RsaPublicKey {
+    modulus: BigUint,
+    exponent: BigUint,
+}
+
+impl RsaPublicKey {
+    pub fn new(modulus: &[u8], exponent: &[u8]) -> Result<Self, Error> {
+        let key = Self {
+            modulus: BigUint::from_bytes_be(modulus),
+            exponent: BigUint::from_bytes_be(exponent),
+        };
+        if key.size() < 2 {
+            return Err(invalid("RSA modulus is too small."));
+        }
+        Ok(key)
+    }
+
+    /// Reads a PEM file containing an RSA PUBLIC KEY (PKCS#1) or PUBLIC KEY (X.509) block.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_pem(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let begin = pem.find("-----BEGIN ").ok_or_else(|| invalid("No PEM block found."))?;
+        let label_start = begin + "-----BEGIN ".len();
+        let label_end = pem[label_start..].find("-----").map(|end| label_start + end).ok_or_else(|| invalid("Malformed PEM header."))?;
+        let label = &pem[label_start..label_end];
+        let footer = format!("-----END {}-----", label);
+        let body_end = pem[label_end..].find(&footer).map(|end| label_end + end).ok_or_else(|| invalid("PEM block is not terminated."))?;
+
+        let body: String = pem[label_end + 5..body_end].chars().filter(|c| !c.is_whitespace()).collect();
+        let der = STANDARD.decode(body).map_err(|e| invalid(&format!("Invalid PEM base64: {}", e)))?;
+
+        match label {
+            "RSA PUBLIC KEY" => Self::from_pkcs1_der(&der),
+            "PUBLIC KEY" => Self::from_spki_der(&der),
+            _ => Err(invalid(&format!("Unsupported PEM block: {}", label))),
+        }
+    }
+
+    /// Parses an RSAPublicKey structure, a sequence of the modulus and the exponent.
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<Self, Error> {
+        let mut der = DerReader::new(DerReader::new(der).read(TAG_SEQUENCE)?);
+        let modulus = der.read(TAG_INTEGER)?;
+        let exponent = der.read(TAG_INTEGER)?;
+        Self::new(modulus, exponent)
+    }
+
+    /// Parses a SubjectPublicKeyInfo structure wrapping an RSAPublicKey.
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        let mut der = DerReader::new(DerReader::new(der).read(TAG_SEQUENCE)?);
+        der.read(TAG_SEQUENCE)?; // Algorithm identifier
+        let key = der.read(TAG_BIT_STRING)?;
+        match key.split_first() {
+            Some((0, key)) => Self::from_pkcs1_der(key),
+            _ => Err(invalid("Public key bit string has unused bits.")),
+        }
+    }
+
+    /// Size of the modulus in bytes, which is also the size of an encrypted block.
+    pub fn size(&self) -> usize {
+        self.modulus.bits().div_ceil(8) as usize
+    }
+
+    /// Decrypts `data` block by block. Every block of `size()` bytes decrypts to `size() - 1` bytes.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let input_size = self.size();
+        let output_size = input_size - 1;
+        if !data.len().is_multiple_of(input_size) {
+            return Err(invalid(&format!("RSA data size {:#x} is not a multiple of the key size {:#x}.", data.len(), input_size)));
+        }
+
+        let mut output = Vec::with_capacity(data.len() / input_size * output_size);
+        for block in data.chunks_exact(input_size) {
+            let value = BigUint::from_bytes_be(block);
+            if value >= self.modulus {
+                return Err(invalid("RSA block is larger than the modulus, the key does not match."));
+            }
+
+            let plain = value.modpow(&self.exponent, &self.modulus).to_bytes_be();
+            if plain.len() > output_size {
+                return Err(invalid("RSA block does not fit the output block, the key does not match."));
+            }
+            output.resize(output.len() + output_size - plain.len(), 0);
+            output.extend_from_slice(&plain);
+        }
+
+        Ok(output)
+    }
+}
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_SEQUENCE: u8 = 0x30;
+
+// Reads the few DER elements a public key is made of.
+struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    // Reads an element with the given tag and returns its contents.
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        let (&actual, rest) = self.data.split_first().ok_or_else(|| invalid("Public key is cut off."))?;
+        if actual != tag {
+            return Err(invalid(&format!("Expected DER tag {:#04x} in public key, found {:#04x}.", tag, actual)));
+        }
+
+        let (&first, mut rest) = rest.split_first().ok_or_else(|| invalid("Public key is cut off."))?;
+        let length = if first < 0x80 {
+            first as usize
+        } else {
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(invalid("Invalid DER length in public key."));
+            }
+            let length = rest[..count].iter().fold(0, |length, &b| (length << 8) | b as usize);
+            rest = &rest[count..];
+            length
+        };
+
+        if rest.len() < length {
+            return Err(invalid("Public key is cut off."));
+        }
+        let (contents, rest) = rest.split_at(length);
+        self.data = rest;
+        Ok(contents)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
@@ -1,5 +1,7 @@
-use std::io::{Cursor, Read, Error};
+use std::io::{Read, Write, Error, ErrorKind};
+use flate2::Compression;
 use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
 
 use crate::formats::{DCX, CompressionType};
 use crate::util::binary_reader::BinaryReader;
@@ -10,42 +12,57 @@ pub(crate) struct SFUtil {
 
 impl SFUtil {
     pub(crate) fn decompress_if_neccessary(br: &mut BinaryReader, compression: &mut CompressionType) -> Result<(), Error>{
-        if DCX::is(br) {
+        if DCX::is(br) || SFUtil::is_zlib(br) {
             let bytes = DCX::decompress(br, compression)?;
             br.memory = bytes;
-            println!("It is a DCX file!");
-
+            br.position = 0;
+            br.big_endian = false;
         } else {
-            println!("It is not a DCX file!");
             *compression = CompressionType::None;
         };
 
         Ok(())
     }
 
+    /// Applies the given compression to `data`, the inverse of `decompress_if_neccessary`.
+    /// `compression_level` is the level from `DCX::compression_level`, which for zlib is the format
+    /// byte to write again. Zlib streams without one are written with 0xDA.
+    pub(crate) fn compress(data: &[u8], compression: CompressionType, compression_level: u8) -> Result<Vec<u8>, Error> {
+        match compression {
+            CompressionType::None => Ok(data.to_vec()),
+            CompressionType::Zlib => SFUtil::write_zlib(data, if compression_level == 0 { 0xDA } else { compression_level }),
+            CompressionType::Unknown => Err(Error::new(ErrorKind::InvalidInput, "Cannot write an unknown compression type.")),
+            _ => DCX::compress(data, compression, compression_level),
+        }
+    }
+
+    // A bare zlib stream starts with 0x78 followed by one of the four standard level bytes.
+    pub(crate) fn is_zlib(br: &BinaryReader) -> bool {
+        br.memory.len() >= 2 && br.memory[0] == 0x78 && matches!(br.memory[1], 0x01 | 0x5E | 0x9C | 0xDA)
+    }
+
     pub(crate) fn read_zlib(br: &mut BinaryReader, compression_size: usize) -> Result<Vec<u8>, Error> {
-        // Ensure the first two bytes match the Zlib compression header.
-        br.assert_byte(&[0x78]);
-        br.assert_byte(&[0x01, 0x5E, 0x9C, 0xDA]);
-    
-        // Read the compressed data.
-        let compressed = br.read_bytes(compression_size - 2);
-    
-        // Create a Cursor from the compressed data.
-        let compressed_stream = Cursor::new(compressed);
-    
-        // Create a Vec to store the decompressed data.
+        // The decoder checks the header itself and the Adler-32 checksum at the end of the stream.
+        let compressed: &[u8] = br.read_span_view(compression_size)?;
         let mut decompressed_data = Vec::new();
-    
-        // Create a ZlibDecoder to handle the decompression.
-        let mut decoder = ZlibDecoder::new(compressed_stream);
-    
-        // Use the ZlibDecoder to copy the decompressed data to the Vec.
-        decoder
-            .read_to_end(&mut decompressed_data)?;
-    
-        // Return the decompressed data as a Vec<u8>.
-        return Ok(decompressed_data);
+        ZlibDecoder::new(compressed).read_to_end(&mut decompressed_data)?;
+        Ok(decompressed_data)
+    }
+
+    /// Deflates `data` into a zlib stream whose second header byte is `format_byte`.
+    /// The level is chosen so the encoder writes that same byte back.
+    pub(crate) fn write_zlib(data: &[u8], format_byte: u8) -> Result<Vec<u8>, Error> {
+        let level = match format_byte {
+            0x01 => Compression::fast(),
+            0x5E => Compression::new(3),
+            0x9C => Compression::default(),
+            0xDA => Compression::best(),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown zlib format byte {:#04X}.", format_byte))),
+        };
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), level);
+        encoder.write_all(data)?;
+        encoder.finish()
     }
 
 }
\ No newline at end of file
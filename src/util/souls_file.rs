@@ -1,8 +1,9 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Error, ErrorKind, Read};
 use std::path::PathBuf;
 use crate::util::sf_util::SFUtil;
 use crate::util::binary_reader::BinaryReader;
+use crate::util::binary_writer::BinaryWriter;
 use crate::formats::CompressionType;
 
 // Common functions for all souls filetypes
@@ -10,7 +11,7 @@ pub trait SoulsFile: Default {
     fn is(&self, br: &mut BinaryReader) -> bool;
     fn read(file_path: &PathBuf) -> Self {
         // Create an instance of the specified type using the default constructor.
-        let format = Self::default();
+        let mut format = Self::default();
 
         // Read and initialize the instance from the provided file path.
         format.common_read(file_path);
@@ -18,7 +19,7 @@ pub trait SoulsFile: Default {
         // Return the initialized instance.
         return format;
     }
-    fn common_read(&self, file_path: &PathBuf) {
+    fn common_read(&mut self, file_path: &PathBuf) {
         // Open the file and handle potential errors
         let file = File::open(file_path).expect("Failed to open file");
 
@@ -36,7 +37,11 @@ pub trait SoulsFile: Default {
         SFUtil::decompress_if_neccessary(&mut br, &mut compression).expect("Error in decompressing!");
 
         // Delegate to the specific implementation for the provided reader
-        self.specific_read(&mut br);
+        self.specific_read(&mut br).expect("Error reading file!");
+    }
+    fn specific_read(&mut self, br: &mut BinaryReader) -> Result<(), Error>;
+    // Serializes the file without any compression. Formats that can be written override this.
+    fn specific_write(&self, _bw: &mut BinaryWriter) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported, "Writing this format is not supported."))
     }
-    fn specific_read(&self, br: &mut BinaryReader);
 }
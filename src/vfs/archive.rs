@@ -0,0 +1,88 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use crate::formats::bhd5::{find_archives, read_archive_header, FileHeader, Game, BHD5};
+use crate::formats::{ArchiveDictionary, BDT};
+use crate::util::path_hash::normalize_path;
+use crate::vfs::{split_path, VfsKind, VfsMetadata, VfsRoot};
+
+/// The dvdbnd archives of a game install.
+///
+/// Files are found by hashing their path, so any path can be read whether or not the dictionary
+/// knows it, but only paths in the dictionary show up when listing directories.
+pub struct ArchiveRoot {
+    pub game: Game,
+    archives: Vec<(BHD5, Mutex<BDT<BufReader<File>>>)>,
+    // Children of every directory the dictionary names, keyed by the normalized directory path.
+    directories: HashMap<String, BTreeSet<String>>,
+}
+
+impl ArchiveRoot {
+    /// Opens every archive in `install_dir`. Encrypted headers need a key named after them in `key_directory`.
+    pub fn open<P: AsRef<Path>>(install_dir: P, game: Game, dictionary: &ArchiveDictionary, key_directory: Option<&Path>) -> Result<Self, Error> {
+        let mut paths = Vec::new();
+        find_archives(install_dir.as_ref(), None, &mut paths)?;
+        paths.sort();
+
+        let mut archives = Vec::with_capacity(paths.len());
+        for bhd_path in paths {
+            let bhd = read_archive_header(&bhd_path, game, key_directory)?;
+            archives.push((bhd, BDT::open(bhd_path.with_extension("bdt"))?));
+        }
+        Ok(Self::new(game, archives, dictionary))
+    }
+
+    pub fn new(game: Game, archives: Vec<(BHD5, BDT<BufReader<File>>)>, dictionary: &ArchiveDictionary) -> Self {
+        let mut directories: HashMap<String, BTreeSet<String>> = HashMap::new();
+        directories.entry("/".to_string()).or_default();
+        for (bhd, _) in &archives {
+            for named in dictionary.resolve(bhd).named {
+                let components = split_path(named.name);
+                let mut directory = String::from("/");
+                for (i, component) in components.iter().enumerate() {
+                    directories.entry(directory.clone()).or_default().insert(component.to_string());
+                    if i + 1 < components.len() {
+                        directory = format!("{}{}/", directory, component);
+                    }
+                }
+            }
+        }
+
+        let archives = archives.into_iter().map(|(bhd, bdt)| (bhd, Mutex::new(bdt))).collect();
+        Self { game, archives, directories }
+    }
+
+    fn find(&self, path: &[&str]) -> Option<(&FileHeader, &Mutex<BDT<BufReader<File>>>)> {
+        let hash = self.game.path_hash(&path.join("/"));
+        self.archives.iter().find_map(|(bhd, bdt)| bhd.file(hash).map(|header| (header, bdt)))
+    }
+}
+
+impl VfsRoot for ArchiveRoot {
+    fn metadata(&self, path: &[&str]) -> Option<VfsMetadata> {
+        if self.directories.contains_key(&directory_key(path)) {
+            return Some(VfsMetadata { kind: VfsKind::Directory, len: 0 });
+        }
+        self.find(path).map(|(header, _)| VfsMetadata { kind: VfsKind::File, len: header.file_size() })
+    }
+
+    fn read(&self, path: &[&str]) -> Result<Vec<u8>, Error> {
+        let (header, bdt) = self.find(path).ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} does not exist.", path.join("/"))))?;
+        bdt.lock().unwrap_or_else(PoisonError::into_inner).read_file(header)
+    }
+
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<String>, Error> {
+        self.directories
+            .get(&directory_key(path))
+            .map(|children| children.iter().cloned().collect())
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} is not a known directory.", path.join("/"))))
+    }
+}
+
+// Directories are keyed like `/chr/`, matching the form dictionary paths are normalized to.
+fn directory_key(path: &[&str]) -> String {
+    let key = normalize_path(&path.join("/"));
+    if key.ends_with('/') { key } else { format!("{}/", key) }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use crate::vfs::{VfsKind, VfsMetadata, VfsRoot};
+
+/// Loose files in a directory on disk, such as an unpacked game or a mod folder.
+///
+/// Components that do not exist with the exact case given are matched ignoring case, so game
+/// paths resolve on case-sensitive file systems too.
+pub struct DirectoryRoot {
+    pub path: PathBuf,
+}
+
+impl DirectoryRoot {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn find(&self, path: &[&str]) -> Option<PathBuf> {
+        let mut found = self.path.clone();
+        for component in path {
+            // Paths may not leave the directory
+            if *component == "." || *component == ".." {
+                return None;
+            }
+            let exact = found.join(component);
+            found = if exact.exists() { exact } else { find_ignoring_case(&found, component)? };
+        }
+        Some(found)
+    }
+}
+
+impl VfsRoot for DirectoryRoot {
+    fn metadata(&self, path: &[&str]) -> Option<VfsMetadata> {
+        let metadata = fs::metadata(self.find(path)?).ok()?;
+        Some(if metadata.is_dir() {
+            VfsMetadata { kind: VfsKind::Directory, len: 0 }
+        } else {
+            VfsMetadata { kind: VfsKind::File, len: metadata.len() }
+        })
+    }
+
+    fn read(&self, path: &[&str]) -> Result<Vec<u8>, Error> {
+        fs::read(self.find(path).ok_or_else(|| not_found(path))?)
+    }
+
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.find(path).ok_or_else(|| not_found(path))?)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn find_ignoring_case(directory: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        .map(|entry| entry.path())
+}
+
+fn not_found(path: &[&str]) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{} does not exist.", path.join("/")))
+}
@@ -0,0 +1,253 @@
+pub mod archive;
+pub mod directory;
+pub mod overlay;
+
+use std::io::{Cursor, Error, ErrorKind};
+use crate::formats::{AnyBinder, CompressionType, BXF3, BXF4, TPF};
+use crate::util::binary_reader::BinaryReader;
+use crate::util::sf_util::SFUtil;
+use crate::util::SoulsFile;
+
+pub use archive::ArchiveRoot;
+pub use directory::DirectoryRoot;
+pub use overlay::OverlayRoot;
+
+/// Whether a path names a directory of the root or a file, which may itself be a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfsKind {
+    Directory,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VfsMetadata {
+    pub kind: VfsKind,
+    /// Size of the data `Vfs::read` returns, 0 for directories. That is the stored size for files
+    /// of the root, DCX compression included, but the decompressed size for binder entries.
+    pub len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VfsDirEntry {
+    pub name: String,
+    pub kind: VfsKind,
+}
+
+/// The top level of a virtual file system, such as a directory on disk or the game archives.
+///
+/// Paths are passed as their components, in the case the caller used.
+pub trait VfsRoot {
+    fn metadata(&self, path: &[&str]) -> Option<VfsMetadata>;
+    fn read(&self, path: &[&str]) -> Result<Vec<u8>, Error>;
+    /// Lists the names of the children of a directory.
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<String>, Error>;
+    /// The name of the layer that supplies a path, for roots made of several layers.
+    fn layer_name(&self, _path: &[&str]) -> Option<&str> {
+        None
+    }
+}
+
+/// A file system that continues into DCX files, binders, split binders and TPFs.
+///
+/// A path such as `parts/am_m_1000.partsbnd.dcx/am_m_1000.tpf/am_m_1000_a` is followed through the
+/// root until it reaches a file, after which every further component names an entry of the
+/// container before it. Entries are matched by their file name, ignoring case, and the data file
+/// of a split binder is looked up next to its header, in the same container or any outer level.
+pub struct Vfs {
+    root: Box<dyn VfsRoot>,
+}
+
+// A file reached while resolving a path, with the containers it was found in.
+struct Resolved<'a> {
+    // Components of the last directory of the root on the way.
+    directory: Vec<&'a str>,
+    // The containers entered after the directory, outermost first.
+    containers: Vec<Vec<ContainerEntry>>,
+    file: Option<ContainerEntry>,
+}
+
+#[derive(Clone)]
+struct ContainerEntry {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Vfs {
+    pub fn new<R: VfsRoot + 'static>(root: R) -> Self {
+        Self { root: Box::new(root) }
+    }
+
+    /// Opens a file for reading. Files are returned as stored, so a `.dcx` file is still compressed.
+    pub fn open(&self, path: &str) -> Result<Cursor<Vec<u8>>, Error> {
+        Ok(Cursor::new(self.read(path)?))
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        match self.resolve(path)?.file {
+            Some(file) => Ok(file.data),
+            None => Err(Error::new(ErrorKind::InvalidInput, format!("{} is a directory.", path))),
+        }
+    }
+
+    /// Lists a directory of the root or the entries of a container.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<VfsDirEntry>, Error> {
+        let resolved = self.resolve(path)?;
+        match &resolved.file {
+            None => {
+                let names = self.root.read_dir(&resolved.directory)?;
+                Ok(names
+                    .into_iter()
+                    .map(|name| {
+                        let mut child = resolved.directory.clone();
+                        child.push(&name);
+                        let kind = self.root.metadata(&child).map_or(VfsKind::File, |metadata| metadata.kind);
+                        VfsDirEntry { name, kind }
+                    })
+                    .collect())
+            }
+            Some(file) => {
+                let entries = self.open_container(file, &resolved.directory, &resolved.containers)?;
+                Ok(entries.into_iter().map(|entry| VfsDirEntry { name: entry.name, kind: VfsKind::File }).collect())
+            }
+        }
+    }
+
+    /// The layer of an `OverlayRoot` that supplies a file or binder entry, None for single-layer roots.
+    pub fn layer(&self, path: &str) -> Option<&str> {
+        self.root.layer_name(&split_path(path))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    pub fn metadata(&self, path: &str) -> Result<VfsMetadata, Error> {
+        let components = split_path(path);
+        if let Some(metadata) = self.root.metadata(&components) {
+            return Ok(metadata);
+        }
+        match self.resolve(path)?.file {
+            Some(file) => Ok(VfsMetadata { kind: VfsKind::File, len: file.data.len() as u64 }),
+            None => Ok(VfsMetadata { kind: VfsKind::Directory, len: 0 }),
+        }
+    }
+
+    fn resolve<'a>(&self, path: &'a str) -> Result<Resolved<'a>, Error> {
+        let components = split_path(path);
+        let mut resolved = Resolved {
+            directory: Vec::new(),
+            containers: Vec::new(),
+            file: None,
+        };
+
+        let mut i = 0;
+        while i < components.len() {
+            let mut child = resolved.directory.clone();
+            child.push(components[i]);
+            i += 1;
+            match self.root.metadata(&child).map(|metadata| metadata.kind) {
+                Some(VfsKind::Directory) => resolved.directory = child,
+                Some(VfsKind::File) => {
+                    let name = components[i - 1].to_string();
+                    resolved.file = Some(ContainerEntry { name, data: self.root.read(&child)? });
+                    break;
+                }
+                None => return Err(not_found(path)),
+            }
+        }
+
+        for &component in &components[i..] {
+            let file = resolved.file.take().ok_or_else(|| not_found(path))?;
+            let entries = self.open_container(&file, &resolved.directory, &resolved.containers)?;
+            let entry = find_entry(&entries, component).cloned().ok_or_else(|| not_found(path))?;
+            resolved.containers.push(entries);
+            resolved.file = Some(entry);
+        }
+
+        Ok(resolved)
+    }
+
+    // Lists the entries of a file that is a container, decompressing it first if needed.
+    fn open_container(&self, file: &ContainerEntry, directory: &[&str], containers: &[Vec<ContainerEntry>]) -> Result<Vec<ContainerEntry>, Error> {
+        let mut br = BinaryReader::new(false, file.data.clone());
+        let mut compression = CompressionType::Unknown;
+        SFUtil::decompress_if_neccessary(&mut br, &mut compression)?;
+
+        let magic = br.memory.get(..4).unwrap_or_default();
+        let entries = match magic {
+            b"BND3" | b"BND4" => binder_entries(read_souls_file::<AnyBinder>(&mut br)?.into_files()),
+            b"TPF\0" => {
+                let tpf = read_souls_file::<TPF>(&mut br)?;
+                tpf.textures.into_iter().map(|texture| ContainerEntry { name: texture.name, data: texture.data }).collect()
+            }
+            b"BHF3" | b"BHF4" => {
+                let bdt = self.find_data_file(&file.name, directory, containers)?;
+                if magic == b"BHF3" {
+                    binder_entries(BXF3::from_bytes(br.memory, bdt)?.files)
+                } else {
+                    binder_entries(BXF4::from_bytes(br.memory, bdt)?.files)
+                }
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a container.", file.name))),
+        };
+        Ok(entries)
+    }
+
+    // Finds the data file of a split binder, searching from the innermost container outwards.
+    fn find_data_file(&self, header_name: &str, directory: &[&str], containers: &[Vec<ContainerEntry>]) -> Result<Vec<u8>, Error> {
+        let name = data_file_name(header_name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} has no data file name.", header_name)))?;
+        for entries in containers.iter().rev() {
+            if let Some(entry) = find_entry(entries, &name) {
+                return Ok(entry.data.clone());
+            }
+        }
+
+        let mut path = directory.to_vec();
+        path.push(&name);
+        self.root.read(&path).map_err(|_| Error::new(ErrorKind::NotFound, format!("No data file {} found for {}.", name, header_name)))
+    }
+}
+
+fn read_souls_file<T: SoulsFile>(br: &mut BinaryReader) -> Result<T, Error> {
+    let mut file = T::default();
+    file.specific_read(br)?;
+    Ok(file)
+}
+
+fn binder_entries(files: Vec<crate::formats::BinderFile>) -> Vec<ContainerEntry> {
+    files
+        .into_iter()
+        .map(|file| {
+            let name = match &file.name {
+                Some(name) => file_name(name).to_string(),
+                None => file.id.to_string(),
+            };
+            ContainerEntry { name, data: file.data }
+        })
+        .collect()
+}
+
+fn find_entry<'a>(entries: &'a [ContainerEntry], name: &str) -> Option<&'a ContainerEntry> {
+    entries.iter().find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
+
+// `c1000.chrtpfbhd` keeps its data in `c1000.chrtpfbdt`, `m10.tpfbhd` in `m10.tpfbdt` and so on.
+fn data_file_name(header_name: &str) -> Option<String> {
+    let (stem, extension) = header_name.rsplit_once('.')?;
+    let prefix = extension.to_ascii_lowercase().strip_suffix("bhd").map(str::len)?;
+    Some(format!("{}.{}bdt", stem, &extension[..prefix]))
+}
+
+// The last component of a binder entry name, which is usually a full Windows path.
+pub(crate) fn file_name(name: &str) -> &str {
+    name.rsplit(['\\', '/']).next().unwrap_or(name)
+}
+
+pub(crate) fn split_path(path: &str) -> Vec<&str> {
+    path.split(['\\', '/']).filter(|component| !component.is_empty()).collect()
+}
+
+fn not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{} does not exist.", path))
+}
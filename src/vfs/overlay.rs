@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use crate::formats::{AnyBinder, Binder, BinderFile, CompressionType, FileFlags};
+use crate::util::oodle::Oodle;
+use crate::util::{compressed, Compressed, SoulsFile};
+use crate::vfs::{file_name, DirectoryRoot, VfsKind, VfsMetadata, VfsRoot};
+
+// A rebuilt binder, or the kind and message of the error that kept it from being rebuilt.
+type Rebuilt = Result<Vec<u8>, (ErrorKind, String)>;
+
+/// Mod folders layered over the game data, the way runtime mod loaders see them.
+///
+/// Layers are searched in order of priority, with the base last. A file in a higher layer shadows
+/// the same path in every lower one. A directory named after a binder, such as
+/// `chr/c1000.chrbnd.dcx/c1000.flver`, instead replaces single entries of the binder below it,
+/// which is then rebuilt with its original compression. DCX_KRAK needs a native oo2core to be
+/// written, so without one such binders and entries are rebuilt uncompressed. Rebuilt binders are
+/// kept in memory. Directories of every layer are merged.
+pub struct OverlayRoot {
+    // Highest priority first, the base last.
+    layers: Vec<Layer>,
+    // Binders with replaced entries by path, or why they could not be rebuilt, so each is only rebuilt once.
+    rebuilt: Mutex<HashMap<Vec<String>, Rebuilt>>,
+}
+
+struct Layer {
+    name: String,
+    root: Box<dyn VfsRoot>,
+}
+
+impl OverlayRoot {
+    /// Creates an overlay with only the base layer, named `base`.
+    pub fn new<R: VfsRoot + 'static>(base: R) -> Self {
+        Self {
+            layers: vec![Layer { name: "base".to_string(), root: Box::new(base) }],
+            rebuilt: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Layers the given mod directories over `base`, the first having the highest priority.
+    pub fn with_mod_directories<R: VfsRoot + 'static, I: IntoIterator<Item = PathBuf>>(base: R, mod_directories: I) -> Self {
+        let mut overlay = Self::new(base);
+        for path in mod_directories {
+            overlay.push_directory(path);
+        }
+        overlay
+    }
+
+    /// Adds a layer with a lower priority than every layer added before it, but above the base.
+    pub fn push_layer<R: VfsRoot + 'static, S: Into<String>>(&mut self, name: S, root: R) {
+        let base = self.layers.len() - 1;
+        self.layers.insert(base, Layer { name: name.into(), root: Box::new(root) });
+        self.rebuilt.get_mut().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Adds a mod directory as a layer named after its path.
+    pub fn push_directory<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        self.push_layer(path.to_string_lossy().into_owned(), DirectoryRoot::new(path));
+    }
+
+    /// The names of the layers, highest priority first.
+    pub fn layer_names(&self) -> Vec<&str> {
+        self.layers.iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    // The highest layer with a file at `path`.
+    fn find_file(&self, path: &[&str]) -> Option<usize> {
+        self.layers.iter().position(|layer| kind_of(layer.root.as_ref(), path) == Some(VfsKind::File))
+    }
+
+    // Layers above `index` that replace entries of the file at `path`, lowest priority first.
+    fn entry_layers(&self, index: usize, path: &[&str]) -> Vec<&Layer> {
+        self.layers[..index]
+            .iter()
+            .rev()
+            .filter(|layer| kind_of(layer.root.as_ref(), path) == Some(VfsKind::Directory))
+            .collect()
+    }
+
+    // The file at `path` in the layer at `index` with the entries of every layer above it replaced.
+    fn rebuild(&self, index: usize, path: &[&str]) -> Result<Vec<u8>, Error> {
+        let key: Vec<String> = path.iter().map(|component| component.to_ascii_lowercase()).collect();
+        let cached = self.rebuilt.lock().unwrap_or_else(PoisonError::into_inner).get(&key).cloned();
+        let result = match cached {
+            Some(result) => result,
+            None => {
+                let result = self.replace_all(index, path).map_err(|error| (error.kind(), error.to_string()));
+                self.rebuilt.lock().unwrap_or_else(PoisonError::into_inner).insert(key, result.clone());
+                result
+            }
+        };
+        result.map_err(|(kind, message)| Error::new(kind, message))
+    }
+
+    fn replace_all(&self, index: usize, path: &[&str]) -> Result<Vec<u8>, Error> {
+        let mut data = self.layers[index].root.read(path)?;
+        for layer in self.entry_layers(index, path) {
+            data = replace_entries(data, layer.root.as_ref(), path)?;
+        }
+        Ok(data)
+    }
+}
+
+impl VfsRoot for OverlayRoot {
+    fn metadata(&self, path: &[&str]) -> Option<VfsMetadata> {
+        match self.find_file(path) {
+            // A binder that cannot be rebuilt is listed as it is, reading it reports the error
+            Some(index) if !self.entry_layers(index, path).is_empty() => match self.rebuild(index, path) {
+                Ok(data) => Some(VfsMetadata { kind: VfsKind::File, len: data.len() as u64 }),
+                Err(_) => self.layers[index].root.metadata(path),
+            },
+            Some(index) => self.layers[index].root.metadata(path),
+            None => self
+                .layers
+                .iter()
+                .any(|layer| kind_of(layer.root.as_ref(), path) == Some(VfsKind::Directory))
+                .then_some(VfsMetadata { kind: VfsKind::Directory, len: 0 }),
+        }
+    }
+
+    fn read(&self, path: &[&str]) -> Result<Vec<u8>, Error> {
+        let index = self
+            .find_file(path)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} does not exist.", path.join("/"))))?;
+        if self.entry_layers(index, path).is_empty() {
+            return self.layers[index].root.read(path);
+        }
+        self.rebuild(index, path)
+    }
+
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = Vec::new();
+        let mut found = false;
+        for layer in &self.layers {
+            if kind_of(layer.root.as_ref(), path) != Some(VfsKind::Directory) {
+                continue;
+            }
+            found = true;
+            for name in layer.root.read_dir(path)? {
+                if !names.iter().any(|existing| existing.eq_ignore_ascii_case(&name)) {
+                    names.push(name);
+                }
+            }
+        }
+        if !found {
+            return Err(Error::new(ErrorKind::NotFound, format!("{} is not a directory.", path.join("/"))));
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn layer_name(&self, path: &[&str]) -> Option<&str> {
+        // The file on disk or in the archives the path leads through, and the layer it comes from
+        let (end, index) = (1..=path.len()).find_map(|end| self.find_file(&path[..end]).map(|index| (end, index)))?;
+
+        // Entries are supplied by the highest layer replacing them, or the container otherwise
+        for entry_end in (end + 1..=path.len()).rev() {
+            if let Some(layer) = self.layers[..index].iter().find(|layer| kind_of(layer.root.as_ref(), &path[..entry_end]) == Some(VfsKind::File)) {
+                return Some(&layer.name);
+            }
+        }
+        Some(&self.layers[index].name)
+    }
+}
+
+fn kind_of(root: &dyn VfsRoot, path: &[&str]) -> Option<VfsKind> {
+    root.metadata(path).map(|metadata| metadata.kind)
+}
+
+// Replaces the entries of a binder with the files `root` has in the directory at `path`, recursing
+// into nested binders for subdirectories.
+fn replace_entries(data: Vec<u8>, root: &dyn VfsRoot, path: &[&str]) -> Result<Vec<u8>, Error> {
+    let (mut br, compression, compression_level) = compressed::decompress(data)?;
+    let mut file = AnyBinder::default();
+    if !file.is(&mut br) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Entries of {} cannot be replaced, only BND3 and BND4 entries can.", path.join("/")),
+        ));
+    }
+    file.specific_read(&mut br)?;
+    let mut binder = Compressed::new(file, compression, compression_level);
+    replace_files(binder.files_mut(), root, path)?;
+
+    for file in binder.files_mut() {
+        if file.flags.is_compressed() && !can_compress(file.compression) {
+            file.flags = FileFlags(file.flags.0 & !FileFlags::COMPRESSED.0);
+        }
+    }
+    if !can_compress(binder.compression) {
+        binder.compression = CompressionType::None;
+    }
+    binder.to_bytes()
+}
+
+// Every compression can be written except DCX_KRAK, which needs a native oo2core.
+fn can_compress(compression: CompressionType) -> bool {
+    compression != CompressionType::DCX_KRAK || Oodle::get_native_compressor().is_ok()
+}
+
+// Children of the directory without a matching entry are ignored, as the game would never ask for them.
+fn replace_files(files: &mut [BinderFile], root: &dyn VfsRoot, path: &[&str]) -> Result<(), Error> {
+    for child in root.read_dir(path)? {
+        let mut child_path = path.to_vec();
+        child_path.push(&child);
+        let kind = kind_of(root, &child_path);
+        for file in files.iter_mut().filter(|file| entry_name(file).eq_ignore_ascii_case(&child)) {
+            file.data = match kind {
+                Some(VfsKind::File) => root.read(&child_path)?,
+                Some(VfsKind::Directory) => replace_entries(std::mem::take(&mut file.data), root, &child_path)?,
+                None => continue,
+            };
+        }
+    }
+    Ok(())
+}
+
+// Entries are named the way `Vfs` lists them, by file name or by ID when they have none.
+fn entry_name(file: &BinderFile) -> String {
+    match &file.name {
+        Some(name) => file_name(name).to_string(),
+        None => file.id.to_string(),
+    }
+}
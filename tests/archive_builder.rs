@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use from_formats::formats::bhd5::unpack::MANIFEST_NAME;
+use from_formats::formats::{ArchiveBuilder, CompressionType, Game, BDT, BHD5};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("from_formats_archive_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn data(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31) ^ seed).collect()
+}
+
+// Builds the archive and reads both halves back the way the game files would be read.
+fn build(builder: &ArchiveBuilder) -> (BHD5, Vec<u8>) {
+    let mut bdt = Vec::new();
+    let bhd = builder.build(&mut bdt).unwrap();
+    let read = BHD5::from_bytes(bhd.to_bytes().unwrap(), builder.game).unwrap();
+    assert_eq!(read, bhd);
+    assert!(bdt.starts_with(b"BDF3"));
+    (read, bdt)
+}
+
+#[test]
+fn round_trips_an_archive_before_ds3() {
+    let files = [("/chr/c0000.chrbnd", data(1, 0x15)), ("/param/gameparam/gameparam.parambnd", data(2, 0x40)), ("/sound/frpg_main.fsb", Vec::new())];
+    let mut builder = ArchiveBuilder::new(Game::DarkSouls1);
+    for (path, data) in &files {
+        builder.add_file(path, data.clone());
+    }
+    builder.add_hashed_file(0x1234_abcd, data(3, 7));
+
+    let (bhd, bdt) = build(&builder);
+    let mut bdt = BDT::new(Cursor::new(bdt));
+    assert_eq!(bhd.files().count(), 4);
+    let expected = files.iter().map(|(path, data)| (Game::DarkSouls1.path_hash(path), data.clone())).chain([(0x1234_abcd, data(3, 7))]);
+    for (hash, data) in expected {
+        assert!(hash <= u32::MAX as u64);
+        let header = bhd.file(hash).unwrap();
+        // Sizes are recorded without padding, which only the DS3 layout can tell apart
+        assert_eq!(header.padded_file_size as usize, data.len());
+        assert_eq!(header.unpadded_file_size, None);
+        assert_eq!(header.file_offset % 0x10, 0);
+        assert_eq!(bdt.read_file(header).unwrap(), data);
+    }
+}
+
+#[test]
+fn round_trips_compressed_archives_from_ds3() {
+    for game in [Game::DarkSouls3, Game::EldenRing] {
+        let mut builder = ArchiveBuilder::new(game);
+        builder.salt = "Data0".to_string();
+        builder.compression = Some(CompressionType::DCX_DFLT_10000_44_9);
+        builder.add_file("/chr/c1000.anibnd", data(4, 0x1234));
+        builder.add_file("/map/m10_00_00_00.msb.dcx", CompressionType::DCX_DFLT_10000_44_9.compress(&data(5, 0x300)).unwrap());
+
+        let (bhd, bdt) = build(&builder);
+        assert_eq!(bhd.salt, "Data0");
+        let mut bdt = BDT::new(Cursor::new(bdt));
+
+        // Files are compressed under their path with `.dcx` added, unless they are compressed already
+        assert!(bhd.file_by_name("/chr/c1000.anibnd").is_none());
+        for (path, expected) in [("/chr/c1000.anibnd.dcx", data(4, 0x1234)), ("/map/m10_00_00_00.msb.dcx", data(5, 0x300))] {
+            let header = bhd.file_by_name(path).unwrap();
+            assert_eq!(header.file_name_hash, game.path_hash(path));
+            assert_eq!(game == Game::EldenRing, header.file_name_hash > u32::MAX as u64);
+
+            let stored = bdt.read_file(header).unwrap();
+            assert_eq!(header.unpadded_file_size, Some(stored.len() as u64));
+            assert_eq!(header.padded_file_size as usize, stored.len().next_multiple_of(0x10));
+            assert_eq!(header.file_offset % 0x10, 0);
+            assert!(stored.starts_with(b"DCX\0"));
+            assert_eq!(bdt.read_file_decompressed(header).unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn adds_unpacked_directories_with_unknown_files() {
+    for (game, hash) in [(Game::DarkSouls3, 0xdead_beefu64), (Game::EldenRing, 0x0123_4567_89ab_cdef)] {
+        let dir = temp_dir(&format!("{:?}", game));
+        fs::create_dir_all(dir.join("chr")).unwrap();
+        fs::write(dir.join("chr/c0000.anibnd.dcx"), data(6, 0x21)).unwrap();
+        let width = if game >= Game::EldenRing { 16 } else { 8 };
+        let unknown = dir.join("_unknown").join("Data1").join(format!("{:0width$x}", hash, width = width));
+        fs::create_dir_all(unknown.parent().unwrap()).unwrap();
+        fs::write(&unknown, data(7, 0x30)).unwrap();
+        fs::write(dir.join(MANIFEST_NAME), "Data1\t0\t0\t0\tnothing\n").unwrap();
+        // Left behind by an interrupted unpack
+        fs::write(dir.join("chr/c1000.anibnd.dcx.part"), data(8, 0x10)).unwrap();
+
+        let mut builder = ArchiveBuilder::new(game);
+        builder.add_directory(&dir).unwrap();
+        let (bhd, bdt) = build(&builder);
+        let mut bdt = BDT::new(Cursor::new(bdt));
+
+        // The manifest and partial files of `unpack_game` are not packed
+        assert_eq!(bhd.files().count(), 2);
+        let named = bhd.file_by_name("/chr/c0000.anibnd.dcx").unwrap();
+        assert_eq!(bdt.read_file(named).unwrap(), data(6, 0x21));
+        assert_eq!(named.padded_file_size, 0x30);
+        assert_eq!(named.unpadded_file_size, Some(0x21));
+        let unknown = bhd.file(hash).unwrap();
+        assert_eq!(bdt.read_file(unknown).unwrap(), data(7, 0x30));
+        assert_eq!(unknown.unpadded_file_size, Some(0x30));
+    }
+}
+
+#[test]
+fn rejects_directories_unpacked_with_decompression() {
+    let dir = temp_dir("decompressed");
+    fs::create_dir_all(dir.join("chr")).unwrap();
+    fs::write(dir.join("chr/c0000.anibnd"), data(9, 0x20)).unwrap();
+    fs::write(dir.join(MANIFEST_NAME), "Data1\t00000000deadbeef\t32\t1\tchr/c0000.anibnd\n").unwrap();
+
+    let error = ArchiveBuilder::new(Game::DarkSouls3).add_directory(&dir).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
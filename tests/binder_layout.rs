@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::PathBuf;
+use from_formats::formats::{repack_binder, unpack_binder, unpack_split_binder, BinderFile, CompressionType, FileFlags, Format, PackedBinder, BND3, BND4, BXF4};
+use from_formats::util::Compressed;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("from_formats_layout_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn entry(id: i32, name: &str, len: usize, compressed: bool) -> BinderFile {
+    let data = (0..len).map(|i| (i * 7 % 251) as u8 ^ id as u8).collect();
+    let mut file = BinderFile::new(id, Some(format!("N:\\FDP\\data\\INTERROOT_win64\\chr\\c1000\\{}", name)), data);
+    if compressed {
+        file.flags = FileFlags::FLAG1 | FileFlags::COMPRESSED;
+        file.compression = CompressionType::DCX_DFLT_10000_24_9;
+    }
+    file
+}
+
+// A DCX with padding after the stream, as other tools leave it, which compressing again would not reproduce.
+fn padded_dcx(data: &[u8]) -> Vec<u8> {
+    let mut dcx = CompressionType::DCX_DFLT_10000_24_9.compress(data).unwrap();
+    dcx.extend_from_slice(&[0; 0x20]);
+    dcx
+}
+
+// A BND3 whose second entry holds a padded DCX, with the compressed flag set on the written file.
+fn bnd3_with_foreign_entry() -> Vec<u8> {
+    let mut bnd = BND3 {
+        format: Format::IDS | Format::NAMES1 | Format::NAMES2,
+        alignment: 0x20,
+        ..BND3::default()
+    };
+    bnd.files.push(entry(0, "c1000.flver", 0x123, false));
+    bnd.files.push(BinderFile::new(1, Some("N:\\FDP\\data\\INTERROOT_win64\\chr\\c1000\\c1000.hkx".to_string()), padded_dcx(&[5; 0x400])));
+    bnd.files.push(entry(2, "c1000.tae", 0, false));
+
+    let mut bytes = Compressed::new(bnd, CompressionType::None, 0).to_bytes().unwrap();
+    // Flags are stored with their bits reversed: FLAG1 becomes 0x40, FLAG1 | COMPRESSED 0xC0
+    let flags = 0x20 + 0x14;
+    assert_eq!(bytes[flags], 0x40);
+    bytes[flags] = 0xC0;
+    bytes
+}
+
+fn bnd4_compressed() -> Vec<u8> {
+    let mut bnd = BND4::default();
+    bnd.extended = 4;
+    bnd.files.push(entry(100, "c1000.anibnd", 0x2345, true));
+    bnd.files.push(entry(200, "c1000.flver", 0x80, false));
+    bnd.files.push(entry(300, "c1000.hkx", 0x1000, true));
+    let mut bytes = Compressed::new(bnd, CompressionType::DCX_DFLT_10000_24_9, 0).to_bytes().unwrap();
+    bytes.extend_from_slice(&[0; 0x10]);
+    bytes
+}
+
+fn bxf4() -> (Vec<u8>, Vec<u8>) {
+    let mut bxf = BXF4::default();
+    bxf.alignment = 0x800;
+    bxf.files.push(entry(0, "c1000_a.tpf", 0x10, true));
+    bxf.files.push(entry(1, "c1000_b.tpf", 0x999, false));
+    bxf.files.push(entry(2, "c1000_c.tpf", 0x3000, true));
+    bxf.to_bytes().unwrap()
+}
+
+fn single(packed: PackedBinder) -> Vec<u8> {
+    match packed {
+        PackedBinder::Single(bytes) => bytes,
+        PackedBinder::Split { .. } => panic!("Expected a single binder."),
+    }
+}
+
+#[test]
+fn repacks_unchanged_binders_byte_for_byte() {
+    let bnd3 = bnd3_with_foreign_entry();
+    let dir = temp_dir("bnd3");
+    let layout = unpack_binder(bnd3.clone(), &dir).unwrap();
+    assert_eq!(layout.alignment, 0x20);
+    assert!(layout.files[1].stored.is_some());
+    assert_eq!(fs::read(dir.join("c1000.hkx")).unwrap(), vec![5; 0x400]);
+    assert_eq!(single(repack_binder(&dir).unwrap()), bnd3);
+
+    let bnd4 = bnd4_compressed();
+    let dir = temp_dir("bnd4");
+    let layout = unpack_binder(bnd4.clone(), &dir).unwrap();
+    assert_eq!(layout.compression, CompressionType::DCX_DFLT_10000_24_9);
+    assert!(layout.stored.is_some());
+    assert_eq!(single(repack_binder(&dir).unwrap()), bnd4);
+
+    let (bhd, bdt) = bxf4();
+    let dir = temp_dir("bxf4");
+    let layout = unpack_split_binder(bhd.clone(), bdt.clone(), &dir).unwrap();
+    assert_eq!(layout.alignment, 0x800);
+    assert_eq!(repack_binder(&dir).unwrap(), PackedBinder::Split { bhd, bdt });
+}
+
+#[test]
+fn compresses_changed_entries_again() {
+    let dir = temp_dir("changed");
+    unpack_binder(bnd4_compressed(), &dir).unwrap();
+    fs::write(dir.join("c1000.hkx"), b"changed").unwrap();
+
+    let repacked = single(repack_binder(&dir).unwrap());
+    let bnd = Compressed::<BND4>::from_bytes(repacked).unwrap();
+    assert_eq!(bnd.compression, CompressionType::DCX_DFLT_10000_24_9);
+    assert_eq!(bnd.files[2].data, b"changed");
+    assert!(bnd.files[2].flags.is_compressed());
+    assert_eq!(bnd.files[0].data, entry(100, "c1000.anibnd", 0x2345, true).data);
+}
@@ -0,0 +1,32 @@
+use std::io::Cursor;
+use from_formats::formats::{BinderFile, LazyBinder, BXF4};
+
+// A BXF4 whose data header is 0x40 bytes long, as in some retail files, with the first entry right after it.
+fn bxf4_with_header_size(header_size: u64) -> (Vec<u8>, Vec<u8>, BXF4) {
+    let mut bxf = BXF4::default();
+    bxf.alignment = 0x40;
+    bxf.files.push(BinderFile::new(0, Some("a.tpf".to_string()), vec![1; 0x44]));
+    bxf.files.push(BinderFile::new(1, Some("b.tpf".to_string()), vec![2; 0x50]));
+    let (bhd, mut bdt) = bxf.to_bytes().unwrap();
+    assert_eq!(&bdt[0x30..0x40], &[0; 0x10]);
+    bdt[0x10..0x18].copy_from_slice(&header_size.to_le_bytes());
+    (bhd, bdt, bxf)
+}
+
+#[test]
+fn reads_bdf4_headers_of_both_sizes() {
+    let (bhd, bdt, bxf) = bxf4_with_header_size(0x40);
+    let read = BXF4::from_bytes(bhd.clone(), bdt.clone()).unwrap();
+    assert_eq!(read.files, bxf.files);
+    assert_eq!(read.alignment, 0x40);
+
+    let lazy = LazyBinder::new_split(bhd, Cursor::new(bdt)).unwrap();
+    assert_eq!(lazy.load_files().unwrap(), bxf.files);
+}
+
+#[test]
+fn rejects_other_bdf4_header_sizes() {
+    let (bhd, bdt, _) = bxf4_with_header_size(0x50);
+    assert!(BXF4::from_bytes(bhd.clone(), bdt.clone()).is_err());
+    assert!(LazyBinder::new_split(bhd, Cursor::new(bdt)).is_err());
+}
@@ -0,0 +1,149 @@
+use std::io::{Cursor, Read};
+use from_formats::formats::{CompressionType, DcxDecoder};
+
+// Compressible but not repetitive, spanning several EDGE chunks with a partial one at the end.
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i / 3) as u32).wrapping_mul(2654435761).to_be_bytes()[(i % 4) / 2] & 0x3F).collect()
+}
+
+fn decode(dcx: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    DcxDecoder::new(Cursor::new(dcx)).unwrap().read_to_end(&mut decoded).unwrap();
+    decoded
+}
+
+// Reads through a buffer smaller than any block, which has to give the same bytes as one large read.
+fn decode_in_small_reads(dcx: &[u8], buffer_size: usize) -> Vec<u8> {
+    let mut decoder = DcxDecoder::new(Cursor::new(dcx)).unwrap();
+    let mut decoded = Vec::new();
+    let mut buffer = vec![0; buffer_size];
+    loop {
+        let count = decoder.read(&mut buffer).unwrap();
+        if count == 0 {
+            return decoded;
+        }
+        decoded.extend_from_slice(&buffer[..count]);
+    }
+}
+
+fn check(compression: CompressionType, dcx: Vec<u8>, expected: &[u8]) {
+    let (decompressed, detected) = CompressionType::decompress(dcx.clone()).unwrap();
+    assert_eq!(detected, compression);
+    assert_eq!(decompressed, expected);
+
+    let decoder = DcxDecoder::new(Cursor::new(&dcx)).unwrap();
+    assert_eq!(decoder.compression(), compression);
+    assert_eq!(decoder.uncompressed_size(), expected.len() as u64);
+    assert_eq!(decode(&dcx), decompressed, "{:?}", compression);
+    for buffer_size in [1, 7, 0x1000] {
+        assert_eq!(decode_in_small_reads(&dcx, buffer_size), decompressed, "{:?} in reads of {}", compression, buffer_size);
+    }
+
+    // Cutting the file anywhere must give an error, not a shorter payload or a panic
+    // EDGE files may end in padding, so the last cut stays clear of it
+    for len in [3, 0x20, 0x4C, dcx.len() / 2, dcx.len() - 0x20] {
+        let truncated = &dcx[..len];
+        let result = DcxDecoder::new(Cursor::new(truncated)).and_then(|mut decoder| decoder.read_to_end(&mut Vec::new()));
+        assert!(result.is_err(), "{:?} cut at {:#x}", compression, len);
+    }
+}
+
+#[test]
+fn streams_the_same_bytes_as_decompressing_in_memory() {
+    let expected = data(0x2_3456);
+    for compression in [
+        CompressionType::DCX_DFLT_10000_24_9,
+        CompressionType::DCX_DFLT_10000_44_9,
+        CompressionType::DCX_DFLT_11000_44_8,
+        CompressionType::DCX_DFLT_11000_44_9,
+        CompressionType::DCX_DFLT_11000_44_9_15,
+        CompressionType::DCX_EDGE,
+        CompressionType::DCP_DFLT,
+        CompressionType::DCP_EDGE,
+    ] {
+        check(compression, compression.compress(&expected).unwrap(), &expected);
+    }
+}
+
+#[test]
+fn streams_empty_files() {
+    for compression in [CompressionType::DCX_DFLT_11000_44_9, CompressionType::DCX_EDGE] {
+        let dcx = compression.compress(&[]).unwrap();
+        assert_eq!(CompressionType::decompress(dcx.clone()).unwrap().0, Vec::<u8>::new());
+        assert_eq!(decode(&dcx), Vec::<u8>::new());
+    }
+}
+
+// A DCX_KRAK holding uncompressed Kraken blocks, which needs no encoder to produce.
+#[cfg(feature = "kraken")]
+fn krak_dcx(data: &[u8]) -> Vec<u8> {
+    let mut stream = Vec::new();
+    for block in data.chunks(0x40000) {
+        stream.extend_from_slice(&[0xCC, 0x06]);
+        stream.extend_from_slice(block);
+    }
+
+    let mut dcx = Vec::new();
+    dcx.extend_from_slice(b"DCX\0");
+    for field in [0x11000i32, 0x18, 0x24, 0x44, 0x4C] {
+        dcx.extend_from_slice(&field.to_be_bytes());
+    }
+    dcx.extend_from_slice(b"DCS\0");
+    dcx.extend_from_slice(&(data.len() as i32).to_be_bytes());
+    dcx.extend_from_slice(&(stream.len() as i32).to_be_bytes());
+    dcx.extend_from_slice(b"DCP\0KRAK");
+    dcx.extend_from_slice(&0x20i32.to_be_bytes());
+    dcx.extend_from_slice(&[9, 0, 0, 0]);
+    dcx.extend_from_slice(&[0; 12]);
+    dcx.extend_from_slice(&0x10100i32.to_be_bytes());
+    dcx.extend_from_slice(b"DCA\0");
+    dcx.extend_from_slice(&8i32.to_be_bytes());
+    dcx.extend_from_slice(&stream);
+    dcx
+}
+
+#[test]
+#[cfg(feature = "kraken")]
+fn streams_kraken_files() {
+    let expected = data(0x4_1234);
+    check(CompressionType::DCX_KRAK, krak_dcx(&expected), &expected);
+}
+
+// Patches a big endian field of the EgdT block in a DCX_EDGE header.
+fn with_edge_field(dcx: &[u8], offset: usize, value: i32) -> Vec<u8> {
+    let mut dcx = dcx.to_vec();
+    dcx[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    dcx
+}
+
+#[test]
+fn rejects_edge_chunk_counts_out_of_range() {
+    let dcx = CompressionType::DCX_EDGE.compress(&data(0x2_0000)).unwrap();
+    // The chunk count follows the EgdT size at 0x68 in a DCX_EDGE file
+    assert_eq!(&dcx[0x4C..0x50], b"EgdT");
+    assert_eq!(i32::from_be_bytes(dcx[0x68..0x6C].try_into().unwrap()), 2);
+
+    for chunk_count in [-1, i32::MIN, i32::MAX, 0x0FFF_FFFF, 3] {
+        let egdt_size = 0x24i32.wrapping_add(chunk_count.wrapping_mul(0x10));
+        let patched = with_edge_field(&with_edge_field(&dcx, 0x68, chunk_count), 0x64, egdt_size);
+        let error = DcxDecoder::new(Cursor::new(&patched)).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(CompressionType::decompress(patched).is_err());
+    }
+}
+
+#[test]
+fn rejects_zlib_streams_with_a_wrong_checksum() {
+    let expected = data(0x1234);
+    let mut dcx = CompressionType::DCX_DFLT_10000_24_9.compress(&expected).unwrap();
+    // The stream starts after the 0x4C byte header and ends in its Adler-32
+    let compressed_size = u32::from_be_bytes(dcx[0x20..0x24].try_into().unwrap()) as usize;
+    dcx[0x4C + compressed_size - 1] ^= 1;
+    assert!(CompressionType::decompress(dcx.clone()).is_err());
+    assert!(DcxDecoder::new(Cursor::new(&dcx)).unwrap().read_to_end(&mut Vec::new()).is_err());
+
+    let mut zlib = CompressionType::Zlib.compress_with_level(&expected, 0xDA).unwrap();
+    assert_eq!(CompressionType::decompress(zlib.clone()).unwrap(), (expected, CompressionType::Zlib));
+    *zlib.last_mut().unwrap() ^= 1;
+    assert!(CompressionType::decompress(zlib).is_err());
+}
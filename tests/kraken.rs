@@ -0,0 +1,347 @@
+#![cfg(feature = "kraken")]
+
+// Hand built Kraken and Mermaid streams, decoded with the pure Rust decoder. Every fixture is
+// assembled from the raw stream structures, and its expected output is built separately by
+// replaying the same literals and matches.
+
+use from_formats::util::kraken::Kraken;
+use from_formats::util::oodle::OodleCompressor;
+
+const KRAKEN: u8 = 6;
+const MERMAID: u8 = 10;
+
+// Writes bits starting at the most significant bit of each byte.
+#[derive(Default)]
+struct MsbWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl MsbWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+// Writes bits starting at the least significant bit of each byte.
+#[derive(Default)]
+struct LsbWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl LsbWriter {
+    fn write_bit(&mut self, bit: u32) {
+        if self.bits.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit != 0 {
+            *self.bytes.last_mut().unwrap() |= 1 << (self.bits % 8);
+        }
+        self.bits += 1;
+    }
+}
+
+// Stored entropy block with the two byte header.
+fn stored(data: &[u8]) -> Vec<u8> {
+    let mut block = vec![0x80 | (data.len() >> 8) as u8, data.len() as u8];
+    block.extend_from_slice(data);
+    block
+}
+
+// Stored entropy block with the three byte header, the only form allowed where the first bit is a flag.
+fn stored_long(data: &[u8]) -> Vec<u8> {
+    let mut block = vec![(data.len() >> 16) as u8, (data.len() >> 8) as u8, data.len() as u8];
+    block.extend_from_slice(data);
+    block
+}
+
+// Entropy block of the given type with the five byte header.
+fn entropy(kind: u8, payload: &[u8], decoded_size: usize) -> Vec<u8> {
+    let size = decoded_size - 1;
+    let mut block = vec![kind << 4 | (size >> 14) as u8 & 0xF];
+    block.extend_from_slice(&(((size & 0x3FFF) << 18 | payload.len()) as u32).to_be_bytes());
+    block.extend_from_slice(payload);
+    block
+}
+
+// Huffman payload with a sparse table that gives each of four symbols a two bit code, split into
+// the three interleaved streams.
+fn huffman(data: &[u8], symbols: [u8; 4]) -> Vec<u8> {
+    let mut table = MsbWriter::default();
+    table.write(0, 2);
+    table.write(4, 8);
+    table.write(1, 3);
+    for symbol in symbols {
+        table.write(symbol as u32, 8);
+        table.write(1, 1);
+    }
+
+    let mut streams: [LsbWriter; 3] = Default::default();
+    for (i, byte) in data.iter().enumerate() {
+        let code = symbols.iter().position(|symbol| symbol == byte).unwrap() as u32;
+        streams[i % 3].write_bit(code >> 1);
+        streams[i % 3].write_bit(code & 1);
+    }
+    let [forward, backward, middle] = streams.map(|stream| stream.bytes);
+
+    let mut payload = table.bytes;
+    payload.extend_from_slice(&(forward.len() as u16).to_le_bytes());
+    payload.extend(forward);
+    payload.extend(middle);
+    payload.extend(backward.iter().rev());
+    payload
+}
+
+// Huffman payload with a single symbol, which fills the whole output.
+fn huffman_fill(symbol: u8) -> Vec<u8> {
+    let mut table = MsbWriter::default();
+    table.write(0, 2);
+    table.write(1, 8);
+    table.write(symbol as u32, 8);
+    table.bytes
+}
+
+// LZ chunk with raw literals.
+fn lz_chunk(streams: &[u8]) -> Vec<u8> {
+    let mut chunk = (0x800000u32 | 1 << 19 | streams.len() as u32).to_be_bytes()[1..].to_vec();
+    chunk.extend_from_slice(streams);
+    chunk
+}
+
+// A single block of up to 256 KiB holding one quantum.
+fn block(decoder: u8, quantum: &[u8]) -> Vec<u8> {
+    let mut block = vec![0x8C, decoder];
+    block.extend_from_slice(&((quantum.len() - 1) as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(quantum);
+    block
+}
+
+fn repeat_match(output: &mut Vec<u8>, distance: usize, length: usize) {
+    for _ in 0..length {
+        output.push(output[output.len() - distance]);
+    }
+}
+
+struct Fixture {
+    name: &'static str,
+    compressed: Vec<u8>,
+    expected: Vec<u8>,
+}
+
+fn kraken_lz() -> Fixture {
+    // Two matches at distance 10, the first with an explicit offset and the second reusing it
+    let mut streams = b"abcdefgh".to_vec();
+    streams.extend(stored_long(b"XYZWend!"));
+    streams.extend(stored(&[2 | 14 << 2 | 3 << 6, 2 | 14 << 2]));
+    streams.extend(stored_long(&[0x02]));
+    streams.extend(stored(&[]));
+    streams.extend([0x00, 0x80]);
+
+    let mut expected = b"abcdefghXY".to_vec();
+    repeat_match(&mut expected, 10, 16);
+    expected.extend(b"ZW");
+    repeat_match(&mut expected, 10, 16);
+    expected.extend(b"end!");
+
+    Fixture {
+        name: "kraken_lz",
+        compressed: block(KRAKEN, &lz_chunk(&streams)),
+        expected,
+    }
+}
+
+fn kraken_huffman() -> Fixture {
+    let expected: Vec<u8> = (0..300u32).map(|i| b"ACGT"[(i * i % 7 % 4) as usize]).collect();
+    Fixture {
+        name: "kraken_huffman",
+        compressed: block(KRAKEN, &entropy(2, &huffman(&expected, *b"GATC"), expected.len())),
+        expected,
+    }
+}
+
+fn kraken_rle() -> Fixture {
+    // Commands run from the back: set the byte, copy two bytes and repeat 15 times, repeat 5 times
+    let rle = [0x00, b'z', b'a', b'b', 0x5F, 0xFD, 0x01];
+    let mut expected = b"ab".to_vec();
+    expected.extend([b'z'; 20]);
+    Fixture {
+        name: "kraken_rle",
+        compressed: block(KRAKEN, &entropy(3, &rle, expected.len())),
+        expected,
+    }
+}
+
+fn kraken_multi_chunk() -> Fixture {
+    // The second chunk starts without raw bytes and matches into the first one
+    let mut streams = stored_long(b"XYZWend!");
+    streams.extend(stored(&[2 | 14 << 2 | 3 << 6, 2 | 14 << 2]));
+    streams.extend(stored_long(&[0x02]));
+    streams.extend(stored(&[]));
+    streams.extend([0x00, 0x80]);
+
+    let mut quantum = entropy(2, &huffman_fill(b'q'), 0x20000);
+    quantum.extend(lz_chunk(&streams));
+
+    let mut expected = vec![b'q'; 0x20000];
+    expected.extend(b"XY");
+    repeat_match(&mut expected, 10, 16);
+    expected.extend(b"ZW");
+    repeat_match(&mut expected, 10, 16);
+    expected.extend(b"end!");
+
+    Fixture {
+        name: "kraken_multi_chunk",
+        compressed: block(KRAKEN, &quantum),
+        expected,
+    }
+}
+
+fn kraken_blocks() -> Fixture {
+    // An uncompressed block, then a block whose only quantum is stored as is
+    let expected: Vec<u8> = (0..0x40000 + 50u32).map(|i| (i * 31 % 251) as u8).collect();
+    let mut compressed = vec![0x4C, KRAKEN];
+    compressed.extend_from_slice(&expected[..0x40000]);
+    compressed.extend(block(KRAKEN, &expected[0x40000..]));
+    Fixture {
+        name: "kraken_blocks",
+        compressed,
+        expected,
+    }
+}
+
+fn kraken_memset() -> Fixture {
+    Fixture {
+        name: "kraken_memset",
+        compressed: vec![0x8C, KRAKEN, 0x07, 0xFF, 0xFF, b'm'],
+        expected: vec![b'm'; 100],
+    }
+}
+
+fn mermaid_lz() -> Fixture {
+    // Short matches with a new and a reused offset, then a long match from the length stream
+    let mut streams = b"abcdefgh".to_vec();
+    streams.extend(stored(b"XYZend"));
+    streams.extend(stored(&[2 | 15 << 3, 0x80 | 1 | 8 << 3, 1]));
+    streams.extend([2, 0, 10, 0, 3, 0]);
+    streams.extend([0, 0, 0]);
+    streams.extend([9]);
+
+    let mut expected = b"abcdefghXY".to_vec();
+    repeat_match(&mut expected, 10, 15);
+    expected.extend(b"Z");
+    repeat_match(&mut expected, 10, 8);
+    repeat_match(&mut expected, 3, 100);
+    expected.extend(b"end");
+
+    Fixture {
+        name: "mermaid_lz",
+        compressed: block(MERMAID, &lz_chunk(&streams)),
+        expected,
+    }
+}
+
+fn mermaid_halves() -> Fixture {
+    // A chunk over 64 KiB is split in two halves. The first is filled by an extended long match,
+    // the second reuses its offset and copies from the start of the output with a far offset.
+    let mut streams = b"abcdefgh".to_vec();
+    streams.extend(stored(b"XYLMNopqrstuvwxyz0123456"));
+    streams.extend(stored(&[1, 0x80 | 3 | 8 << 3, 5]));
+    streams.extend([1, 0]);
+    streams.extend([1, 0, 8, 0]);
+    streams.extend([1, 0, 0]);
+    streams.extend([0x00, 0x00, 0x01]);
+    streams.extend([255]);
+    streams.extend(16295u16.to_le_bytes());
+
+    let mut expected = b"abcdefgh".to_vec();
+    repeat_match(&mut expected, 8, 65526);
+    expected.extend(b"XYLMN");
+    repeat_match(&mut expected, 8, 8);
+    let far = expected[..10].to_vec();
+    expected.extend(far);
+    expected.extend(b"opqrstuvwxyz0123456");
+
+    Fixture {
+        name: "mermaid_halves",
+        compressed: block(MERMAID, &lz_chunk(&streams)),
+        expected,
+    }
+}
+
+fn fixtures() -> Vec<Fixture> {
+    vec![
+        kraken_lz(),
+        kraken_huffman(),
+        kraken_rle(),
+        kraken_multi_chunk(),
+        kraken_blocks(),
+        kraken_memset(),
+        mermaid_lz(),
+        mermaid_halves(),
+    ]
+}
+
+#[test]
+fn decodes_fixtures() {
+    for fixture in fixtures() {
+        let decoded = Kraken::new().decompress(&fixture.compressed, fixture.expected.len());
+        assert_eq!(decoded.ok().as_ref(), Some(&fixture.expected), "{}", fixture.name);
+    }
+}
+
+#[test]
+fn fixtures_compress_their_lz_and_entropy_chunks() {
+    // Guards against a fixture silently taking the stored quantum path instead
+    for fixture in fixtures().into_iter().filter(|fixture| fixture.name != "kraken_blocks") {
+        assert!(fixture.compressed.len() < fixture.expected.len(), "{}", fixture.name);
+    }
+}
+
+#[test]
+fn rejects_truncated_input() {
+    for fixture in fixtures().into_iter().filter(|fixture| fixture.expected.len() <= 0x40000) {
+        for length in 0..fixture.compressed.len() {
+            let decoded = Kraken::new().decompress(&fixture.compressed[..length], fixture.expected.len());
+            assert!(decoded.is_err(), "{} truncated to {} bytes", fixture.name, length);
+        }
+    }
+}
+
+#[test]
+fn rejects_trailing_output() {
+    // A memset quantum fills whatever size is asked for
+    for fixture in fixtures().into_iter().filter(|fixture| fixture.name != "kraken_memset") {
+        let decoded = Kraken::new().decompress(&fixture.compressed, fixture.expected.len() + 1);
+        assert!(decoded.is_err(), "{}", fixture.name);
+    }
+}
+
+#[test]
+fn survives_corrupt_input() {
+    for fixture in fixtures().into_iter().filter(|fixture| fixture.expected.len() <= 0x40000) {
+        for i in 0..fixture.compressed.len() {
+            for mask in [0x01, 0x10, 0x80, 0xFF] {
+                let mut corrupt = fixture.compressed.clone();
+                corrupt[i] ^= mask;
+                // Any result is fine as long as the decoder does not panic
+                let _ = Kraken::new().decompress(&corrupt, fixture.expected.len());
+            }
+        }
+    }
+}
+
+#[test]
+fn rejects_leviathan() {
+    let mut compressed = kraken_lz().compressed;
+    compressed[1] = 12;
+    assert!(Kraken::new().decompress(&compressed, kraken_lz().expected.len()).is_err());
+}
@@ -0,0 +1,28 @@
+use std::fs;
+use from_formats::util::oodle::Oodle;
+
+// A library named like oo2core 8 that cannot be loaded must not hide the versions and decoders after it.
+#[test]
+fn skips_libraries_that_fail_to_load() {
+    let dir = std::env::temp_dir().join(format!("from_formats_oodle_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let name = if cfg!(windows) {
+        "oo2core_8_win64.dll"
+    } else if cfg!(target_os = "macos") {
+        "liboo2coremac64.2.8.dylib"
+    } else {
+        "liboo2corelinux64.so.8"
+    };
+    fs::write(dir.join(name), b"not a library").unwrap();
+    Oodle::set_library_path(&dir);
+
+    let native = Oodle::get_native_compressor();
+    if cfg!(feature = "kraken") {
+        assert!(Oodle::get_oodle_compressor(9).is_ok());
+    }
+    // Only another oo2core on this machine can make it load
+    if let Err(error) = native {
+        assert_ne!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use from_formats::formats::{BinderFile, CompressionType, BND4};
+use from_formats::util::Compressed;
+use from_formats::vfs::{DirectoryRoot, OverlayRoot, VfsRoot};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("from_formats_overlay_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn chrbnd() -> BND4 {
+    let mut bnd = BND4::default();
+    bnd.files.push(BinderFile::new(200, Some("N:\\GR\\data\\INTERROOT_win64\\chr\\c1000\\c1000.flver".to_string()), vec![1; 0x100]));
+    bnd.files.push(BinderFile::new(300, Some("N:\\GR\\data\\INTERROOT_win64\\chr\\c1000\\c1000.hkx".to_string()), vec![2; 0x80]));
+    bnd
+}
+
+#[cfg(feature = "kraken")]
+// A DCX_KRAK holding a single uncompressed Kraken block, which needs no encoder to produce.
+fn krak_dcx(data: &[u8]) -> Vec<u8> {
+    assert!(data.len() <= 0x40000);
+    let mut stream = vec![0xCC, 0x06];
+    stream.extend_from_slice(data);
+
+    let mut dcx = Vec::new();
+    dcx.extend_from_slice(b"DCX\0");
+    for field in [0x11000i32, 0x18, 0x24, 0x44, 0x4C] {
+        dcx.extend_from_slice(&field.to_be_bytes());
+    }
+    dcx.extend_from_slice(b"DCS\0");
+    dcx.extend_from_slice(&(data.len() as i32).to_be_bytes());
+    dcx.extend_from_slice(&(stream.len() as i32).to_be_bytes());
+    dcx.extend_from_slice(b"DCP\0KRAK");
+    dcx.extend_from_slice(&0x20i32.to_be_bytes());
+    dcx.extend_from_slice(&[9, 0, 0, 0]);
+    dcx.extend_from_slice(&[0; 12]);
+    dcx.extend_from_slice(&0x10100i32.to_be_bytes());
+    dcx.extend_from_slice(b"DCA\0");
+    dcx.extend_from_slice(&8i32.to_be_bytes());
+    dcx.extend_from_slice(&stream);
+    dcx
+}
+
+fn overlay(base: &Path, binder: &[u8]) -> OverlayRoot {
+    fs::create_dir_all(base.join("game/chr")).unwrap();
+    fs::write(base.join("game/chr/c1000.chrbnd.dcx"), binder).unwrap();
+    fs::create_dir_all(base.join("mod/chr/c1000.chrbnd.dcx")).unwrap();
+    fs::write(base.join("mod/chr/c1000.chrbnd.dcx/c1000.flver"), b"modded").unwrap();
+    OverlayRoot::with_mod_directories(DirectoryRoot::new(base.join("game")), [base.join("mod")])
+}
+
+#[test]
+fn replaces_entries_of_a_compressed_binder() {
+    let dir = temp_dir("dflt");
+    let binder = Compressed::new(chrbnd(), CompressionType::DCX_DFLT_11000_44_9, 0).to_bytes().unwrap();
+    let root = overlay(&dir, &binder);
+    let path = ["chr", "c1000.chrbnd.dcx"];
+
+    let data = root.read(&path).unwrap();
+    assert_eq!(root.metadata(&path).unwrap().len, data.len() as u64);
+    let bnd = Compressed::<BND4>::from_bytes(data).unwrap();
+    assert_eq!(bnd.compression, CompressionType::DCX_DFLT_11000_44_9);
+    assert_eq!(bnd.files[0].data, b"modded");
+    assert_eq!(bnd.files[1].data, vec![2; 0x80]);
+}
+
+#[test]
+#[cfg(feature = "kraken")]
+fn rebuilds_kraken_binders_uncompressed_without_oodle() {
+    use from_formats::formats::FileFlags;
+    use from_formats::util::oodle::Oodle;
+
+    let expected = if Oodle::get_native_compressor().is_ok() { CompressionType::DCX_KRAK } else { CompressionType::None };
+
+    let dir = temp_dir("krak");
+    let mut bnd = chrbnd();
+    bnd.files[1].data = krak_dcx(&bnd.files[1].data);
+    let mut binder = Compressed::new(bnd, CompressionType::None, 0).to_bytes().unwrap();
+    // Mark the second entry as compressed, flags are stored with their bits reversed
+    let flags = 0x40 + 0x24;
+    assert_eq!(binder[flags], FileFlags::FLAG1.0.reverse_bits());
+    binder[flags] = (FileFlags::FLAG1 | FileFlags::COMPRESSED).0.reverse_bits();
+    let root = overlay(&dir, &krak_dcx(&binder));
+    let path = ["chr", "c1000.chrbnd.dcx"];
+
+    let bnd = Compressed::<BND4>::from_bytes(root.read(&path).unwrap()).unwrap();
+    assert_eq!(bnd.compression, expected);
+    assert_eq!(bnd.files[0].data, b"modded");
+    assert_eq!(bnd.files[1].data, vec![2; 0x80]);
+    assert_eq!(bnd.files[1].flags.is_compressed(), expected == CompressionType::DCX_KRAK);
+}
+
+#[test]
+fn lists_binders_that_cannot_be_rebuilt() {
+    let dir = temp_dir("broken");
+    let root = overlay(&dir, b"not a binder");
+    let path = ["chr", "c1000.chrbnd.dcx"];
+
+    assert_eq!(root.metadata(&path).unwrap().len, 12);
+    assert!(root.read(&path).is_err());
+}
@@ -0,0 +1,38 @@
+use from_formats::util::{normalize_path, path_hash_32, path_hash_64};
+
+#[test]
+fn hashes_each_utf16_unit_of_the_normalized_path() {
+    // '/' is 47 and 'a' is 97
+    assert_eq!(path_hash_32("/a"), 47 * 37 + 97);
+    assert_eq!(path_hash_64("/a"), 47 * 133 + 97);
+    assert_eq!(path_hash_32(""), 47);
+    assert_eq!(path_hash_64(""), 47);
+}
+
+#[test]
+fn matches_reference_hashes() {
+    let paths: [(&str, u32, u64); 5] = [
+        ("/chr/c0000.anibnd.dcx", 0xf8630fb1, 0xb5d79fd786383451),
+        ("/map/m10_00_00_00/m10_00_00_00.msb.dcx", 0x22c58cae, 0x671ddf202a4efb2e),
+        ("/param/gameparam/gameparam.parambnd.dcx", 0x2ef41580, 0x04c5922297eaa6a0),
+        ("/sound/fdp_main.fsb", 0x880f47b2, 0x822fc5c12b992712),
+        // Characters outside ASCII are hashed as UTF-16, not as their UTF-8 bytes
+        ("/chr/テスト.dcx", 0x5ba401c7, 0x6dc76d76301fca87),
+    ];
+    for (path, hash_32, hash_64) in paths {
+        assert_eq!(path_hash_32(path), hash_32, "{}", path);
+        assert_eq!(path_hash_64(path), hash_64, "{}", path);
+    }
+}
+
+#[test]
+fn normalizes_separators_case_and_the_leading_slash() {
+    assert_eq!(normalize_path("chr\\C0000.ANIBND.dcx"), "/chr/c0000.anibnd.dcx");
+    assert_eq!(normalize_path("  /Chr/c0000.anibnd.dcx\n"), "/chr/c0000.anibnd.dcx");
+    assert_eq!(normalize_path("\\map\\m10_00_00_00\\m10_00_00_00.msb.dcx"), "/map/m10_00_00_00/m10_00_00_00.msb.dcx");
+
+    for path in ["chr\\C0000.ANIBND.dcx", "/CHR/c0000.anibnd.DCX", " chr/c0000.anibnd.dcx "] {
+        assert_eq!(path_hash_32(path), 0xf8630fb1, "{}", path);
+        assert_eq!(path_hash_64(path), 0xb5d79fd786383451, "{}", path);
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use from_formats::formats::bhd5::unpack::MANIFEST_NAME;
+use from_formats::formats::{unpack_game, ArchiveBuilder, ArchiveDictionary, Game, UnpackOptions};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("from_formats_unpack_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn write_archive(dir: &Path, name: &str, files: &[(&str, &[u8])]) {
+    let mut builder = ArchiveBuilder::new(Game::DarkSouls3);
+    for (path, data) in files {
+        builder.add_file(path, data.to_vec());
+    }
+    let mut bdt = Vec::new();
+    let bhd = builder.build(&mut bdt).unwrap();
+    fs::write(dir.join(format!("{}.bhd", name)), bhd.to_bytes().unwrap()).unwrap();
+    fs::write(dir.join(format!("{}.bdt", name)), bdt).unwrap();
+}
+
+fn options(dictionary: &ArchiveDictionary) -> UnpackOptions {
+    let mut options = UnpackOptions::new(Game::DarkSouls3);
+    options.dictionary = Some(dictionary.clone());
+    options
+}
+
+#[test]
+fn keeps_files_of_later_archives_with_the_same_name() {
+    let dir = temp_dir("clash");
+    let install = dir.join("game");
+    let out = dir.join("out");
+    fs::create_dir_all(&install).unwrap();
+    write_archive(&install, "Data0", &[("/chr/c0000.anibnd", b"data0"), ("/chr/c1000.anibnd", b"only data0")]);
+    write_archive(&install, "Data1", &[("/chr/c0000.anibnd", b"data1")]);
+    let mut dictionary = ArchiveDictionary::new(Game::DarkSouls3);
+    dictionary.add_list("/chr/c0000.anibnd\n/chr/c1000.anibnd\n");
+
+    let report = unpack_game(&install, &out, options(&dictionary)).unwrap();
+    assert_eq!(report.written, 3);
+    assert_eq!(report.conflicting, 1);
+    assert_eq!(fs::read(out.join("chr/c0000.anibnd")).unwrap(), b"data0");
+    let hash = Game::DarkSouls3.path_hash("/chr/c0000.anibnd");
+    assert_eq!(fs::read(out.join("_unknown/Data1").join(format!("{:08x}", hash))).unwrap(), b"data1");
+
+    // Running again keeps the same split, so nothing is written twice
+    let report = unpack_game(&install, &out, options(&dictionary)).unwrap();
+    assert_eq!((report.written, report.skipped, report.conflicting), (0, 3, 1));
+}
+
+#[test]
+fn starts_a_new_line_after_an_interrupted_manifest() {
+    let dir = temp_dir("manifest");
+    let install = dir.join("game");
+    let out = dir.join("out");
+    fs::create_dir_all(&install).unwrap();
+    fs::create_dir_all(&out).unwrap();
+    write_archive(&install, "Data0", &[("/chr/c0000.anibnd", b"data0")]);
+    let mut dictionary = ArchiveDictionary::new(Game::DarkSouls3);
+    dictionary.add("/chr/c0000.anibnd");
+    fs::write(out.join(MANIFEST_NAME), "Data0\t00000000").unwrap();
+
+    assert_eq!(unpack_game(&install, &out, options(&dictionary)).unwrap().written, 1);
+    let manifest = fs::read_to_string(out.join(MANIFEST_NAME)).unwrap();
+    assert_eq!(manifest.lines().count(), 2);
+    assert!(manifest.ends_with('\n'));
+    // The new line is read back, so the file is not unpacked again
+    let report = unpack_game(&install, &out, options(&dictionary)).unwrap();
+    assert_eq!((report.written, report.skipped), (0, 1));
+}